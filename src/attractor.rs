@@ -0,0 +1,274 @@
+//! Early termination for orbits which settle into attracting cycles.
+//!
+//! Escape-time rendering spends almost all of its time on interior points: an orbit which
+//! never escapes costs the full iteration limit, while most escaping orbits are done after
+//! a handful of steps. But interior orbits of maps with attracting cycles usually settle
+//! down quickly, and once an orbit has come back to (very nearly) where it was, it's
+//! never going to escape.
+//!
+//! Two tests are provided. The convergence test stops once successive values are within
+//! some distance of each other, which catches attracting fixed points cheaply. The
+//! periodicity test uses Brent's cycle detection algorithm: it remembers the value at
+//! each power-of-two step and stops once the orbit returns near it, which catches cycles
+//! of any period and reports the period, at the cost of one extra comparison per step.
+
+use image::{ImageBuffer, Luma, LumaA};
+use num::complex::Complex64;
+use std::fmt;
+
+use bailout::Bailout;
+use stats::RenderStats;
+use super::parallel_rows;
+
+/// Which early-termination tests to apply, and their tolerances.
+///
+/// The default applies neither, which makes `fate` agree with `applications_until_escape`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AttractorTest {
+    /// Stop once `|z_{n+1} - z_n|` is less than this
+    pub convergence: Option<f64>,
+    /// Stop once the orbit returns to within this distance of a remembered value
+    pub periodicity: Option<f64>,
+}
+
+impl AttractorTest {
+    /// A tolerance for the periodicity test which is small enough not to mistake slowly
+    /// escaping orbits for cycles.
+    pub const DEFAULT_TOLERANCE: f64 = 1e-10;
+
+    pub fn is_none(&self) -> bool {
+        self.convergence.is_none() && self.periodicity.is_none()
+    }
+}
+
+/// What became of an orbit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fate {
+    /// The orbit escaped after this many steps.
+    Escaped(usize),
+    /// The orbit was caught in an attracting cycle of `period` after `steps` steps.
+    Attracted { steps: usize, period: usize },
+    /// The iteration limit was reached without a decision.
+    Bounded(usize),
+}
+
+impl Fate {
+    /// The escape count `applications_until_escape` would have produced: the step count
+    /// for escaped orbits, and the iteration limit for all others.
+    pub fn count(&self, bound: usize) -> usize {
+        match *self {
+            Fate::Escaped(steps) => steps,
+            Fate::Attracted { .. } | Fate::Bounded(_) => bound,
+        }
+    }
+
+    /// The number of steps actually computed.
+    pub fn steps(&self) -> usize {
+        match *self {
+            Fate::Escaped(steps) | Fate::Attracted { steps, .. } | Fate::Bounded(steps) => steps,
+        }
+    }
+}
+
+impl Default for Fate {
+    fn default() -> Fate {
+        Fate::Bounded(0)
+    }
+}
+
+impl fmt::Display for Fate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fate::Escaped(steps) => write!(f, "escaped after {} steps", steps),
+            Fate::Attracted { steps, period } => {
+                write!(f, "attracted to a cycle of period {} after {} steps", period, steps)
+            }
+            Fate::Bounded(steps) => write!(f, "undecided after {} steps", steps),
+        }
+    }
+}
+
+/// Follow the orbit of `initial` under `function` until it escapes, is caught by an
+/// attractor, or `bound` steps have passed.
+pub fn fate(initial: Complex64,
+            function: &dyn Fn(Complex64) -> Complex64,
+            bailout: &Bailout,
+            bound: usize,
+            test: &AttractorTest)
+            -> Fate {
+    let convergence = test.convergence.map(|e| e * e);
+    let periodicity = test.periodicity.map(|e| e * e);
+
+    let mut value = initial;
+    let mut count = 0;
+    // Brent's algorithm: `saved` is the value at the most recent power-of-two step, and
+    // `lambda` is how many steps ago that was
+    let mut saved = initial;
+    let mut power = 1;
+    let mut lambda = 0;
+    loop {
+        if count >= bound {
+            return Fate::Bounded(count);
+        }
+        if bailout.escaped(value) {
+            return Fate::Escaped(count);
+        }
+        let next = function(value);
+        count += 1;
+
+        if let Some(epsilon) = convergence {
+            if (next - value).norm_sqr() < epsilon {
+                return Fate::Attracted {
+                    steps: count,
+                    period: 1,
+                };
+            }
+        }
+        if let Some(epsilon) = periodicity {
+            lambda += 1;
+            if (next - saved).norm_sqr() < epsilon {
+                return Fate::Attracted {
+                    steps: count,
+                    period: lambda,
+                };
+            }
+            if lambda == power {
+                saved = next;
+                power *= 2;
+                lambda = 0;
+            }
+        }
+        value = next;
+    }
+}
+
+/// Compute the fate of every pixel in parallel on `threads` threads.
+#[allow(clippy::too_many_arguments)]
+pub fn render(width: u32,
+              height: u32,
+              function: &(dyn Fn(Complex64) -> Complex64 + Sync),
+              interpolate: &(dyn Fn(u32, u32) -> Complex64 + Sync),
+              bailout: &Bailout,
+              iterations: usize,
+              test: &AttractorTest,
+              threads: usize)
+              -> Vec<Fate> {
+    parallel_rows(width, height, threads, &|x, y| {
+        fate(interpolate(x, y), function, bailout, iterations, test)
+    })
+}
+
+/// Gather statistics about fates, counting attracted pixels as filled.
+pub fn stats(fates: &[Fate], width: u32, height: u32, iterations: usize) -> RenderStats {
+    let iterations = iterations.max(1);
+    let counts = fates.iter().map(|fate| fate.count(iterations)).collect::<Vec<_>>();
+    let work = fates.iter().map(|fate| fate.steps() as u64).sum();
+    RenderStats::new(width, height, iterations, &counts, work)
+}
+
+/// Map fates onto the escape-count image `parallel_image_state` would have produced.
+pub fn to_escape_image(fates: &[Fate],
+                       width: u32,
+                       height: u32,
+                       iterations: usize)
+                       -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let iterations = iterations.max(1);
+    ImageBuffer::from_fn(width, height, |x, y| {
+        Luma([(fates[(y * width + x) as usize].count(iterations) * 255 / iterations) as u8])
+    })
+}
+
+/// Map fates onto an image for `InteriorColorizer`.
+///
+/// For escaped and undecided pixels, luma is the escape count scaled so that `iterations`
+/// maps onto full brightness, exactly as `parallel_image_state` would produce, and alpha is
+/// 0. For attracted pixels, alpha is the period (saturating at 255), and luma is a shade
+/// which falls off with the number of steps taken to be caught.
+pub fn to_image(fates: &[Fate],
+                width: u32,
+                height: u32,
+                iterations: usize)
+                -> ImageBuffer<LumaA<u8>, Vec<u8>> {
+    let iterations = iterations.max(1);
+    let scale = ((iterations + 1) as f64).ln();
+    ImageBuffer::from_fn(width, height, |x, y| {
+        match fates[(y * width + x) as usize] {
+            Fate::Attracted { steps, period } => {
+                // never darker than a quarter, so that slow basins keep their color
+                let shade = 1.0 - 0.75 * ((steps + 1) as f64).ln() / scale;
+                LumaA([(shade * u8::MAX as f64).round() as u8,
+                       period.min(u8::MAX as usize) as u8])
+            }
+            fate => LumaA([(fate.count(iterations) * 255 / iterations) as u8, 0]),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::applications_until_escape;
+
+    fn quadratic(c: Complex64) -> impl Fn(Complex64) -> Complex64 {
+        move |z| z * z + c
+    }
+
+    #[test]
+    fn test_no_test_agrees_with_applications_until() {
+        let f = quadratic(Complex64::new(-0.221, -0.713));
+        let bailout = Bailout::Modulus(2.0);
+        for &(re, im) in &[(0.0, 0.0), (-1.0, 1.0), (0.3, 0.1), (1.0, -1.0)] {
+            let z = Complex64::new(re, im);
+            let fate = fate(z, &f, &bailout, 255, &AttractorTest::default());
+            assert_eq!(fate.count(255), applications_until_escape(z, &f, &bailout, Some(255)));
+        }
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        // c = 0: the origin attracts the whole unit disk
+        let f = quadratic(Complex64::new(0.0, 0.0));
+        let test = AttractorTest {
+            convergence: Some(1e-12),
+            periodicity: None,
+        };
+        match fate(Complex64::new(0.5, 0.1), &f, &Bailout::default(), 1000, &test) {
+            Fate::Attracted { period, steps } => {
+                assert_eq!(period, 1);
+                assert!(steps < 10);
+            }
+            other => panic!("unexpected {}", other),
+        }
+    }
+
+    #[test]
+    fn test_periods() {
+        // c = -1 has an attracting 2-cycle {0, -1}; c = -0.12 + 0.75i, the Douady rabbit,
+        // has an attracting 3-cycle
+        let test = AttractorTest {
+            convergence: None,
+            periodicity: Some(AttractorTest::DEFAULT_TOLERANCE),
+        };
+        for &(c, expected) in &[(Complex64::new(-1.0, 0.0), 2),
+                                (Complex64::new(-0.12, 0.75), 3)] {
+            let f = quadratic(c);
+            match fate(Complex64::new(0.0, 0.0), &f, &Bailout::default(), 10_000, &test) {
+                Fate::Attracted { period, .. } => assert_eq!(period, expected),
+                other => panic!("unexpected {} for c = {}", other, c),
+            }
+        }
+        // escaping points are unaffected
+        let f = quadratic(Complex64::new(-1.0, 0.0));
+        assert_eq!(fate(Complex64::new(1.9, 0.0), &f, &Bailout::default(), 255, &test),
+                   Fate::Escaped(1));
+    }
+
+    #[test]
+    fn test_stats_count_the_work_done() {
+        let fates = [Fate::Escaped(3), Fate::Attracted { steps: 20, period: 2 }, Fate::Bounded(100)];
+        let stats = stats(&fates, 3, 1, 100);
+        assert_eq!(stats.filled(), 2);
+        assert_eq!(stats.work, 123);
+        assert_eq!((stats.min(), stats.max()), (3, 100));
+    }
+}
@@ -0,0 +1,181 @@
+//! Criteria for deciding that an orbit has escaped.
+//!
+//! For polynomials, an orbit escapes once its modulus passes some radius. Transcendental
+//! maps behave differently: orbits of `c·exp(z)` escape by running off to the right, with
+//! the real part growing without bound while the imaginary part goes wherever it likes,
+//! and orbits of `c·sin(z)` and `c·cos(z)` escape up or down, through the imaginary part.
+//! Orbits of `c·exp(z)` which run off to the left don't escape at all: `exp(z)` vanishes
+//! there, so they fall straight back towards 0. A modulus test wrongly treats points
+//! which merely wander far from the origin as escaped, and wrongly finds no structure
+//! where the real escape happens.
+
+use num::complex::Complex64;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// A test for whether a value has escaped.
+///
+/// Each built-in criterion compares a measure of the value against a radius. Non-finite
+/// values always count as escaped.
+#[derive(Clone)]
+pub enum Bailout {
+    /// `|z| >= r`
+    Modulus(f64),
+    /// `|Re z| >= r`
+    Real(f64),
+    /// `Re z >= r`
+    RealPositive(f64),
+    /// `|Im z| >= r`
+    Imaginary(f64),
+    /// `|Re z| + |Im z| >= r`
+    Manhattan(f64),
+    /// Any predicate which returns true once the value has escaped
+    Custom(Arc<dyn Fn(Complex64) -> bool + Send + Sync>),
+}
+
+impl Bailout {
+    /// The names accepted by `from_str`, which each take a radius: `real:50`.
+    pub const NAMES: &'static [&'static str] = &["modulus", "real", "right", "imag", "manhattan"];
+
+    /// Construct a custom bailout from a predicate.
+    pub fn custom<F>(predicate: F) -> Bailout
+        where F: Fn(Complex64) -> bool + Send + Sync + 'static
+    {
+        Bailout::Custom(Arc::new(predicate))
+    }
+
+    /// Whether `z` has escaped.
+    #[inline]
+    pub fn escaped(&self, z: Complex64) -> bool {
+        // phrased as "not within" so that NaN escapes
+        match *self {
+            Bailout::Modulus(r) => !within(z.norm_sqr(), r * r),
+            Bailout::Real(r) => !within(z.re.abs(), r),
+            Bailout::RealPositive(r) => !within(z.re, r),
+            Bailout::Imaginary(r) => !within(z.im.abs(), r),
+            Bailout::Manhattan(r) => !within(z.re.abs() + z.im.abs(), r),
+            Bailout::Custom(ref predicate) => predicate(z),
+        }
+    }
+
+    /// The radius of a built-in criterion.
+    pub fn radius(&self) -> Option<f64> {
+        match *self {
+            Bailout::Modulus(r) |
+            Bailout::Real(r) |
+            Bailout::RealPositive(r) |
+            Bailout::Imaginary(r) |
+            Bailout::Manhattan(r) => Some(r),
+            Bailout::Custom(_) => None,
+        }
+    }
+}
+
+#[inline]
+fn within(measure: f64, radius: f64) -> bool {
+    measure < radius
+}
+
+impl Default for Bailout {
+    fn default() -> Bailout {
+        Bailout::Modulus(2.0)
+    }
+}
+
+impl PartialEq for Bailout {
+    fn eq(&self, other: &Bailout) -> bool {
+        match (self, other) {
+            (Bailout::Modulus(a), Bailout::Modulus(b)) |
+            (Bailout::Real(a), Bailout::Real(b)) |
+            (Bailout::RealPositive(a), Bailout::RealPositive(b)) |
+            (Bailout::Imaginary(a), Bailout::Imaginary(b)) |
+            (Bailout::Manhattan(a), Bailout::Manhattan(b)) => a == b,
+            (Bailout::Custom(a), Bailout::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Bailout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bailout({})", self)
+    }
+}
+
+impl fmt::Display for Bailout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Bailout::Modulus(r) => write!(f, "modulus:{}", r),
+            Bailout::Real(r) => write!(f, "real:{}", r),
+            Bailout::RealPositive(r) => write!(f, "right:{}", r),
+            Bailout::Imaginary(r) => write!(f, "imag:{}", r),
+            Bailout::Manhattan(r) => write!(f, "manhattan:{}", r),
+            Bailout::Custom(_) => write!(f, "custom"),
+        }
+    }
+}
+
+impl FromStr for Bailout {
+    type Err = String;
+
+    /// Parse a built-in criterion in the form `KIND:RADIUS`, such as `real:50`.
+    fn from_str(s: &str) -> Result<Bailout, String> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap().trim().to_lowercase();
+        let radius = match parts.next().map(|r| f64::from_str(r.trim())) {
+            Some(Ok(r)) if r > 0.0 && r.is_finite() => r,
+            Some(_) => return Err(format!("invalid bailout radius in '{}'", s)),
+            None => return Err(format!("bailout '{}' needs a radius, as in '{}:2'", s, kind)),
+        };
+        match kind.as_str() {
+            "modulus" | "mod" => Ok(Bailout::Modulus(radius)),
+            "real" | "re" => Ok(Bailout::Real(radius)),
+            "right" => Ok(Bailout::RealPositive(radius)),
+            "imag" | "imaginary" | "im" => Ok(Bailout::Imaginary(radius)),
+            "manhattan" => Ok(Bailout::Manhattan(radius)),
+            _ => {
+                Err(format!("unknown bailout '{}'; expected one of {}",
+                            kind,
+                            Bailout::NAMES.join(", ")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escaped() {
+        let z = Complex64::new(3.0, -4.0);
+        assert!(Bailout::Modulus(5.0).escaped(z));
+        assert!(!Bailout::Modulus(5.1).escaped(z));
+        assert!(Bailout::Real(3.0).escaped(z));
+        assert!(!Bailout::Real(3.5).escaped(z));
+        assert!(Bailout::RealPositive(3.0).escaped(z));
+        assert!(!Bailout::RealPositive(3.0).escaped(-z));
+        assert!(Bailout::Imaginary(3.5).escaped(z));
+        assert!(!Bailout::Manhattan(7.5).escaped(z));
+        assert!(Bailout::custom(|z| z.re > 2.0).escaped(z));
+        assert!(Bailout::Real(50.0).escaped(Complex64::new(f64::NAN, 0.0)));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for bailout in &[Bailout::Modulus(2.0),
+                         Bailout::Real(50.0),
+                         Bailout::RealPositive(50.0),
+                         Bailout::Imaginary(0.5),
+                         Bailout::Manhattan(4.0)] {
+            assert_eq!(&Bailout::from_str(&bailout.to_string()).unwrap(), bailout);
+        }
+        assert!(Bailout::from_str("real").is_err());
+        assert!(Bailout::from_str("real:-1").is_err());
+        assert!(Bailout::from_str("real:NaN").is_err());
+        assert!(Bailout::from_str("modulus:inf").is_err());
+        assert!(Bailout::from_str("sideways:3").is_err());
+        assert!(Bailout::from_str("custom").is_err());
+    }
+}
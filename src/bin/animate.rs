@@ -1,21 +1,28 @@
 #[macro_use]
 extern crate clap;
 extern crate csv;
-extern crate image;
 extern crate julia_set;
 extern crate lerp;
 extern crate num;
 
 use clap::{App, Arg};
-use julia_set::{parallel_image, interpolate_rectilinear};
-use julia_set::colorize::{Colorizer, HSLColorizer};
+use julia_set::{render, render_with_stats, Bailout, Plane, Viewport, DEFAULT_THREADS};
+use julia_set::attractor::AttractorTest;
+use julia_set::classify::{classify, Classification};
+use julia_set::dimension::BoxCounting;
+use julia_set::family::Family;
+use julia_set::field::{EscapeField, FieldHeader};
 use julia_set::iter::DuplicateFirst;
+use julia_set::metadata::RenderParameters;
+use julia_set::multibrot::Multibrot;
+use julia_set::output::{save_field, OutputFormat};
 use lerp::LerpIter;
 use num::complex::Complex64;
 use std::env;
 use std::fs;
 use std::io;
 use std::io::Write;
+use std::iter;
 use std::path;
 use std::str::FromStr;
 
@@ -25,21 +32,42 @@ fn main() {
     // --------------------
     let conf = match AnimationConfiguration::new() {
         Ok(conf) => conf,
-        Err(err) => panic!(err),
+        Err(err) => panic!("{}", err),
     };
+    // read the whole path up front, so that a bad row is caught before any frames are made
+    let path = match interpolated_path(&conf) {
+        Ok(path) => path,
+        Err(err) => {
+            println!("FATAL: Invalid points file {:?}.", conf.pointsfile);
+            println!("\t{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(ref csv_path) = conf.dimension {
+        println!("Estimating dimensions along {:?} into {:?}...", conf.pointsfile, csv_path);
+        sweep_dimension(&conf, &path, csv_path).expect("Fatal IO Error");
+        println!("Done!");
+        return;
+    }
 
     let out_path = conf.basepath.join("animate");
     if !out_path.exists() {
         fs::create_dir(out_path.clone())
-            .expect(&format!("Couldn't create output directory at {:?}", out_path));
+            .unwrap_or_else(|_| panic!("Couldn't create output directory at {:?}", out_path));
     }
 
     println!("Input parameters:");
     println!("  Points file: {:?}", conf.pointsfile);
+    match conf.degree {
+        Some(degree) => println!("  Function:    z^{} + c", degree),
+        None => println!("  Function:    {}", conf.function),
+    }
     println!("Output parameters:");
     println!("  Colorize:    {}", conf.colorize);
     println!("  Dimensions:  {:?}", (conf.width, conf.height));
     println!("  Mul Factor:  {}", conf.multiply);
+    println!("  Format:      {}", conf.format);
     println!("  Output path: {:?}", out_path);
     print!("Clearing output path... ");
     remove_files_from(&out_path).expect("FATAL error clearing output path!");
@@ -50,9 +78,7 @@ fn main() {
     // ---------------------------
     // set up prerequisite objects
     // ---------------------------
-    let interpolate = interpolate_rectilinear(conf.width, conf.height, -1.1, 1.1, -1.1, 1.1);
-    let colorizer = HSLColorizer::new();
-    let mut rdr = csv::Reader::from_file(conf.pointsfile.clone()).unwrap().flexible(true);
+    let viewport = Viewport::new(-1.1, 1.1, -1.1, 1.1).rectilinear(conf.width, conf.height);
     let mut index_file = fs::File::create(out_path.join("index.toml")).unwrap();
 
     // annotate how this was most recently run
@@ -64,104 +90,267 @@ fn main() {
              AnimationConfiguration::called_as())
         .unwrap();
 
-    // determine at runtime if we have headers
-    {
-        let headers = rdr.headers().unwrap();
-        if headers.len() == 3 &&
-           (f64::from_str(&headers[0]).is_err() || f64::from_str(&headers[1]).is_err() ||
-            usize::from_str(&headers[2]).is_err()) {
-            rdr = rdr.has_headers(true);
-        } else {
-            rdr = rdr.has_headers(false);
-        }
-    }
-
     // ---------
     // main loop
     // ---------
-    //
-    // this looks complex, but it's all just a sequence of operations on iterators:
-    //   - get a row from the CSV reader
-    //   - map it to a (Complex64, usize)
-    //   - map it to (Complex64, usize, Complex64) so we know our bounds
-    //   - fill in the appropriate default number of steps if unspecified
-    //   - map it to a long sequence of Complex64 by lerping
-    //   - enumerate it
-    //   - for each of the (enumeration, complex), act out the body of the loop
-    for (count, cplx) in rdr.decode()
-        .map(|record| {
-            let (real, imag, steps): (f64, f64, Option<usize>) =
-                record.expect("Invalid format in input CSV");
-            (Complex64::new(real, imag), steps)
-        })
-        .duplicate_first()
-        .map(|(start, steps, end)| {
-            let steps = match steps {
-                Some(s) => s,
-                None => {
-                    // if steps wasn't specified, generate steps from
-                    // the distance between the two points
-                    const DEFAULT_STEPS_PER_UNIT: usize = 5;
-                    ((end - start).norm() * DEFAULT_STEPS_PER_UNIT as f64).ceil() as usize
-                }
-            };
-            (start, steps, end)
-        })
-        .flat_map(|(start, steps, end)| start.lerp_iter(end, steps * conf.multiply))
-        .enumerate() {
-
-
+    for (count, &(cplx, degree)) in path.iter().enumerate() {
 
         // actually generate the image
 
-        let filename = format!("julia_set_{:06}.png", count);
+        let filename = format!("julia_set_{:06}.{}", count, conf.format.extension());
         let file_path = out_path.join(filename.clone());
         print!("Generating {:?}... ", filename.clone());
 
-        let image = parallel_image(conf.width,
-                                   conf.height,
-                                   &move |z| (z * z) + cplx,
-                                   &*interpolate,
-                                   2.0);
-
-
-
-        if conf.colorize {
-            print!("colorizing... ");
-            let image = colorizer.colorize(&image);
+        let family = conf.family_at(degree);
+        let params = RenderParameters {
+            width: conf.width,
+            height: conf.height,
+            supersample: 1,
+            viewport,
+            function: family.expression(),
+            c: cplx,
+            plane: Plane::Julia,
+            bailout: family.bailout(cplx),
+            iterations: 255,
+            colorizer: String::from(if conf.colorize { "hsl" } else { "none" }),
+            attractors: AttractorTest::default(),
+            interior: false,
+            orbit: None,
+        };
+        let saved = if conf.format.holds_field() {
+            // the escape values themselves, rather than an 8-bit image of them
+            let header = FieldHeader {
+                width: conf.width,
+                height: conf.height,
+                viewport,
+                function: format!("{} where c = {}", family, cplx),
+                bailout: params.bailout.clone(),
+                iterations: params.iterations,
+            };
+            let field = EscapeField::render(header, &*family.bind(cplx), DEFAULT_THREADS);
+            if conf.stats {
+                print!("{}; ", field.stats().summary());
+            }
             print!("saving... ");
-            image.save(file_path.clone()).expect("Fatal IO Error");
+            save_field(&field, &file_path, conf.format)
         } else {
+            let image = if conf.stats {
+                let (image, stats) = render_with_stats(&params, DEFAULT_THREADS);
+                print!("{}; ", stats.summary());
+                image
+            } else {
+                render(&params, DEFAULT_THREADS)
+            };
             print!("saving... ");
-            image.save(file_path.clone()).expect("Fatal IO Error");
-        }
+            params.save(&image, &file_path, conf.format)
+        };
+        saved.expect("Fatal IO Error");
 
         println!("done!");
 
         // annotate this position in the output file
-        writeln!(&mut index_file, "").unwrap();
+        writeln!(&mut index_file).unwrap();
         writeln!(&mut index_file, "[{:06}]", count).unwrap();
         writeln!(&mut index_file, "path = \"{}\"", file_path.display()).unwrap();
         writeln!(&mut index_file, "real = {}", cplx.re).unwrap();
         writeln!(&mut index_file, "imag = {}", cplx.im).unwrap();
+        if let Some(degree) = degree {
+            writeln!(&mut index_file, "degree = {}", degree).unwrap();
+        }
         writeln!(&mut index_file,
                  "repr = \"({:.3}{:+.3}i)\"",
                  cplx.re,
                  cplx.im)
             .unwrap();
+        let classification = classify(&family, cplx, Classification::DEFAULT_ITERATIONS);
+        writeln!(&mut index_file,
+                 "classification = \"{}\"",
+                 classification.name())
+            .unwrap();
+        match classification {
+            Classification::Connected { period } => {
+                writeln!(&mut index_file, "period = {}", period).unwrap()
+            }
+            Classification::Disconnected { steps } => {
+                writeln!(&mut index_file, "escaped = {}", steps).unwrap()
+            }
+            Classification::Boundary => {}
+        }
     }
 
     println!("Done!");
     println!("You can create an animation from the output with the command:");
     println!(" > ffmpeg -framerate 25 -i {} -c:v libx264 -vf fps=25 -pix_fmt yuv420p out.mp4",
-             out_path.join("julia_set_%06d.png").display());
+             out_path.join(format!("julia_set_%06d.{}", conf.format.extension())).display());
+}
+
+/// Estimate the dimension of the Julia set at every frame of `path`, and write them to a
+/// CSV file at `csv_path` instead of rendering.
+fn sweep_dimension(conf: &AnimationConfiguration,
+                   path: &[(Complex64, Option<f64>)],
+                   csv_path: &path::Path)
+                   -> csv::Result<()> {
+    let mut writer = csv::Writer::from_file(csv_path)?;
+    writer.write(["frame",
+                  "real",
+                  "imag",
+                  "degree",
+                  "dimension",
+                  "standard_error",
+                  "low",
+                  "high",
+                  "r_squared",
+                  "classification"]
+        .iter())?;
+
+    for (count, &(cplx, degree)) in path.iter().enumerate() {
+        print!("Estimating ({:.3}{:+.3}i)... ", cplx.re, cplx.im);
+        let family = conf.family_at(degree);
+        let bailout = family.bailout(cplx);
+        // the whole set lies within the escape radius, when there is one
+        let viewport = match bailout {
+            Bailout::Modulus(radius) => Viewport::new(-radius, radius, -radius, radius),
+            _ => Viewport::new(-2.0, 2.0, -2.0, 2.0),
+        };
+        let classification = classify(&family, cplx, Classification::DEFAULT_ITERATIONS);
+        let fields = match BoxCounting::new(viewport).estimate(&*family.bind(cplx),
+                                                               &bailout,
+                                                               DEFAULT_THREADS) {
+            Ok(estimate) => {
+                println!("{:.4} ± {:.4}", estimate.dimension, estimate.standard_error);
+                let (low, high) = estimate.interval();
+                vec![estimate.dimension.to_string(),
+                     estimate.standard_error.to_string(),
+                     low.to_string(),
+                     high.to_string(),
+                     estimate.r_squared.to_string()]
+            }
+            Err(error) => {
+                println!("{}", error);
+                vec![String::new(); 5]
+            }
+        };
+        let mut row = vec![count.to_string(),
+                           cplx.re.to_string(),
+                           cplx.im.to_string(),
+                           degree.map(|d| d.to_string()).unwrap_or_default()];
+        row.extend(fields);
+        row.push(classification.name().to_string());
+        writer.write(row.iter())?;
+    }
+    writer.flush()
+}
+
+/// Read the points file, and interpolate the path it describes into the sequence of
+/// `(c, degree)` for each frame.
+fn interpolated_path(conf: &AnimationConfiguration)
+                     -> Result<Vec<(Complex64, Option<f64>)>, String> {
+    let mut rdr = csv::Reader::from_file(conf.pointsfile.clone())
+        .map_err(|e| e.to_string())?
+        .flexible(true);
+
+    // determine at runtime if we have headers
+    let has_headers = {
+        let headers = rdr.headers().map_err(|e| e.to_string())?;
+        headers.len() >= 2 &&
+        (f64::from_str(&headers[0]).is_err() || f64::from_str(&headers[1]).is_err())
+    };
+    rdr = rdr.has_headers(has_headers);
+    let keyframes = rdr.records()
+        .enumerate()
+        .map(|(index, record)| {
+            // rows are numbered as in the file, from 1
+            let row = index + 1 + has_headers as usize;
+            let record = record.map_err(|e| format!("row {}: {}", row, e))?;
+            Keyframe::parse(&record, conf.degree).map_err(|e| format!("row {}: {}", row, e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    // this looks complex, but it's all just a sequence of operations on iterators:
+    //   - take each (Keyframe, usize) parsed from a row of the CSV file
+    //   - map it to (Keyframe, usize, Keyframe) so we know our bounds
+    //   - fill in the appropriate default number of steps if unspecified
+    //   - map it to a long sequence of (Complex64, degree) by lerping
+    Ok(keyframes.into_iter()
+        .duplicate_first()
+        .map(|(start, steps, end)| {
+            let steps = match steps {
+                Some(s) => s,
+                None => {
+                    // if steps wasn't specified, generate steps from
+                    // the distance between the two points
+                    const DEFAULT_STEPS_PER_UNIT: usize = 5;
+                    ((end.c - start.c).norm() * DEFAULT_STEPS_PER_UNIT as f64).ceil() as usize
+                }
+            };
+            (start, steps, end)
+        })
+        .flat_map(|(start, steps, end)| {
+            let steps = steps * conf.multiply;
+            // the degree is only interpolated if both ends have one
+            let degrees: Box<dyn Iterator<Item = Option<f64>>> = match (start.degree,
+                                                                         end.degree) {
+                (Some(from), Some(to)) => Box::new(from.lerp_iter(to, steps).map(Some)),
+                (degree, _) => Box::new(iter::repeat(degree)),
+            };
+            start.c.lerp_iter(end.c, steps).zip(degrees)
+        })
+        .collect())
+}
+
+/// A point on the animation path, as read from one row of the points file:
+/// `real, imag[, steps[, degree]]`.
+#[derive(Clone, Copy, Debug)]
+struct Keyframe {
+    c: Complex64,
+    /// Multibrot degree at this point, if animating one
+    degree: Option<f64>,
+}
+
+impl Keyframe {
+    /// Parse a row, returning the keyframe and the number of steps to the next one.
+    fn parse(record: &[String],
+             default_degree: Option<f64>)
+             -> Result<(Keyframe, Option<usize>), String> {
+        if record.len() < 2 {
+            return Err(format!("expected at least 2 fields, got {}", record.len()));
+        }
+        let real = parse_field::<f64>(&record[0])?.ok_or("missing real part")?;
+        let imag = parse_field::<f64>(&record[1])?.ok_or("missing imaginary part")?;
+        let steps = match record.get(2) {
+            Some(field) => parse_field::<usize>(field)?,
+            None => None,
+        };
+        let degree = match record.get(3) {
+            Some(field) => parse_field::<f64>(field)?,
+            None => None,
+        };
+        if let Some(degree) = degree {
+            Multibrot::new(degree)?;
+        }
+        Ok((Keyframe {
+                c: Complex64::new(real, imag),
+                degree: degree.or(default_degree),
+            },
+            steps))
+    }
+}
+
+/// Parse a CSV field, treating an empty one as absent.
+fn parse_field<T: FromStr>(field: &str) -> Result<Option<T>, String> {
+    let field = field.trim();
+    if field.is_empty() {
+        Ok(None)
+    } else {
+        T::from_str(field).map(Some).map_err(|_| format!("couldn't parse '{}'", field))
+    }
 }
 
 fn remove_files_from<P: AsRef<path::Path>>(path: &P) -> io::Result<()> {
-    for entry in try!(fs::read_dir(path)) {
-        let entry = try!(entry);
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
         if entry.path().is_file() {
-            try!(fs::remove_file(entry.path()));
+            fs::remove_file(entry.path())?;
         }
     }
     Ok(())
@@ -169,14 +358,35 @@ fn remove_files_from<P: AsRef<path::Path>>(path: &P) -> io::Result<()> {
 
 struct AnimationConfiguration {
     colorize: bool,
+    /// If set, write dimension estimates along the path to this CSV file instead of
+    /// rendering
+    dimension: Option<path::PathBuf>,
+    /// Whether to print statistics about each frame
+    stats: bool,
     width: u32,
     height: u32,
     multiply: usize,
+    function: Family,
+    degree: Option<f64>,
+    format: OutputFormat,
     basepath: path::PathBuf,
     pointsfile: path::PathBuf,
 }
 
 impl AnimationConfiguration {
+    /// The family to render at a point on the path with the given degree.
+    ///
+    /// Every degree on the path lies between two which were checked as they were read, so
+    /// it's valid too.
+    fn family_at(&self, degree: Option<f64>) -> Family {
+        match degree {
+            Some(degree) => {
+                Family::Multibrot(Multibrot::new(degree).expect("degrees are checked when read"))
+            }
+            None => self.function.clone(),
+        }
+    }
+
     fn build_cli() -> App<'static, 'static> {
         App::new("animate")
            .about("generates sequences of images of julia sets for compilation to animation")
@@ -191,6 +401,7 @@ impl AnimationConfiguration {
                     .short("d")
                     .long("dimensions")
                     .value_names(&["WIDTH", "HEIGHT"])
+                    .use_delimiter(true)
                     .default_value("800,600")
                     .help("Set the dimensions of the output images.")
                 )
@@ -201,6 +412,40 @@ impl AnimationConfiguration {
                     .default_value("1")
                     .help("Multiply the number of interpolation steps between each path point.")
                 )
+          .arg(Arg::with_name("function")
+                    .short("F")
+                    .long("function")
+                    .value_names(&["EXPR"])
+                    .default_value(RenderParameters::DEFAULT_FUNCTION)
+                    .help("Iteration function of z and c, such as 'z^3 + c*sin(z)', or one of \
+                           the families exp, sin, cos, burning-ship, tricorn, celtic or buffalo.")
+                )
+          .arg(Arg::with_name("degree")
+                    .long("degree")
+                    .value_names(&["D"])
+                    .conflicts_with("function")
+                    .help("Animate the multibrot function z^D + c. A fourth column in the points \
+                           file overrides D at that point, and is interpolated between points.")
+                )
+          .arg(Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .value_names(&["FORMAT"])
+                    .default_value("png")
+                    .help("Output image format: png, jpeg[:QUALITY], tiff, webp or ppm, or png16 \
+                           or exr for the escape values behind each frame.")
+                )
+          .arg(Arg::with_name("dimension")
+                    .long("dimension")
+                    .value_names(&["CSV"])
+                    .help("Instead of rendering, estimate the fractal dimension of the Julia set \
+                           at every step by box counting, and write the estimates to this CSV \
+                           file.")
+                )
+          .arg(Arg::with_name("stats")
+                    .long("stats")
+                    .help("Print a summary of statistics about each frame's render.")
+                )
           .arg(Arg::with_name("pointsfile")
                     .short("p")
                     .long("points-file")
@@ -217,11 +462,23 @@ impl AnimationConfiguration {
 
     fn unpack_matches(matches: clap::ArgMatches) -> Result<AnimationConfiguration, String> {
         let colorize = matches.is_present("colorize");
+        let dimension = matches.value_of("dimension").map(path::PathBuf::from);
+        let stats = matches.is_present("stats");
         let (width, height) = {
             let dimensions = values_t!(matches, "dimensions", u32).unwrap_or_else(|e| e.exit());
             (dimensions[0], dimensions[1])
         };
         let multiply = value_t!(matches, "multiply", usize).unwrap_or_else(|e| e.exit());
+        let function = value_t!(matches, "function", Family).unwrap_or_else(|e| e.exit());
+        let degree = match matches.value_of("degree") {
+            Some(_) => {
+                let degree = value_t!(matches, "degree", f64).unwrap_or_else(|e| e.exit());
+                Multibrot::new(degree)?;
+                Some(degree)
+            }
+            None => None,
+        };
+        let format = value_t!(matches, "format", OutputFormat).unwrap_or_else(|e| e.exit());
         let pointsfile = value_t!(matches, "pointsfile", String).unwrap_or_else(|e| e.exit());
 
         let path = env::current_dir().unwrap();
@@ -232,12 +489,17 @@ impl AnimationConfiguration {
         }
 
         Ok(AnimationConfiguration {
-            colorize: colorize,
-            width: width,
-            height: height,
-            multiply: multiply,
+            colorize,
+            dimension,
+            stats,
+            width,
+            height,
+            multiply,
+            function,
+            degree,
+            format,
             basepath: path,
-            pointsfile: pointsfile,
+            pointsfile,
         })
     }
 
@@ -0,0 +1,89 @@
+#[macro_use]
+extern crate clap;
+extern crate image;
+extern crate julia_set;
+
+use clap::{App, Arg, ArgMatches};
+use image::DynamicImage;
+use julia_set::colorize::{Colorizer, HSLColorizer};
+use julia_set::field::EscapeField;
+use julia_set::metadata::key;
+use julia_set::output::{save_image, OutputFormat};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn main() {
+    let matches = App::new("recolor")
+        .about("re-colors a saved escape field without recomputing it")
+        .version(crate_version!())
+        .arg(Arg::with_name("field")
+            .required(true)
+            .value_names(&["FIELD"])
+            .help("Escape field file to load, as saved by julia-set."))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_names(&["PATH"])
+            .help("Output image path. Defaults to the field path with a '.png' extension."))
+        .arg(Arg::with_name("format")
+            .short("f")
+            .long("format")
+            .value_names(&["FORMAT"])
+            .help("Output image format. Defaults to the format implied by the output path."))
+        .arg(Arg::with_name("colorizer")
+            .short("c")
+            .long("colorizer")
+            .value_names(&["PALETTE"])
+            .possible_values(&["hsl", "fire", "ice", "rainbow", "none"])
+            .default_value("hsl")
+            .help("Colorize the field with this palette, or not at all."))
+        .arg(Arg::with_name("stats")
+            .long("stats")
+            .help("Print statistics about the field's escape counts."))
+        .after_help("Exits with status 1 on any error.")
+        .get_matches();
+
+    if let Err(error) = recolor(&matches) {
+        println!("FATAL: {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Load the field named on the command line, then colorize and save it.
+fn recolor(matches: &ArgMatches) -> Result<(), String> {
+    let field_path = PathBuf::from(matches.value_of("field").unwrap());
+    let out_path = match matches.value_of("output") {
+        Some(path) => PathBuf::from(path),
+        None => field_path.with_extension("png"),
+    };
+    let format = match matches.value_of("format") {
+        Some(format) => OutputFormat::from_str(format)?,
+        None => OutputFormat::from_path(&out_path).unwrap_or_default(),
+    };
+    if format.holds_field() {
+        return Err(format!("recolor saves images, not the escape values {} holds", format));
+    }
+
+    let field = EscapeField::load(&field_path)
+        .map_err(|e| format!("Couldn't load escape field at {:?}: {}", field_path, e))?;
+
+    println!("Loaded escape field:");
+    println!("  Function:    {}", field.header.function);
+    println!("  Dimensions:  {:?}", (field.header.width, field.header.height));
+    println!("  Iterations:  {}", field.header.iterations);
+    println!("  Output path: {:?}", out_path);
+    if matches.is_present("stats") {
+        print!("{}", field.stats());
+    }
+
+    let colorizer = matches.value_of("colorizer").unwrap();
+    let image = field.to_image();
+    let image = match HSLColorizer::from_name(colorizer) {
+        Some(palette) => DynamicImage::ImageRgb8(palette.colorize(&image)),
+        None => DynamicImage::ImageLuma8(image),
+    };
+    let mut text = field.header.to_text();
+    text.push((key("colorizer"), String::from(colorizer)));
+    save_image(&image, &out_path, format, &text)
+        .map_err(|e| format!("Couldn't save {:?}: {}", out_path, e))
+}
@@ -0,0 +1,93 @@
+#[macro_use]
+extern crate clap;
+extern crate image;
+extern crate julia_set;
+
+use clap::{App, Arg, ArgMatches};
+use julia_set::{render, render_with_stats, DEFAULT_THREADS};
+use julia_set::metadata::RenderParameters;
+use julia_set::output::OutputFormat;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn main() {
+    let matches = App::new("rerender")
+        .about("re-renders an image exactly from the parameters embedded in its PNG metadata")
+        .version(crate_version!())
+        .arg(Arg::with_name("image")
+            .required(true)
+            .value_names(&["IMAGE"])
+            .help("PNG image saved by julia-set or animate."))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_names(&["PATH"])
+            .help("Output image path. Defaults to overwriting the input image."))
+        .arg(Arg::with_name("format")
+            .short("f")
+            .long("format")
+            .value_names(&["FORMAT"])
+            .help("Output image format. Defaults to the format implied by the output path."))
+        .arg(Arg::with_name("stats")
+            .long("stats")
+            .help("Print statistics about the render."))
+        .arg(Arg::with_name("verify")
+            .long("verify")
+            .help("Instead of saving, check that the re-rendered image matches the input."))
+        .after_help("Only escape-time renders can be reproduced; images made in any other mode \
+                     are refused. Exits with status 1 on any error, or if --verify finds the \
+                     images differ.")
+        .get_matches();
+
+    if let Err(error) = rerender(&matches) {
+        println!("FATAL: {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Re-render the image named on the command line, then save or verify it.
+fn rerender(matches: &ArgMatches) -> Result<(), String> {
+    let in_path = PathBuf::from(matches.value_of("image").unwrap());
+    let out_path = matches.value_of("output").map(PathBuf::from).unwrap_or_else(|| in_path.clone());
+
+    let format = match matches.value_of("format") {
+        Some(format) => OutputFormat::from_str(format)?,
+        None => OutputFormat::from_path(&out_path).unwrap_or_default(),
+    };
+    if format.holds_field() {
+        return Err(format!("rerender saves images, not the escape values {} holds", format));
+    }
+
+    let params = RenderParameters::load(&in_path)
+        .map_err(|e| format!("Couldn't read render parameters from {:?}: {}", in_path, e))?;
+
+    println!("Render parameters:");
+    println!("  c:           {}", params.c);
+    println!("  Dimensions:  {:?}", (params.width, params.height));
+    println!("  Supersample: {}", params.supersample);
+    println!("  Viewport:    {:?}", params.viewport);
+    println!("  Bailout:     {}", params.bailout);
+    println!("  Colorizer:   {}", params.colorizer);
+
+    let image = if matches.is_present("stats") {
+        let (image, stats) = render_with_stats(&params, DEFAULT_THREADS);
+        print!("{}", stats);
+        image
+    } else {
+        render(&params, DEFAULT_THREADS)
+    };
+
+    if matches.is_present("verify") {
+        let original = image::open(&in_path)
+            .map_err(|e| format!("Couldn't decode {:?}: {}", in_path, e))?;
+        if original.raw_pixels() != image.raw_pixels() {
+            return Err(String::from("Re-rendered image DIFFERS from the input."));
+        }
+        println!("Re-rendered image matches.");
+        Ok(())
+    } else {
+        println!("Saving as {:?}", out_path);
+        params.save(&image, &out_path, format)
+            .map_err(|e| format!("Couldn't save {:?}: {}", out_path, e))
+    }
+}
@@ -1,76 +1,895 @@
+#[macro_use]
+extern crate clap;
+extern crate crossbeam;
+extern crate csv;
 extern crate image;
 extern crate julia_set;
 extern crate num;
 
-use image::{ImageBuffer, GenericImage};
-use julia_set::{parallel_image, interpolate_stretch};
+use clap::{App, Arg};
+use image::{DynamicImage, GenericImage, GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+use julia_set::{parallel_image_escape, parallel_image_plane, parallel_image_stats, Plane,
+                RenderStats, Viewport, DEFAULT_THREADS};
+use julia_set::classify::{classify, Classification};
+use julia_set::colorize::{Colorizer, HSLColorizer};
+use julia_set::family::Family;
+use julia_set::metadata::{key, software, RenderParameters};
+use julia_set::output::{fits_tiff, save_image, OutputFormat, RowWriter};
+use julia_set::sheet::{format_complex, Axes, Map, MapPlacement, Sheet};
 use num::complex::Complex64;
-use std::env;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
 
-/// Construct a boxed function which computes the Julia set
-/// J(f_c(z)) where f_c(z) = z^2 + c.
-fn reify_fcz(c: Complex64) -> Box<Fn(Complex64) -> Complex64 + Sync> {
-    Box::new(move |z| (z * z) + c)
+fn main() {
+    let conf = match TiledConfiguration::new() {
+        Ok(conf) => conf,
+        Err(err) => {
+            println!("FATAL: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Input parameters:");
+    println!("  Function:    {}", conf.family);
+    println!("  Real:        {} to {} in {} steps", conf.real.0, conf.real.1, conf.steps.0);
+    println!("  Imaginary:   {} to {} in {} steps", conf.imag.0, conf.imag.1, conf.steps.1);
+    println!("  Viewport:    {:?}", conf.viewport);
+    println!("  Iterations:  {}", conf.iterations);
+    println!("  Threads:     {}", conf.threads);
+    println!("Output parameters:");
+    println!("  Tile size:   {:?}", conf.tile);
+    println!("  Colorizer:   {}", conf.colorizer);
+    println!("  Gutter:      {}", conf.gutter);
+    println!("  Output path: {:?}", conf.path);
+
+    let colorizer: Option<HSLColorizer<GrayImage>> = HSLColorizer::from_name(&conf.colorizer);
+    let palette = |level| match colorizer {
+        Some(ref colorizer) => colorizer.colorize_pixel(0, 0, Luma([level])),
+        None => Rgb([level, level, level]),
+    };
+    // without a palette, the sheet is entirely gray
+    let gray = colorizer.is_none();
+    let mut sheet = conf.sheet();
+    let placement = conf.map_placement(&sheet);
+    if let Some(placement) = placement {
+        println!("Rendering the parameter plane as a {}", placement);
+        sheet.map = Some(render_map(&conf, &sheet, placement, &palette));
+    }
+    let (width, height) = sheet.dimensions();
+
+    let cache = if conf.resume {
+        match TileCache::open(&conf.path, &conf.tile_parameters()) {
+            Ok(cache) => Some(cache),
+            Err(error) => {
+                println!("FATAL: {}", error);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut sink = if conf.stream {
+        println!("Streaming to {:?}", conf.path);
+        let channels = if gray { 1 } else { 3 };
+        let writer = File::create(&conf.path).and_then(|file| {
+            RowWriter::new(BufWriter::new(file), conf.format, width, height, channels)
+        });
+        match writer {
+            Ok(writer) => Sink::Stream(writer),
+            Err(error) => {
+                println!("FATAL: Failed to create image.");
+                println!("\t{}", error);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        Sink::Memory(ImageBuffer::new(width, height))
+    };
+
+    let tiles = match generate_tiled(&conf,
+                                     &sheet,
+                                     &palette,
+                                     cache.as_ref(),
+                                     &mut |first, band| sink.write(first, band, gray)) {
+        Ok(tiles) => tiles,
+        Err(error) => {
+            println!("FATAL: Failed to render tiles.");
+            println!("\t{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let mut text = vec![software(), (key("mode"), String::from("tiled"))];
+    text.extend(conf.tile_parameters());
+    text.extend(vec![(key("tiled.classification"),
+                      tiles.iter()
+                          .map(|tile| tile.classification.to_string())
+                          .collect::<Vec<_>>()
+                          .join(";")),
+                     (key("tiled.gutter"), conf.gutter.to_string()),
+                     (key("colorizer"), conf.colorizer.clone())]);
+    if let Some(placement) = placement {
+        text.push((key("tiled.map"), placement.to_string()));
+    }
+
+    let saved = match sink {
+        Sink::Memory(output) => {
+            let output = if gray {
+                DynamicImage::ImageLuma8(DynamicImage::ImageRgb8(output).to_luma())
+            } else {
+                DynamicImage::ImageRgb8(output)
+            };
+            println!("Saving as {:?}", conf.path);
+            save_image(&output, &conf.path, conf.format, &text)
+        }
+        Sink::Stream(writer) => writer.finish(&text).map(|_| ()),
+    };
+    if let Err(error) = saved {
+        println!("FATAL: Failed to save image.");
+        println!("\t{}", error);
+        std::process::exit(1);
+    }
+
+    if let Some(cache) = cache {
+        if let Err(error) = cache.remove() {
+            println!("WARNING: Failed to remove cached tiles.");
+            println!("\t{}", error);
+        }
+    }
+
+    if conf.labels {
+        let labels_path = conf.path.with_extension("csv");
+        println!("Saving labels as {:?}", labels_path);
+        if let Err(error) = write_labels(&labels_path, &sheet, &tiles) {
+            println!("FATAL: Failed to save labels.");
+            println!("\t{}", error);
+            std::process::exit(1);
+        }
+    }
 }
 
-fn generate_tiled(low: f64,
-                  steps: u32,
-                  interval: f64,
-                  tile_edge: u32,
-                  threshold: f64)
-                  -> ImageBuffer<image::Luma<u8>, Vec<u8>> {
-    let interpolate = interpolate_stretch(tile_edge, tile_edge, -1.0, 1.0, -1.0, 1.0);
-
-    let mut output = ImageBuffer::new(tile_edge * steps, tile_edge * steps);
-
-    for (y, imag) in (0..steps)
-        .map(|s| ((steps - s - 1) * tile_edge, low + (s as f64 * interval))) {
-        for (x, real) in (0..steps).map(|s| (s * tile_edge, low + (s as f64 * interval))) {
-            println!("\tGenerating tile for ({} + {}i)", real, imag);
-            let fcz = reify_fcz(Complex64::new(real, imag));
-            let tile = parallel_image(tile_edge, tile_edge, &*fcz, &*interpolate, threshold);
-            if !output.copy_from(&tile, x, y) {
-                println!("FATAL: Failed to copy tile into output.");
-                println!("\tTile at ({}, {}) sized ({}, {})",
-                         x,
-                         y,
-                         tile_edge,
-                         tile_edge);
-                let (width, height) = output.dimensions();
-                println!("\tOutput container dimensions ({}, {})", width, height);
-                panic!();
+/// A tile of the mosaic: the Julia set for one value of `c`.
+struct Tile {
+    /// Grid position, counting columns from the left and rows from the top
+    column: u32,
+    row: u32,
+    c: Complex64,
+    classification: Classification,
+}
+
+/// A tile on its way from a worker to the sheet.
+struct Rendered {
+    tile: Tile,
+    image: GrayImage,
+    /// `None` if the tile came from the cache, or statistics weren't asked for
+    stats: Option<RenderStats>,
+}
+
+/// Where the finished bands of the sheet go.
+enum Sink {
+    /// Into a whole image in memory, to be saved at the end
+    Memory(RgbImage),
+    /// Straight to disk
+    Stream(RowWriter<BufWriter<File>>),
+}
+
+impl Sink {
+    /// Take the band of the sheet starting from row `first`.
+    fn write(&mut self, first: u32, band: RgbImage, gray: bool) -> io::Result<()> {
+        match *self {
+            Sink::Memory(ref mut output) => {
+                if output.copy_from(&band, 0, first) {
+                    Ok(())
+                } else {
+                    Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       "band doesn't fit the rest of the image"))
+                }
+            }
+            Sink::Stream(ref mut writer) => {
+                if gray {
+                    writer.write_rows(&DynamicImage::ImageRgb8(band).to_luma().into_raw())
+                } else {
+                    writer.write_rows(&band.into_raw())
+                }
             }
         }
     }
+}
 
-    output
+/// Finished tiles kept on disk, so that an interrupted run can pick up where it left off.
+struct TileCache {
+    directory: PathBuf,
 }
 
-/// As this isn't really a user-facing program so much as a dev tool,
-/// we just hard-code a bunch of constants here and recompile if we
-/// want to change them.
-fn main() {
-    const LOW: f64 = -1.5;
-    const HIGH: f64 = 0.0;
-    const STEPS: u32 = 7;
-    const INTERVAL: f64 = (HIGH - LOW) / (STEPS - 1) as f64; // 0.5 in range [-1.5..1.5] with 7
-    const TILE_EDGE: u32 = 200;
-    const THRESHOLD: f64 = 2.0;
-
-    let output = generate_tiled(LOW, STEPS, INTERVAL, TILE_EDGE, THRESHOLD);
-
-    let file_name = {
-        let mut path = env::current_dir().unwrap();
-        path.push("tiles");
-        path.push(&format!("julia_range_{}..{}.png", LOW, HIGH));
-        path.to_string_lossy().into_owned()
+impl TileCache {
+    const MANIFEST: &'static str = "parameters.txt";
+
+    /// Open the cache for `output`, creating it if need be. Any tiles already in it must
+    /// have been rendered with the same `parameters`.
+    fn open(output: &Path, parameters: &[(String, String)]) -> Result<TileCache, String> {
+        let mut directory = output.as_os_str().to_owned();
+        directory.push(".parts");
+        let directory = PathBuf::from(directory);
+        let manifest = parameters.iter()
+            .map(|(keyword, value)| format!("{}={}\n", keyword, value))
+            .collect::<String>();
+        let manifest_path = directory.join(TileCache::MANIFEST);
+
+        match fs::read_to_string(&manifest_path) {
+            Ok(ref existing) if *existing != manifest => {
+                return Err(format!("the tiles in {:?} were rendered with different \
+                                    parameters; remove it to start over",
+                                   directory))
+            }
+            Ok(_) => println!("Resuming from {:?}", directory),
+            Err(_) => {
+                fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+                fs::write(&manifest_path, manifest).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(TileCache { directory })
+    }
+
+    fn tile_path(&self, column: u32, row: u32) -> PathBuf {
+        self.directory.join(format!("{}-{}.png", row, column))
+    }
+
+    /// The tile in `column` and `row`, if it's been cached at the right size.
+    fn load(&self, column: u32, row: u32, size: (u32, u32)) -> Option<GrayImage> {
+        image::open(self.tile_path(column, row))
+            .ok()
+            .map(|image| image.to_luma())
+            .filter(|image| image.dimensions() == size)
+    }
+
+    /// Cache the tile in `column` and `row`. It's written under another name and then
+    /// renamed, so that an interruption can't leave half a tile behind.
+    fn store(&self, column: u32, row: u32, image: &GrayImage) -> io::Result<()> {
+        let path = self.tile_path(column, row);
+        let partial = path.with_extension("partial");
+        save_image(&DynamicImage::ImageLuma8(image.clone()),
+                   &partial,
+                   OutputFormat::Png,
+                   &[])?;
+        fs::rename(partial, path)
+    }
+
+    fn remove(self) -> io::Result<()> {
+        fs::remove_dir_all(&self.directory)
+    }
+}
+
+/// Classify the Julia set for the tile in `column` and `row`, and render it on `threads`
+/// threads, unless it's already in `cache`.
+fn render_tile(conf: &TiledConfiguration,
+               cache: Option<&TileCache>,
+               column: u32,
+               row: u32,
+               threads: usize)
+               -> io::Result<Rendered> {
+    let (columns, rows) = conf.steps;
+    let c = Complex64::new(interval_step(conf.real, columns, column),
+                           interval_step(conf.imag, rows, rows - row - 1));
+    let tile = Tile {
+        column,
+        row,
+        c,
+        classification: classify(&conf.family, c, Classification::DEFAULT_ITERATIONS),
     };
+    if let Some(image) = cache.and_then(|cache| cache.load(column, row, conf.tile)) {
+        return Ok(Rendered {
+            tile,
+            image,
+            stats: None,
+        });
+    }
 
-    println!("\tSaving as {:?}", file_name);
-    if let Err(error) = output.save(file_name) {
-        println!("FATAL: Failed to save image.");
-        println!("\t{}", error);
-        panic!();
+    let (tile_width, tile_height) = conf.tile;
+    let viewport = conf.viewport.rectilinear(tile_width, tile_height);
+    let interpolate = viewport.interpolate(tile_width, tile_height);
+    let fcz = conf.family.bind(c);
+    let bailout = conf.family.bailout(c);
+    let (image, stats) = if conf.stats {
+        let (image, stats) = parallel_image_stats(tile_width,
+                                                  tile_height,
+                                                  &*fcz,
+                                                  &*interpolate,
+                                                  &bailout,
+                                                  conf.iterations,
+                                                  threads);
+        (image, Some(stats))
+    } else {
+        (parallel_image_escape(tile_width,
+                               tile_height,
+                               &*fcz,
+                               &*interpolate,
+                               &bailout,
+                               conf.iterations,
+                               threads),
+         None)
+    };
+    if let Some(cache) = cache {
+        cache.store(column, row, &image)?;
     }
+    Ok(Rendered { tile, image, stats })
+}
 
+/// Render the parameter plane under the grid of `sheet`, colored by `palette`.
+fn render_map(conf: &TiledConfiguration,
+              sheet: &Sheet,
+              placement: MapPlacement,
+              palette: &dyn Fn(u8) -> Rgb<u8>)
+              -> Map {
+    let (width, height) = sheet.map_size(placement);
+    let family = &conf.family;
+    let image = parallel_image_plane(width,
+                                     height,
+                                     &|z, c| family.eval(z, c),
+                                     &Plane::Parameter(family.critical_point()),
+                                     Complex64::new(0.0, 0.0),
+                                     &*conf.map_viewport(sheet).interpolate(width, height),
+                                     // the orbits of interest start small, so their escape
+                                     // radius is that of c = 0
+                                     &family.bailout(Complex64::new(0.0, 0.0)),
+                                     conf.iterations,
+                                     conf.threads);
+    Map {
+        image: ImageBuffer::from_fn(width, height, |x, y| palette(image.get_pixel(x, y).data[0])),
+        placement,
+    }
+}
+
+/// Render the tiles on a pool of workers, and pass the sheet, colored by `palette`, to
+/// `emit` a band at a time from the top down, along with the first row of each band.
+///
+/// Real parts increase to the right and imaginary parts upwards; tiles are listed from the
+/// bottom row up, and left to right along each row.
+fn generate_tiled(conf: &TiledConfiguration,
+                  sheet: &Sheet,
+                  palette: &dyn Fn(u8) -> Rgb<u8>,
+                  cache: Option<&TileCache>,
+                  emit: &mut dyn FnMut(u32, RgbImage) -> io::Result<()>)
+                  -> io::Result<Vec<Tile>> {
+    let (columns, rows) = conf.steps;
+    let (tile_width, tile_height) = conf.tile;
+    let count = (columns * rows) as usize;
+    let workers = conf.threads.clamp(1, count);
+    // with fewer tiles than threads, the spare threads work within tiles
+    let threads_per_tile = (conf.threads / count).max(1);
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    crossbeam::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                // tiles are handed out in sheet order, so that bands finish in turn
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= count {
+                    break;
+                }
+                let (column, row) = (index as u32 % columns, index as u32 / columns);
+                let rendered = render_tile(conf, cache, column, row, threads_per_tile);
+                // a closed channel means the sheet has given up
+                if sender.send((index, rendered)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let slot = |column: u32, row: u32| (row * columns + column) as usize;
+        let mut pending = HashMap::new();
+        let mut tiles = Vec::with_capacity(count);
+        let mut band = 0;
+        for (index, rendered) in receiver {
+            let rendered = rendered?;
+            println!("\tTile for {}: {}",
+                     format_complex(rendered.tile.c),
+                     rendered.tile.classification);
+            match rendered.stats {
+                Some(ref stats) => println!("\t\t{}", stats.summary()),
+                None if conf.stats => println!("\t\tcached"),
+                None => {}
+            }
+            pending.insert(index, rendered);
+
+            while band < rows &&
+                  (0..columns).all(|column| pending.contains_key(&slot(column, band))) {
+                let (first, height) = sheet.band(band);
+                let mut window = sheet.window(palette, first, height);
+                for column in 0..columns {
+                    let rendered = pending.remove(&slot(column, band)).unwrap();
+                    let image = ImageBuffer::from_fn(tile_width, tile_height, |x, y| {
+                        palette(rendered.image.get_pixel(x, y).data[0])
+                    });
+                    sheet.place(&mut window,
+                                first,
+                                column,
+                                band,
+                                &image,
+                                Some(&format_complex(rendered.tile.c)));
+                    tiles.push(rendered.tile);
+                }
+                emit(first, window)?;
+                band += 1;
+            }
+        }
+
+        tiles.sort_by_key(|tile| (rows - tile.row, tile.column));
+        Ok(tiles)
+    })
+}
+
+/// The `step`th of `steps` evenly spaced values from `interval.0` to `interval.1`.
+fn interval_step(interval: (f64, f64), steps: u32, step: u32) -> f64 {
+    if steps < 2 {
+        interval.0
+    } else {
+        interval.0 + (interval.1 - interval.0) * step as f64 / (steps - 1) as f64
+    }
+}
+
+/// Write the position, `c` and classification of every tile to a CSV file.
+fn write_labels(path: &PathBuf, sheet: &Sheet, tiles: &[Tile]) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_file(path)?;
+    writer.write(["column", "row", "x", "y", "real", "imag", "classification"].iter())?;
+    for tile in tiles {
+        let (x, y) = sheet.tile_origin(tile.column, tile.row);
+        writer.write([tile.column.to_string(),
+                      tile.row.to_string(),
+                      x.to_string(),
+                      y.to_string(),
+                      tile.c.re.to_string(),
+                      tile.c.im.to_string(),
+                      tile.classification.to_string()]
+            .iter())?;
+    }
+    writer.flush()
+}
+
+struct TiledConfiguration {
+    family: Family,
+    /// The range of real parts of `c`, from the left column to the right
+    real: (f64, f64),
+    /// The range of imaginary parts of `c`, from the bottom row to the top
+    imag: (f64, f64),
+    /// The number of columns and rows
+    steps: (u32, u32),
+    /// The size of each tile
+    tile: (u32, u32),
+    /// The region of the plane shown in each tile
+    viewport: Viewport,
+    iterations: usize,
+    colorizer: String,
+    /// Whether to write the `c` of each tile to a CSV file beside the image
+    labels: bool,
+    /// The space between tiles, in pixels
+    gutter: u32,
+    axes: bool,
+    captions: bool,
+    legend: bool,
+    /// The size of each pixel of the label font
+    text_scale: u32,
+    /// The number of tiles to render at once
+    threads: usize,
+    /// Whether to print statistics about each tile's render
+    stats: bool,
+    /// Whether to write the image a band at a time, rather than hold it all in memory
+    stream: bool,
+    /// Whether to keep finished tiles on disk, and reuse any from an earlier run
+    resume: bool,
+    /// How to show the parameter plane under the grid, if at all
+    map: Option<String>,
+    map_opacity: f64,
+    /// The width of an inset map, if not the default
+    inset_width: Option<u32>,
+    format: OutputFormat,
+    path: PathBuf,
+}
+
+impl TiledConfiguration {
+    fn build_cli() -> App<'static, 'static> {
+        App::new("tiled")
+           .about("renders a grid of julia sets over a range of c, for comparing parameters")
+          .version(crate_version!())
+          .arg(Arg::with_name("function")
+                    .short("F")
+                    .long("function")
+                    .value_names(&["EXPR"])
+                    .default_value(RenderParameters::DEFAULT_FUNCTION)
+                    .help("Iteration function of z and c, such as 'z^3 + c*sin(z)', a number d \
+                           for the multibrot z^d + c, or one of the families exp, sin, cos, \
+                           burning-ship, tricorn, celtic or buffalo.")
+                )
+          .arg(Arg::with_name("real")
+                    .long("real")
+                    .value_names(&["MIN", "MAX"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .default_value("-1.5,0")
+                    .help("The range of the real part of c, from the left column to the right.")
+                )
+          .arg(Arg::with_name("imag")
+                    .long("imag")
+                    .value_names(&["MIN", "MAX"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .default_value("-1.5,0")
+                    .help("The range of the imaginary part of c, from the bottom row to the top.")
+                )
+          .arg(Arg::with_name("steps")
+                    .short("s")
+                    .long("steps")
+                    .value_names(&["COLUMNS", "ROWS"])
+                    .use_delimiter(true)
+                    .default_value("7,7")
+                    .help("The number of values of each part of c, including both ends.")
+                )
+          .arg(Arg::with_name("tile")
+                    .short("t")
+                    .long("tile")
+                    .value_names(&["WIDTH", "HEIGHT"])
+                    .use_delimiter(true)
+                    .default_value("200,200")
+                    .help("Set the dimensions of each tile.")
+                )
+          .arg(Arg::with_name("viewport")
+                    .long("viewport")
+                    .value_names(&["MIN_X", "MAX_X", "MIN_Y", "MAX_Y"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .default_value("-1,1,-1,1")
+                    .help("The region of the plane shown in each tile, widened to fit its \
+                           aspect ratio.")
+                )
+          .arg(Arg::with_name("iterations")
+                    .short("i")
+                    .long("iterations")
+                    .value_names(&["N"])
+                    .default_value("255")
+                    .help("Maximum number of iterations per point.")
+                )
+          .arg(Arg::with_name("colorizer")
+                    .long("colorizer")
+                    .value_names(&["PALETTE"])
+                    .possible_values(&["hsl", "fire", "ice", "rainbow", "none"])
+                    .default_value("none")
+                    .help("Colorize the output with this palette, or not at all.")
+                )
+          .arg(Arg::with_name("labels")
+                    .long("labels")
+                    .help("Also write the grid position and c of every tile to a CSV file with \
+                           the same name as the output image.")
+                )
+          .arg(Arg::with_name("gutter")
+                    .long("gutter")
+                    .value_names(&["PIXELS"])
+                    .default_value("0")
+                    .help("Leave this much space between neighboring tiles.")
+                )
+          .arg(Arg::with_name("axes")
+                    .long("axes")
+                    .help("Frame the grid with axes, with ticks labelling the real part of c \
+                           for each column and the imaginary part for each row.")
+                )
+          .arg(Arg::with_name("captions")
+                    .long("captions")
+                    .help("Caption each tile with its value of c.")
+                )
+          .arg(Arg::with_name("legend")
+                    .long("legend")
+                    .help("Add a legend showing the escape count for each color.")
+                )
+          .arg(Arg::with_name("text-scale")
+                    .long("text-scale")
+                    .value_names(&["N"])
+                    .default_value("1")
+                    .help("Draw labels with each font pixel N pixels across. The font is 5x7 \
+                           pixels at scale 1.")
+                )
+          .arg(Arg::with_name("map")
+                    .long("map")
+                    .value_names(&["PLACEMENT"])
+                    .possible_values(&["backdrop", "inset"])
+                    .help("Also render the parameter plane for the same range of c, placing the \
+                           c of each tile at its center: as a backdrop behind the tiles, or as \
+                           an inset beside them. The position of each tile is marked.")
+                )
+          .arg(Arg::with_name("map-opacity")
+                    .long("map-opacity")
+                    .value_names(&["A"])
+                    .requires("map")
+                    .help("How much of a backdrop map shows through the tiles, from 0 to 1. \
+                           [default: 0.35]")
+                )
+          .arg(Arg::with_name("inset-width")
+                    .long("inset-width")
+                    .value_names(&["PIXELS"])
+                    .requires("map")
+                    .help("The width of an inset map, at most that of the grid. [default: a \
+                           third of the grid width]")
+                )
+          .arg(Arg::with_name("threads")
+                    .short("j")
+                    .long("threads")
+                    .value_names(&["N"])
+                    .help("Number of tiles to render at once. [default: 4]")
+                )
+          .arg(Arg::with_name("stats")
+                    .long("stats")
+                    .help("Print statistics about the render of each tile.")
+                )
+          .arg(Arg::with_name("stream")
+                    .long("stream")
+                    .help("Write the image a band of tiles at a time as they're finished, rather \
+                           than holding it all in memory. Only PNG and TIFF can be streamed.")
+                )
+          .arg(Arg::with_name("resume")
+                    .long("resume")
+                    .help("Keep finished tiles in a directory named after the output with \
+                           .parts appended, and reuse any left there by an interrupted run with \
+                           the same parameters. The directory is removed once the image is \
+                           saved.")
+                )
+          .arg(Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .value_names(&["FORMAT"])
+                    .help("Output image format: png, jpeg[:QUALITY], tiff, webp or ppm. \
+                           Defaults to the format implied by the output path.")
+                )
+          .arg(Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .value_names(&["PATH"])
+                    .default_value("tiles.png")
+                    .help("Output image path.")
+                )
+    }
+
+    /// The layout of the output image.
+    fn sheet(&self) -> Sheet {
+        let (columns, rows) = self.steps;
+        let mut sheet = Sheet::new(columns, rows, self.tile.0, self.tile.1);
+        sheet.gutter = self.gutter;
+        if self.axes {
+            sheet.axes = Some(Axes {
+                real: (0..columns).map(|step| interval_step(self.real, columns, step)).collect(),
+                imag: (0..rows).rev().map(|step| interval_step(self.imag, rows, step)).collect(),
+            });
+        }
+        sheet.captions = self.captions;
+        if self.legend {
+            sheet.legend = Some(self.iterations);
+        }
+        sheet.scale = self.text_scale;
+        sheet
+    }
+
+    /// Where to draw the parameter plane on `sheet`, if at all.
+    fn map_placement(&self, sheet: &Sheet) -> Option<MapPlacement> {
+        match self.map.as_deref() {
+            Some("backdrop") => Some(MapPlacement::Backdrop(self.map_opacity)),
+            Some(_) => {
+                let width = self.inset_width.unwrap_or(sheet.grid_dimensions().0 / 3);
+                Some(MapPlacement::Inset(width))
+            }
+            None => None,
+        }
+    }
+
+    /// The region of the parameter plane under the grid of `sheet`, from its top left
+    /// corner to its bottom right, placing the `c` of each tile at the center of the tile.
+    fn map_viewport(&self, sheet: &Sheet) -> Viewport {
+        let (columns, rows) = self.steps;
+        let (origin_x, origin_y) = sheet.tile_origin(0, 0);
+        let (next_x, next_y) = sheet.tile_origin(1, 1);
+        // the change in c from one pixel to the next; a single column or row takes its
+        // scale from the other direction
+        let scale = |interval: (f64, f64), steps: u32, pitch: u32| if steps > 1 {
+            Some((interval.1 - interval.0) / (steps - 1) as f64 / pitch as f64)
+        } else {
+            None
+        };
+        let re = scale(self.real, columns, next_x - origin_x);
+        let im = scale(self.imag, rows, next_y - origin_y);
+        let fallback = (self.viewport.max_x - self.viewport.min_x) / self.tile.0 as f64;
+        let re = re.or_else(|| im.map(f64::abs)).unwrap_or(fallback);
+        let im = im.unwrap_or_else(|| re.abs());
+
+        let (grid_width, grid_height) = sheet.grid_dimensions();
+        let (center_x, center_y) = (self.tile.0 as f64 / 2.0, self.tile.1 as f64 / 2.0);
+        let left = interval_step(self.real, columns, 0);
+        // the top row has the greatest imaginary part
+        let top = interval_step(self.imag, rows, rows - 1);
+        Viewport::new(left - center_x * re,
+                      left + (grid_width as f64 - 1.0 - center_x) * re,
+                      top + center_y * im,
+                      top - (grid_height as f64 - 1.0 - center_y) * im)
+    }
+
+    /// The parameters which decide how each tile is rendered, as text chunks.
+    fn tile_parameters(&self) -> Vec<(String, String)> {
+        // multibrot escape radii depend on c, and so differ from tile to tile
+        let bailout = match self.family {
+            Family::Multibrot(_) => String::from("escape radius"),
+            _ => self.family.bailout(Complex64::new(0.0, 0.0)).to_string(),
+        };
+        let vp = &self.viewport;
+        vec![(key("function"), self.family.to_string()),
+             (key("tiled.real"), format!("{},{}", self.real.0, self.real.1)),
+             (key("tiled.imag"), format!("{},{}", self.imag.0, self.imag.1)),
+             (key("tiled.steps"), format!("{},{}", self.steps.0, self.steps.1)),
+             (key("tiled.tile_size"), format!("{},{}", self.tile.0, self.tile.1)),
+             (key("viewport"), format!("{},{},{},{}", vp.min_x, vp.max_x, vp.min_y, vp.max_y)),
+             (key("bailout"), bailout),
+             (key("iterations"), self.iterations.to_string())]
+    }
+
+    /// Construct a new configuration object by reading and parsing the command line.
+    fn new() -> Result<TiledConfiguration, String> {
+        TiledConfiguration::unpack_matches(TiledConfiguration::build_cli().get_matches())
+    }
+
+    fn unpack_matches(matches: clap::ArgMatches) -> Result<TiledConfiguration, String> {
+        let family = value_t!(matches, "function", Family).unwrap_or_else(|e| e.exit());
+        let real = values_t!(matches, "real", f64).unwrap_or_else(|e| e.exit());
+        let imag = values_t!(matches, "imag", f64).unwrap_or_else(|e| e.exit());
+        let steps = values_t!(matches, "steps", u32).unwrap_or_else(|e| e.exit());
+        if steps.contains(&0) {
+            return Err(String::from("there must be at least one step in each direction"));
+        }
+        let tile = values_t!(matches, "tile", u32).unwrap_or_else(|e| e.exit());
+        if tile[0] < 2 || tile[1] < 2 {
+            return Err(String::from("tiles must be at least 2 pixels in each direction"));
+        }
+        let vp = values_t!(matches, "viewport", f64).unwrap_or_else(|e| e.exit());
+        if real.iter().chain(&imag).chain(&vp).any(|v| !v.is_finite()) {
+            return Err(String::from("real, imag and viewport values must be finite"));
+        }
+        if vp[0] >= vp[1] || vp[2] >= vp[3] {
+            return Err(String::from("viewport minimums must be less than maximums"));
+        }
+        let iterations = value_t!(matches, "iterations", usize).unwrap_or_else(|e| e.exit());
+        if iterations == 0 {
+            return Err(String::from("iterations must be nonzero"));
+        }
+        let gutter = value_t!(matches, "gutter", u32).unwrap_or_else(|e| e.exit());
+        let text_scale = value_t!(matches, "text-scale", u32).unwrap_or_else(|e| e.exit());
+        if text_scale == 0 {
+            return Err(String::from("text scale must be at least 1"));
+        }
+        let threads = match matches.value_of("threads") {
+            Some(_) => value_t!(matches, "threads", usize).unwrap_or_else(|e| e.exit()),
+            None => DEFAULT_THREADS,
+        };
+
+        let path = PathBuf::from(matches.value_of("output").unwrap());
+        let format = match matches.value_of("format") {
+            Some(_) => value_t!(matches, "format", OutputFormat).unwrap_or_else(|e| e.exit()),
+            None => OutputFormat::from_path(&path).unwrap_or_default(),
+        };
+        if format.holds_field() {
+            return Err(format!("mosaics can't be saved as {}", format));
+        }
+        let map_opacity = match matches.value_of("map-opacity") {
+            Some(_) => value_t!(matches, "map-opacity", f64).unwrap_or_else(|e| e.exit()),
+            None => 0.35,
+        };
+        if !(0.0..=1.0).contains(&map_opacity) {
+            return Err(String::from("map opacity must be between 0 and 1"));
+        }
+        let inset_width = if matches.is_present("inset-width") {
+            Some(value_t!(matches, "inset-width", u32).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        };
+        let stream = matches.is_present("stream");
+        if stream && !format.streams() {
+            return Err(format!("{} images can't be streamed; use png or tiff", format));
+        }
+
+        let conf = TiledConfiguration {
+            family,
+            real: (real[0], real[1]),
+            imag: (imag[0], imag[1]),
+            steps: (steps[0], steps[1]),
+            tile: (tile[0], tile[1]),
+            viewport: Viewport::new(vp[0], vp[1], vp[2], vp[3]),
+            iterations,
+            colorizer: String::from(matches.value_of("colorizer").unwrap()),
+            labels: matches.is_present("labels"),
+            gutter,
+            axes: matches.is_present("axes"),
+            captions: matches.is_present("captions"),
+            legend: matches.is_present("legend"),
+            text_scale,
+            threads,
+            stats: matches.is_present("stats"),
+            stream,
+            resume: matches.is_present("resume"),
+            map: matches.value_of("map").map(String::from),
+            map_opacity,
+            inset_width,
+            format,
+            path,
+        };
+
+        if format == OutputFormat::Tiff {
+            // only the placement of a map affects the layout, not what's on it
+            let mut sheet = conf.sheet();
+            sheet.map = conf.map_placement(&sheet).map(|placement| {
+                Map {
+                    image: ImageBuffer::new(0, 0),
+                    placement,
+                }
+            });
+            let (width, height) = sheet.dimensions();
+            let channels = if conf.colorizer == "none" { 1 } else { 3 };
+            if !fits_tiff(width, height, channels) {
+                return Err(format!("a {}x{} mosaic is too large for TIFF; use png",
+                                   width,
+                                   height));
+            }
+        }
+        Ok(conf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn test_validation() {
+        let unpack = |args: &[&str]| {
+            let matches = TiledConfiguration::build_cli()
+                .get_matches_from_safe(Some("tiled").iter().chain(args))
+                .unwrap();
+            TiledConfiguration::unpack_matches(matches)
+        };
+        assert!(unpack(&[]).is_ok());
+        assert!(unpack(&["--viewport=1,-1,-1,1"]).is_err());
+        assert!(unpack(&["--viewport=NaN,1,-1,1"]).is_err());
+        assert!(unpack(&["--viewport=-1,inf,-1,1"]).is_err());
+        assert!(unpack(&["--real=NaN,0"]).is_err());
+        assert!(unpack(&["--iterations=0"]).is_err());
+    }
+
+    #[test]
+    fn test_tile_cache() {
+        let directory = env::temp_dir().join(format!("tiled-test-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let output = directory.join("tiles.png");
+        let parameters = vec![(key("iterations"), String::from("255"))];
+        let tile = ImageBuffer::from_fn(4, 3, |x, y| Luma([(x * 60 + y) as u8]));
+
+        let cache = TileCache::open(&output, &parameters).unwrap();
+        assert!(cache.load(1, 0, (4, 3)).is_none());
+        cache.store(1, 0, &tile).unwrap();
+        // the tile was renamed into place, leaving nothing partial behind
+        assert!(!cache.tile_path(1, 0).with_extension("partial").exists());
+
+        let reopened = TileCache::open(&output, &parameters).unwrap();
+        assert_eq!(reopened.load(1, 0, (4, 3)).map(|image| image.into_raw()),
+                   Some(tile.into_raw()));
+        assert!(reopened.load(1, 0, (3, 4)).is_none());
+        assert!(reopened.load(0, 0, (4, 3)).is_none());
+
+        let changed = vec![(key("iterations"), String::from("100"))];
+        assert!(TileCache::open(&output, &changed).is_err());
+
+        reopened.remove().unwrap();
+        assert!(!cache.directory.exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
 }
@@ -0,0 +1,332 @@
+//! Orbit-density renders: the Buddhabrot and its relatives.
+//!
+//! Instead of coloring each point by its own fate, a density render samples many values
+//! of `c`, follows the orbit of the critical point under each, and counts how often the
+//! orbits pass through each pixel. Counting the orbits which escape gives the Buddhabrot;
+//! counting those which don't gives the anti-Buddhabrot.
+//!
+//! Orbits of different lengths trace out different structures, so a render may keep
+//! several channels, each counting only orbits whose escape count falls in its own range.
+//! Three such channels mapped onto red, green and blue make a Nebulabrot.
+//!
+//! Sampling is split into fixed-size batches, each with its own seed, which threads take
+//! in turn. Each thread accumulates into its own buffer, and the buffers are summed at
+//! the end, so the result doesn't depend on the number of threads.
+
+use crossbeam;
+use image::{ImageBuffer, Luma, Rgb};
+use num::complex::Complex64;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use family::Family;
+use random::XorShift;
+use viewport::Viewport;
+
+/// A range of escape counts, inclusive at both ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Channel {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl Channel {
+    pub fn new(min: usize, max: usize) -> Channel {
+        Channel { min, max }
+    }
+
+    fn contains(&self, count: usize) -> bool {
+        self.min <= count && count <= self.max
+    }
+
+    /// The channels of the classic Nebulabrot, for red, green and blue.
+    pub fn nebulabrot() -> Vec<Channel> {
+        vec![Channel::new(0, 5000), Channel::new(0, 500), Channel::new(0, 50)]
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.min, self.max)
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    /// Parse `MIN:MAX`, or just `MAX` for `0:MAX`.
+    fn from_str(s: &str) -> Result<Channel, String> {
+        let bounds = s.split(':')
+            .map(|part| usize::from_str(part.trim()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid channel '{}'", s))?;
+        let channel = match bounds.len() {
+            1 => Channel::new(0, bounds[0]),
+            2 => Channel::new(bounds[0], bounds[1]),
+            _ => return Err(format!("channel '{}' should be MIN:MAX", s)),
+        };
+        if channel.max == 0 || channel.min > channel.max {
+            return Err(format!("channel '{}' is empty", s));
+        }
+        Ok(channel)
+    }
+}
+
+/// Per-channel hit counts, each in row-major order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Density {
+    pub width: u32,
+    pub height: u32,
+    pub channels: Vec<Vec<u32>>,
+}
+
+impl Density {
+    /// Map a channel onto brightness. A few pixels where orbits pile up can be far
+    /// denser than the rest, so counts are scaled linearly up to the 99.9th percentile of
+    /// the pixels which were hit, and anything denser is saturated.
+    fn scaled(&self, channel: usize) -> Vec<u8> {
+        let counts = &self.channels[channel];
+        let mut hit = counts.iter().cloned().filter(|&count| count > 0).collect::<Vec<_>>();
+        hit.sort();
+        let max = hit.get(hit.len() * 999 / 1000).cloned().unwrap_or(0).max(1) as f64;
+        counts.iter()
+            .map(|&count| ((count as f64 / max).min(1.0) * u8::MAX as f64).round() as u8)
+            .collect()
+    }
+
+    /// An image of the first channel, for the same colorizers as an escape-count image.
+    pub fn to_image(&self) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_raw(self.width, self.height, self.scaled(0)).unwrap()
+    }
+
+    /// An image of the first three channels as red, green and blue; missing channels are
+    /// black.
+    pub fn to_rgb(&self) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+        let scaled = (0..3)
+            .map(|channel| if channel < self.channels.len() {
+                self.scaled(channel)
+            } else {
+                vec![0; (self.width * self.height) as usize]
+            })
+            .collect::<Vec<_>>();
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let i = (y * self.width + x) as usize;
+            Rgb([scaled[0][i], scaled[1][i], scaled[2][i]])
+        })
+    }
+}
+
+/// A Buddhabrot renderer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Buddhabrot {
+    /// The family whose parameter plane is sampled
+    pub family: Family,
+    pub channels: Vec<Channel>,
+    /// Count the orbits which never escape instead of those which do. A bounded orbit is
+    /// counted in every channel, for as many steps as the channel's maximum.
+    pub anti: bool,
+    /// The number of values of `c` to sample
+    pub samples: usize,
+    /// The region values of `c` are sampled from, uniformly
+    pub sample_region: Viewport,
+    pub seed: u64,
+}
+
+impl Buddhabrot {
+    /// Samples are taken in batches of this many, each batch with its own seed.
+    const BATCH: usize = 4096;
+
+    pub fn new(family: Family, channels: Vec<Channel>, samples: usize) -> Buddhabrot {
+        Buddhabrot {
+            family,
+            channels,
+            anti: false,
+            samples,
+            sample_region: Viewport::new(-2.0, 2.0, -2.0, 2.0),
+            seed: 0,
+        }
+    }
+
+    /// The iteration limit: the longest any channel needs.
+    pub fn iterations(&self) -> usize {
+        self.channels.iter().map(|channel| channel.max).max().unwrap_or(0)
+    }
+
+    /// Whether `c` is known to be in the Mandelbrot set's main cardioid or period-2 bulb,
+    /// whose orbits never escape.
+    fn in_main_components(&self, c: Complex64) -> bool {
+        if self.family != Family::default() {
+            return false;
+        }
+        let q = (c.re - 0.25) * (c.re - 0.25) + c.im * c.im;
+        q * (q + (c.re - 0.25)) <= 0.25 * c.im * c.im ||
+        (c.re + 1.0) * (c.re + 1.0) + c.im * c.im <= 0.0625
+    }
+
+    /// Follow the orbit for `c`, and count it into `counts` if it's of interest.
+    fn accumulate(&self,
+                  c: Complex64,
+                  orbit: &mut Vec<Complex64>,
+                  counts: &mut [Vec<u32>],
+                  index: &dyn Fn(Complex64) -> Option<usize>) {
+        if !self.anti && self.in_main_components(c) {
+            return;
+        }
+        let iterations = self.iterations();
+        let bailout = self.family.bailout(c);
+        orbit.clear();
+        let mut z = self.family.critical_point();
+        let mut escaped = false;
+        for _ in 0..iterations {
+            z = self.family.eval(z, c);
+            if bailout.escaped(z) {
+                escaped = true;
+                break;
+            }
+            orbit.push(z);
+        }
+        if escaped == self.anti {
+            return;
+        }
+
+        for (channel, counts) in self.channels.iter().zip(counts.iter_mut()) {
+            let points = if self.anti {
+                &orbit[..channel.max.min(orbit.len())]
+            } else if channel.contains(orbit.len() + 1) {
+                &orbit[..]
+            } else {
+                continue;
+            };
+            for &z in points {
+                if let Some(i) = index(z) {
+                    counts[i] = counts[i].saturating_add(1);
+                }
+            }
+        }
+    }
+
+    /// Accumulate orbit densities over a `width * height` image of `viewport` in parallel
+    /// on `threads` threads.
+    pub fn render(&self,
+                  width: u32,
+                  height: u32,
+                  viewport: &Viewport,
+                  threads: usize)
+                  -> Density {
+        let size = (width * height) as usize;
+        let empty = vec![vec![0u32; size]; self.channels.len()];
+        let totals = Arc::new(Mutex::new(empty.clone()));
+        let batch_n = Arc::new(AtomicUsize::new(0));
+        let batches = self.samples.div_ceil(Buddhabrot::BATCH);
+        let index = |z| viewport.locate(z, width, height).map(|(x, y)| (y * width + x) as usize);
+        let region = &self.sample_region;
+
+        crossbeam::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                let totals = totals.clone();
+                let batch_n = batch_n.clone();
+                let mut counts = empty.clone();
+                let index = &index;
+
+                scope.spawn(move || {
+                    // thread-local storage for the current orbit
+                    let mut orbit = Vec::with_capacity(self.iterations());
+
+                    loop {
+                        let batch = batch_n.fetch_add(1, Ordering::SeqCst);
+                        if batch >= batches {
+                            break;
+                        }
+
+                        let mut rng = XorShift::new(self.seed.wrapping_add(batch as u64)
+                            .wrapping_mul(0xbf58_476d_1ce4_e5b9));
+                        let start = batch * Buddhabrot::BATCH;
+                        for _ in start..(start + Buddhabrot::BATCH).min(self.samples) {
+                            let c = Complex64::new(region.min_x +
+                                                   rng.next_f64() * (region.max_x - region.min_x),
+                                                   region.min_y +
+                                                   rng.next_f64() * (region.max_y - region.min_y));
+                            self.accumulate(c, &mut orbit, &mut counts, index);
+                        }
+                    }
+
+                    // merge this thread's counts into the totals
+                    let mut totals = totals.lock().unwrap();
+                    for (total, counts) in totals.iter_mut().zip(counts.iter()) {
+                        for (t, &n) in total.iter_mut().zip(counts.iter()) {
+                            *t = t.saturating_add(n);
+                        }
+                    }
+                });
+            }
+        });
+
+        Density {
+            width,
+            height,
+            channels: Arc::try_unwrap(totals).ok().unwrap().into_inner().unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_from_str() {
+        assert_eq!(Channel::from_str("500"), Ok(Channel::new(0, 500)));
+        assert_eq!(Channel::from_str("20:500"), Ok(Channel::new(20, 500)));
+        assert!(Channel::from_str("500:20").is_err());
+        assert!(Channel::from_str("0").is_err());
+        assert!(Channel::from_str("a:b").is_err());
+    }
+
+    #[test]
+    fn test_independent_of_threads() {
+        let viewport = Viewport::new(-2.0, 1.0, -1.5, 1.5);
+        let buddhabrot = Buddhabrot::new(Family::default(), Channel::nebulabrot(), 10_000);
+        let one = buddhabrot.render(30, 30, &viewport, 1);
+        assert_eq!(one, buddhabrot.render(30, 30, &viewport, 3));
+        assert!(one.channels.iter().all(|channel| channel.iter().any(|&n| n > 0)));
+        // longer orbits are a superset of shorter ones
+        let total = |channel: &Vec<u32>| channel.iter().map(|&n| n as u64).sum::<u64>();
+        assert!(total(&one.channels[0]) >= total(&one.channels[1]));
+        assert!(total(&one.channels[1]) >= total(&one.channels[2]));
+    }
+
+    #[test]
+    fn test_skips_main_components() {
+        // however the quadratic family is written, its cardioid and bulb are skipped
+        for family in &["z^2 + c", "2"] {
+            let buddhabrot = Buddhabrot::new(Family::from_str(family).unwrap(),
+                                             Channel::nebulabrot(),
+                                             10);
+            assert!(buddhabrot.in_main_components(Complex64::new(0.0, 0.0)));
+            assert!(buddhabrot.in_main_components(Complex64::new(-1.0, 0.1)));
+            assert!(!buddhabrot.in_main_components(Complex64::new(0.5, 0.0)));
+        }
+        let cubic = Buddhabrot::new(Family::from_str("3").unwrap(), Channel::nebulabrot(), 10);
+        assert!(!cubic.in_main_components(Complex64::new(0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_anti_buddhabrot_stays_inside() {
+        // bounded orbits of z^2 + c never leave the disk of radius 2
+        let viewport = Viewport::new(-3.0, 3.0, -3.0, 3.0);
+        let buddhabrot = Buddhabrot {
+            anti: true,
+            ..Buddhabrot::new(Family::default(), vec![Channel::new(0, 100)], 5_000)
+        };
+        let density = buddhabrot.render(61, 61, &viewport, 2);
+        for (i, &n) in density.channels[0].iter().enumerate() {
+            if n > 0 {
+                let z = viewport.pixel(i as u32 % 61, i as u32 / 61, 61, 61);
+                assert!(z.norm() < 2.1, "{} hit {} times", z, n);
+            }
+        }
+        assert!(density.channels[0].iter().any(|&n| n > 0));
+    }
+}
@@ -0,0 +1,138 @@
+//! Classifying Julia sets by the fate of the critical orbit.
+//!
+//! For the multibrots `z^d + c`, the orbit of the critical point 0 decides the shape of the
+//! whole Julia set: if it escapes, the set is a Cantor dust; if it doesn't, the set is
+//! connected. When it's caught by an attracting cycle the set also has an interior, made
+//! of the basins of the cycle, and `c` lies inside a hyperbolic component of the parameter
+//! plane. Bounded orbits which are never caught belong to `c` on the boundary of the
+//! parameter plane's set, or so near it that the limit ran out first: parabolic and Siegel
+//! parameters, dendrites, and the like.
+//!
+//! The same test is applied to every family, with its own critical point and escape
+//! criterion. Outside the multibrots it's a useful hint rather than a theorem.
+
+use num::complex::Complex64;
+use std::fmt;
+
+use attractor::{fate, AttractorTest, Fate};
+use family::Family;
+
+/// What the critical orbit says about the Julia set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Classification {
+    /// The critical orbit is attracted to a cycle of this period: the set is connected and
+    /// has an interior.
+    Connected { period: usize },
+    /// The critical orbit escaped after this many steps: the set is disconnected. The
+    /// more steps, the nearer `c` is to the boundary.
+    Disconnected { steps: usize },
+    /// The critical orbit stayed bounded without being attracted to anything.
+    Boundary,
+}
+
+impl Classification {
+    /// An iteration limit which decides all but the slowest orbits.
+    pub const DEFAULT_ITERATIONS: usize = 10_000;
+
+    /// A one-word name: `connected`, `disconnected` or `boundary`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Classification::Connected { .. } => "connected",
+            Classification::Disconnected { .. } => "disconnected",
+            Classification::Boundary => "boundary",
+        }
+    }
+
+    /// Whether the set is connected, as far as the iteration limit can tell.
+    pub fn is_connected(&self) -> bool {
+        !matches!(*self, Classification::Disconnected { .. })
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Classification::Connected { period } => write!(f, "connected, period {}", period),
+            Classification::Disconnected { steps } => {
+                write!(f, "disconnected, escaped after {} steps", steps)
+            }
+            Classification::Boundary => write!(f, "boundary"),
+        }
+    }
+}
+
+/// Classify the Julia set of `family` for `c` by following the critical orbit for up to
+/// `iterations` steps.
+pub fn classify(family: &Family, c: Complex64, iterations: usize) -> Classification {
+    let function = family.bind(c);
+    let test = AttractorTest {
+        convergence: None,
+        periodicity: Some(AttractorTest::DEFAULT_TOLERANCE),
+    };
+    let start = family.critical_point();
+    match fate(start, &*function, &family.bailout(c), iterations, &test) {
+        Fate::Escaped(steps) => Classification::Disconnected { steps },
+        Fate::Attracted { steps, period } => {
+            // the periodicity test catches any cycle the orbit lands on exactly, repelling
+            // ones included, such as those of Misiurewicz parameters
+            let z = (0..steps).fold(start, |z, _| function(z));
+            if is_attracting(&*function, z, period) {
+                Classification::Connected { period }
+            } else {
+                Classification::Boundary
+            }
+        }
+        Fate::Bounded(_) => Classification::Boundary,
+    }
+}
+
+/// Whether orbits near `z`, a point on a cycle of `period`, close in on it.
+///
+/// Nearby orbits are followed around the cycle a few times in two directions, rather than
+/// computing the multiplier, so that this works for non-holomorphic families too.
+fn is_attracting(function: &dyn Fn(Complex64) -> Complex64,
+                 z: Complex64,
+                 period: usize)
+                 -> bool {
+    const DELTA: f64 = 1e-7;
+    const LAPS: usize = 8;
+    [Complex64::new(DELTA, 0.0), Complex64::new(0.0, DELTA)].iter().all(|&offset| {
+        let (mut near, mut on) = (z + offset, z);
+        for _ in 0..period * LAPS {
+            near = function(near);
+            on = function(on);
+        }
+        (near - on).norm() < DELTA
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quadratic(re: f64, im: f64) -> Classification {
+        classify(&Family::default(),
+                 Complex64::new(re, im),
+                 Classification::DEFAULT_ITERATIONS)
+    }
+
+    #[test]
+    fn test_quadratic() {
+        assert_eq!(quadratic(0.0, 0.0), Classification::Connected { period: 1 });
+        assert_eq!(quadratic(-1.0, 0.0), Classification::Connected { period: 2 });
+        // the Douady rabbit
+        assert_eq!(quadratic(-0.12, 0.75), Classification::Connected { period: 3 });
+        assert_eq!(quadratic(1.0, 0.0), Classification::Disconnected { steps: 2 });
+        // c = i lands exactly on a repelling 2-cycle: a dendrite
+        assert_eq!(quadratic(0.0, 1.0), Classification::Boundary);
+        // the cusp of the main cardioid is parabolic, and converges far too slowly
+        assert_eq!(quadratic(0.25, 0.0), Classification::Boundary);
+    }
+
+    #[test]
+    fn test_is_connected() {
+        assert!(quadratic(-1.0, 0.0).is_connected());
+        assert!(quadratic(0.0, 1.0).is_connected());
+        assert!(!quadratic(0.5, 0.5).is_connected());
+    }
+}
@@ -37,6 +37,7 @@ pub trait Colorizer: 'static {
         self.colorize_pixel(x, y, pixel).to_rgba()
     }
 
+    #[allow(clippy::type_complexity)]
     fn colorize
         (&self,
          image: &Self::Image)
@@ -53,6 +54,7 @@ pub trait Colorizer: 'static {
         buffer
     }
 
+    #[allow(clippy::type_complexity)]
     fn colorize_alpha
         (&self,
          image: &Self::Image)
@@ -79,17 +81,60 @@ pub trait Colorizer: 'static {
 //
 // Our mapping is a spiral around the outside of the cylinder; S remains
 // constant at 1. Black maps to black at L0; white to white at L1.
-// H values vary such that darks produce deep blues, and lights produce
-// bright yellows. This is a purely aesthetic choice.
+// By default, H values vary such that darks produce deep blues, and lights
+// produce bright yellows. This is a purely aesthetic choice; other palettes
+// are available via `HSLColorizer::from_name`.
 //
 // [HSL]: https://en.wikipedia.org/wiki/HSL_and_HSV
 pub struct HSLColorizer<T> {
+    begin: HSL,
+    end: HSL,
     _image_type: PhantomData<T>,
 }
 
+/// Names of the palettes known to `HSLColorizer::from_name`.
+pub const PALETTES: &[&str] = &["hsl", "fire", "ice", "rainbow"];
+
 impl<T> HSLColorizer<T> {
     pub fn new() -> HSLColorizer<T> {
-        HSLColorizer { _image_type: PhantomData }
+        // start deep under the dark blues, almost violet,
+        // and end just over the region where yellow is becoming orange
+        HSLColorizer::with_palette(HSL {
+                                       h: 310_f64,
+                                       s: 1_f64,
+                                       l: 0_f64,
+                                   },
+                                   HSL {
+                                       h: 30_f64,
+                                       s: 1_f64,
+                                       l: 1_f64,
+                                   })
+    }
+
+    /// Construct a colorizer which spirals from `begin` (for black) to `end` (for white).
+    pub fn with_palette(begin: HSL, end: HSL) -> HSLColorizer<T> {
+        HSLColorizer {
+            begin,
+            end,
+            _image_type: PhantomData,
+        }
+    }
+
+    /// Construct a colorizer for one of the named `PALETTES`.
+    pub fn from_name(name: &str) -> Option<HSLColorizer<T>> {
+        let hsl = |h, l| HSL { h, s: 1_f64, l };
+        match name {
+            "hsl" => Some(HSLColorizer::new()),
+            // deep reds through orange into a white-hot yellow
+            "fire" => Some(HSLColorizer::with_palette(hsl(0_f64, 0_f64), hsl(60_f64, 1_f64))),
+            // navy through cyan to white
+            "ice" => Some(HSLColorizer::with_palette(hsl(240_f64, 0_f64), hsl(180_f64, 1_f64))),
+            // the full hue circle at constant lightness
+            "rainbow" => {
+                Some(HSLColorizer::with_palette(hsl(0_f64, 0.5_f64), hsl(300_f64, 0.5_f64)))
+            }
+            _ => None,
+        }
     }
 
     fn interpolate(&self, begin: f64, end: f64, t: f64) -> f64 {
@@ -108,6 +153,12 @@ impl<T> HSLColorizer<T> {
     }
 }
 
+impl<T> Default for HSLColorizer<T> {
+    fn default() -> HSLColorizer<T> {
+        HSLColorizer::new()
+    }
+}
+
 impl<GI> Colorizer for HSLColorizer<GI>
     where GI: GenericImage + 'static,
           GI::Pixel: Pixel<Subpixel = u8>,
@@ -121,20 +172,6 @@ impl<GI> Colorizer for HSLColorizer<GI>
                       pixel: <<Self as Colorizer>::Image as GenericImage>::Pixel)
                       -> Rgb<<<<Self as Colorizer>::Image as GenericImage>::Pixel
                         as Pixel>::Subpixel> {
-        // start deep under the dark blues, almost violet
-        const BEGIN: HSL = HSL {
-            h: 310_f64,
-            s: 1_f64,
-            l: 0_f64,
-        };
-
-        // end just over the region where yellow is becoming orange
-        const END: HSL = HSL {
-            h: 30_f64,
-            s: 1_f64,
-            l: 1_f64,
-        };
-
         // we're only dealing with black-and-white inputs, here; there will be
         // exactly one channel as we continue.
         let pixel = pixel.to_luma();
@@ -144,8 +181,128 @@ impl<GI> Colorizer for HSLColorizer<GI>
         // type's actual min bound is. This is in case someone backs the pixel
         // with a negatable type for some reason.
         let subpixel = pixel.channels()[0];
-        let t = subpixel as f64 / u8::max_value() as f64;
-        let (r, g, b) = self.interpolate_hsl(BEGIN, END, t).to_rgb();
+        let t = subpixel as f64 / u8::MAX as f64;
+        let (r, g, b) = self.interpolate_hsl(self.begin, self.end, t).to_rgb();
         Rgb([r, g, b])
     }
 }
+
+/// `count` fully saturated colors with evenly spaced hues, starting from red.
+fn spaced_hues(count: usize) -> Vec<Rgb<u8>> {
+    let count = count.max(1);
+    (0..count)
+        .map(|i| {
+            let hsl = HSL {
+                h: 360_f64 * i as f64 / count as f64,
+                s: 1_f64,
+                l: 0.5_f64,
+            };
+            let (r, g, b) = hsl.to_rgb();
+            Rgb([r, g, b])
+        })
+        .collect()
+}
+
+/// Scale every channel of `color` by `shade`, in [0, 255].
+fn darken(color: Rgb<u8>, shade: u8) -> Rgb<u8> {
+    let shade = shade as f64 / u8::MAX as f64;
+    Rgb([(color[0] as f64 * shade).round() as u8,
+         (color[1] as f64 * shade).round() as u8,
+         (color[2] as f64 * shade).round() as u8])
+}
+
+/// Colorizer for Newton's-method renders, which gives the basin of each root its own color.
+///
+/// The input is an image as produced by `Newton::render_image`: luma is one more than the
+/// index of the root a pixel converged to, or 0 if it didn't, and alpha is a shade by
+/// which that root's color is darkened. Pixels which didn't converge are black.
+pub struct RootColorizer<T> {
+    colors: Vec<Rgb<u8>>,
+    _image_type: PhantomData<T>,
+}
+
+impl<T> RootColorizer<T> {
+    /// Construct a colorizer with evenly spaced, fully saturated hues for `roots` roots.
+    pub fn new(roots: usize) -> RootColorizer<T> {
+        RootColorizer::with_colors(spaced_hues(roots))
+    }
+
+    /// Construct a colorizer with one color per root; if there are more roots than
+    /// colors, the colors repeat.
+    pub fn with_colors(colors: Vec<Rgb<u8>>) -> RootColorizer<T> {
+        assert!(!colors.is_empty(), "RootColorizer needs at least one color");
+        RootColorizer {
+            colors,
+            _image_type: PhantomData,
+        }
+    }
+}
+
+impl<GI> Colorizer for RootColorizer<GI>
+    where GI: GenericImage + 'static,
+          GI::Pixel: Pixel<Subpixel = u8>
+{
+    type Image = GI;
+
+    fn colorize_pixel(&self,
+                      _: u32,
+                      _: u32,
+                      pixel: <<Self as Colorizer>::Image as GenericImage>::Pixel)
+                      -> Rgb<<<<Self as Colorizer>::Image as GenericImage>::Pixel
+                        as Pixel>::Subpixel> {
+        let pixel = pixel.to_luma_alpha();
+        let (root, shade) = (pixel.channels()[0], pixel.channels()[1]);
+        if root == 0 {
+            return Rgb([0, 0, 0]);
+        }
+        darken(self.colors[(root as usize - 1) % self.colors.len()], shade)
+    }
+}
+
+/// Colorizer for renders with attractor detection, which colors interior points by the
+/// period of the cycle that caught them.
+///
+/// The input is an image as produced by `attractor::to_image`. Where alpha is 0, the pixel
+/// escaped, and luma is its escape count, colored by the exterior `HSLColorizer` if there
+/// is one and left gray otherwise. Elsewhere alpha is the period and luma a shade by which
+/// that period's color is darkened.
+pub struct InteriorColorizer<T> {
+    exterior: Option<HSLColorizer<T>>,
+    periods: Vec<Rgb<u8>>,
+}
+
+impl<T> InteriorColorizer<T> {
+    /// The number of distinct period colors; longer periods reuse them.
+    const PERIOD_COLORS: usize = 12;
+
+    pub fn new(exterior: Option<HSLColorizer<T>>) -> InteriorColorizer<T> {
+        // step five twelfths of the way round the wheel each time, so nearby periods contrast
+        let hues = spaced_hues(InteriorColorizer::<T>::PERIOD_COLORS);
+        let periods = (0..hues.len()).map(|i| hues[(i * 5) % hues.len()]).collect();
+        InteriorColorizer { exterior, periods }
+    }
+}
+
+impl<GI> Colorizer for InteriorColorizer<GI>
+    where GI: GenericImage + 'static,
+          GI::Pixel: Pixel<Subpixel = u8>
+{
+    type Image = GI;
+
+    fn colorize_pixel(&self,
+                      x: u32,
+                      y: u32,
+                      pixel: <<Self as Colorizer>::Image as GenericImage>::Pixel)
+                      -> Rgb<<<<Self as Colorizer>::Image as GenericImage>::Pixel
+                        as Pixel>::Subpixel> {
+        let channels = pixel.to_luma_alpha();
+        let (luma, period) = (channels.channels()[0], channels.channels()[1]);
+        if period > 0 {
+            return darken(self.periods[(period as usize - 1) % self.periods.len()], luma);
+        }
+        match self.exterior {
+            Some(ref exterior) => exterior.colorize_pixel(x, y, pixel),
+            None => Rgb([luma, luma, luma]),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Vector contours of escape-count images.
+//!
+//! Plotters and laser cutters want outlines, not pixels. This module traces iso-contours
+//! of the escape counts produced by `parallel_image` using [marching squares], optionally
+//! simplifies and smooths them, and writes them out as SVG paths.
+//!
+//! Coordinates are in pixel space: the center of pixel `(x, y)` is the point `(x, y)`.
+//!
+//! [marching squares]: https://en.wikipedia.org/wiki/Marching_squares
+
+use image::{ImageBuffer, Luma, Pixel};
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// A point in pixel space
+pub type Point = (f64, f64);
+
+/// A traced contour line
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contour {
+    pub points: Vec<Point>,
+    /// Whether the last point connects back to the first
+    pub closed: bool,
+}
+
+/// How to trace and post-process contours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContourOptions {
+    /// The number of iso-levels to trace, spaced evenly between the lowest and highest
+    /// values in the image.
+    pub levels: usize,
+    /// Maximum distance in pixels a simplified path may deviate from the traced one.
+    /// Zero disables simplification.
+    pub tolerance: f64,
+    /// Number of rounds of corner-cutting to smooth each path with.
+    pub smoothing: usize,
+}
+
+impl Default for ContourOptions {
+    fn default() -> ContourOptions {
+        ContourOptions {
+            levels: 8,
+            tolerance: 0.5,
+            smoothing: 2,
+        }
+    }
+}
+
+/// An edge of the sampling grid: `(vertical, x, y)` identifies the edge leaving the
+/// sample at `(x, y)` either rightwards or downwards.
+type Edge = (bool, u32, u32);
+
+/// Trace the iso-contours of an escape-count image at the given level.
+///
+/// Samples with values `>= level` are considered inside.
+pub fn trace(image: &ImageBuffer<Luma<u8>, Vec<u8>>, level: f64) -> Vec<Contour> {
+    let (width, height) = image.dimensions();
+    if width < 2 || height < 2 {
+        return Vec::new();
+    }
+    let value = |x: u32, y: u32| image.get_pixel(x, y).channels()[0] as f64;
+
+    // where on an edge the contour crosses it
+    let crossing = |edge: Edge| -> Point {
+        let (vertical, x, y) = edge;
+        let (a, b) = if vertical {
+            (value(x, y), value(x, y + 1))
+        } else {
+            (value(x, y), value(x + 1, y))
+        };
+        let t = if a == b { 0.5 } else { (level - a) / (b - a) };
+        if vertical {
+            (x as f64, y as f64 + t)
+        } else {
+            (x as f64 + t, y as f64)
+        }
+    };
+
+    let mut segments: Vec<(Edge, Edge)> = Vec::new();
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let inside = |x, y| value(x, y) >= level;
+            let case = (inside(x, y) as u8) << 3 | (inside(x + 1, y) as u8) << 2 |
+                       (inside(x + 1, y + 1) as u8) << 1 |
+                       inside(x, y + 1) as u8;
+
+            let top = (false, x, y);
+            let bottom = (false, x, y + 1);
+            let left = (true, x, y);
+            let right = (true, x + 1, y);
+
+            match case {
+                0 | 15 => {}
+                1 | 14 => segments.push((left, bottom)),
+                2 | 13 => segments.push((bottom, right)),
+                3 | 12 => segments.push((left, right)),
+                4 | 11 => segments.push((top, right)),
+                6 | 9 => segments.push((top, bottom)),
+                7 | 8 => segments.push((left, top)),
+                5 | 10 => {
+                    // saddle: resolve using the average of the corners
+                    let center = (value(x, y) + value(x + 1, y) + value(x + 1, y + 1) +
+                                  value(x, y + 1)) / 4.0;
+                    if (center >= level) == (case == 10) {
+                        segments.push((left, top));
+                        segments.push((bottom, right));
+                    } else {
+                        segments.push((left, bottom));
+                        segments.push((top, right));
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // every edge is shared by at most two cells, so each appears in at most two segments
+    let mut adjacent: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (index, &(a, b)) in segments.iter().enumerate() {
+        adjacent.entry(a).or_default().push(index);
+        adjacent.entry(b).or_default().push(index);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut contours = Vec::new();
+
+    // open contours begin at edges with only one segment; trace those first so that
+    // whatever remains is a closed loop
+    let mut starts = adjacent.iter()
+        .filter(|&(_, segs)| segs.len() == 1)
+        .map(|(&edge, segs)| (edge, segs[0]))
+        .collect::<Vec<_>>();
+    starts.sort();
+    let loops = (0..segments.len()).map(|i| (segments[i].0, i)).collect::<Vec<_>>();
+
+    for (start, first) in starts.into_iter().chain(loops) {
+        if used[first] {
+            continue;
+        }
+        let mut edges = vec![start];
+        let mut edge = start;
+        let mut segment = first;
+        let closed = loop {
+            used[segment] = true;
+            let (a, b) = segments[segment];
+            edge = if a == edge { b } else { a };
+            edges.push(edge);
+            match adjacent[&edge].iter().find(|&&s| !used[s]) {
+                Some(&next) => segment = next,
+                None => break edge == start,
+            }
+        };
+        if closed {
+            edges.pop();
+        }
+        contours.push(Contour {
+            points: edges.into_iter().map(&crossing).collect(),
+            closed,
+        });
+    }
+
+    contours
+}
+
+/// Simplify a contour using the Ramer-Douglas-Peucker algorithm.
+pub fn simplify(contour: &Contour, tolerance: f64) -> Contour {
+    fn distance(p: Point, a: Point, b: Point) -> f64 {
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len == 0.0 {
+            ((p.0 - a.0).powi(2) + (p.1 - a.1).powi(2)).sqrt()
+        } else {
+            (dy * p.0 - dx * p.1 + b.0 * a.1 - b.1 * a.0).abs() / len
+        }
+    }
+
+    fn rdp(points: &[Point], tolerance: f64, out: &mut Vec<Point>) {
+        let (first, last) = (points[0], points[points.len() - 1]);
+        let farthest = (1..points.len() - 1)
+            .map(|i| (i, distance(points[i], first, last)))
+            .fold((0, 0.0), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+        if farthest.1 > tolerance {
+            rdp(&points[..farthest.0 + 1], tolerance, out);
+            out.pop();
+            rdp(&points[farthest.0..], tolerance, out);
+        } else {
+            out.push(first);
+            out.push(last);
+        }
+    }
+
+    if contour.points.len() < 3 || tolerance <= 0.0 {
+        return contour.clone();
+    }
+    let mut points = contour.points.clone();
+    if contour.closed {
+        // a closed path has no natural endpoints; anchor it on its first point
+        points.push(points[0]);
+    }
+    let mut out = Vec::with_capacity(points.len());
+    rdp(&points, tolerance, &mut out);
+    if contour.closed {
+        out.pop();
+    }
+    Contour {
+        points: out,
+        closed: contour.closed,
+    }
+}
+
+/// Smooth a contour with Chaikin's corner-cutting algorithm.
+///
+/// The endpoints of open contours are preserved.
+pub fn smooth(contour: &Contour, rounds: usize) -> Contour {
+    let mut points = contour.points.clone();
+    for _ in 0..rounds {
+        if points.len() < 3 {
+            break;
+        }
+        let n = points.len();
+        let pairs = if contour.closed { n } else { n - 1 };
+        let mut next = Vec::with_capacity(2 * n);
+        if !contour.closed {
+            next.push(points[0]);
+        }
+        for i in 0..pairs {
+            let (a, b) = (points[i], points[(i + 1) % n]);
+            next.push((0.75 * a.0 + 0.25 * b.0, 0.75 * a.1 + 0.25 * b.1));
+            next.push((0.25 * a.0 + 0.75 * b.0, 0.25 * a.1 + 0.75 * b.1));
+        }
+        if !contour.closed {
+            next.push(points[n - 1]);
+        }
+        points = next;
+    }
+    Contour {
+        points,
+        closed: contour.closed,
+    }
+}
+
+/// The levels at which `options` will trace contours in `image`.
+pub fn levels(image: &ImageBuffer<Luma<u8>, Vec<u8>>, options: &ContourOptions) -> Vec<f64> {
+    let (low, high) = image.pixels()
+        .map(|p| p.channels()[0])
+        .fold((u8::MAX, 0), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if low >= high {
+        return Vec::new();
+    }
+    (1..options.levels + 1)
+        .map(|i| low as f64 + (high - low) as f64 * i as f64 / (options.levels + 1) as f64)
+        .collect()
+}
+
+/// Trace, simplify and smooth every contour level of `image`, and render them as an SVG
+/// document.
+///
+/// Each level becomes a `<path>` in its own group, stroked in a shade of gray matching
+/// its level.
+pub fn to_svg(image: &ImageBuffer<Luma<u8>, Vec<u8>>, options: &ContourOptions) -> String {
+    let (width, height) = image.dimensions();
+    let mut svg = String::new();
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(svg,
+             r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+             width,
+             height,
+             width - 1,
+             height - 1)
+        .unwrap();
+
+    for level in levels(image, options) {
+        let shade = level.round() as u8;
+        writeln!(svg,
+                 r#"  <g fill="none" stroke="rgb({0},{0},{0})" stroke-width="0.5" data-level="{1}">"#,
+                 shade,
+                 level)
+            .unwrap();
+        let mut data = String::new();
+        for contour in trace(image, level) {
+            let contour = smooth(&simplify(&contour, options.tolerance), options.smoothing);
+            for (index, &(x, y)) in contour.points.iter().enumerate() {
+                let command = if index == 0 { 'M' } else { 'L' };
+                write!(data, "{}{:.2},{:.2} ", command, x, y).unwrap();
+            }
+            if contour.closed {
+                data.push_str("Z ");
+            }
+        }
+        writeln!(svg, r#"    <path d="{}"/>"#, data.trim_end()).unwrap();
+        writeln!(svg, "  </g>").unwrap();
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+/// Write the contours of `image` to an SVG file.
+pub fn save_svg<P: AsRef<Path>>(image: &ImageBuffer<Luma<u8>, Vec<u8>>,
+                                path: P,
+                                options: &ContourOptions)
+                                -> io::Result<()> {
+    File::create(path)?.write_all(to_svg(image, options).as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bright disc on a dark background
+    fn disc() -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(21, 21, |x, y| {
+            let (dx, dy) = (x as f64 - 10.0, y as f64 - 10.0);
+            Luma([if dx * dx + dy * dy < 36.0 { 200 } else { 0 }])
+        })
+    }
+
+    #[test]
+    fn test_disc_is_one_closed_contour() {
+        let contours = trace(&disc(), 100.0);
+        assert_eq!(contours.len(), 1);
+        assert!(contours[0].closed);
+        for &(x, y) in &contours[0].points {
+            let r = ((x - 10.0).powi(2) + (y - 10.0).powi(2)).sqrt();
+            assert!(r > 5.0 && r < 7.0, "point ({}, {}) is off the circle", x, y);
+        }
+    }
+
+    #[test]
+    fn test_edge_crossing_is_open() {
+        // a half-plane: the contour runs from the top border to the bottom
+        let image = ImageBuffer::from_fn(5, 4, |x, _| Luma([if x < 2 { 10 } else { 0 }]));
+        let contours = trace(&image, 5.0);
+        assert_eq!(contours.len(), 1);
+        assert!(!contours[0].closed);
+        assert_eq!(contours[0].points.len(), 4);
+        assert!(contours[0].points.iter().all(|&(x, _)| x == 1.5));
+    }
+
+    #[test]
+    fn test_simplify_straight_line() {
+        let line = Contour {
+            points: (0..10).map(|i| (i as f64, 2.0)).collect(),
+            closed: false,
+        };
+        assert_eq!(simplify(&line, 0.1).points, vec![(0.0, 2.0), (9.0, 2.0)]);
+    }
+
+    #[test]
+    fn test_svg_has_requested_levels() {
+        let options = ContourOptions {
+            levels: 3,
+            ..ContourOptions::default()
+        };
+        assert_eq!(to_svg(&disc(), &options).matches("<path").count(), 3);
+    }
+}
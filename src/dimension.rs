@@ -0,0 +1,209 @@
+//! Estimating the fractal dimension of Julia sets by box counting.
+//!
+//! If a set has dimension `D`, covering it with boxes of edge `ε` takes about `ε^-D` of
+//! them, so `D` is the slope of `ln N(ε)` against `ln(1/ε)`. Here the boxes are pixels: the
+//! Julia set is the boundary of the filled Julia set, so the pixels which cover it are the
+//! bounded pixels of an escape-count render with an escaping neighbor. Rendering at several
+//! resolutions gives `N(ε)` for several `ε`, and a least-squares fit gives the slope, with
+//! a standard error from the scatter about the line.
+//!
+//! Renders only see bounded pixels where the iteration limit outlasts the orbits of their
+//! centers, so the estimate is only as good as the limit is generous. Totally disconnected
+//! sets are especially hard: there's no interior, and a high enough limit leaves nothing
+//! to count at all.
+
+use num::complex::Complex64;
+
+use bailout::Bailout;
+use super::{applications_until_escape, parallel_rows};
+use viewport::Viewport;
+
+/// Two-sided 95% quantiles of Student's t distribution, for 1 to 10 degrees of freedom.
+const T_95: [f64; 10] = [12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228];
+
+/// A dimension estimate, with the box counts it was fitted to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Estimate {
+    pub dimension: f64,
+    /// The standard error of the fitted slope; 0 for a fit to only two resolutions
+    pub standard_error: f64,
+    /// The coefficient of determination of the fit
+    pub r_squared: f64,
+    /// The number of boundary pixels at each resolution
+    pub counts: Vec<(u32, usize)>,
+}
+
+impl Estimate {
+    /// The 95% confidence interval for the dimension.
+    pub fn interval(&self) -> (f64, f64) {
+        let df = self.counts.len().saturating_sub(2);
+        let t = if df == 0 {
+            0.0
+        } else {
+            T_95.get(df - 1).cloned().unwrap_or(1.96)
+        };
+        (self.dimension - t * self.standard_error, self.dimension + t * self.standard_error)
+    }
+}
+
+/// A box-counting dimension estimator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoxCounting {
+    /// The region rendered, which should contain the whole set. It's square, so that
+    /// pixels are too.
+    pub viewport: Viewport,
+    /// The edges of the renders, in pixels
+    pub resolutions: Vec<u32>,
+    pub iterations: usize,
+}
+
+impl BoxCounting {
+    pub const DEFAULT_RESOLUTIONS: &'static [u32] = &[64, 128, 256, 512];
+    pub const DEFAULT_ITERATIONS: usize = 1000;
+
+    pub fn new(viewport: Viewport) -> BoxCounting {
+        BoxCounting {
+            viewport,
+            resolutions: BoxCounting::DEFAULT_RESOLUTIONS.to_vec(),
+            iterations: BoxCounting::DEFAULT_ITERATIONS,
+        }
+    }
+
+    /// Count the boundary pixels of a `resolution * resolution` render, in parallel on
+    /// `threads` threads.
+    pub fn count(&self,
+                 function: &(dyn Fn(Complex64) -> Complex64 + Sync),
+                 bailout: &Bailout,
+                 resolution: u32,
+                 threads: usize)
+                 -> usize {
+        let interpolate = self.viewport.interpolate(resolution, resolution);
+        let bounded = parallel_rows(resolution, resolution, threads, &|x, y| {
+            applications_until_escape(interpolate(x, y), function, bailout, Some(self.iterations)) >=
+            self.iterations
+        });
+
+        let at = |x: u32, y: u32| bounded[(y * resolution + x) as usize];
+        let mut count = 0;
+        for y in 0..resolution {
+            for x in 0..resolution {
+                if !at(x, y) {
+                    continue;
+                }
+                let escaping_neighbor = (x > 0 && !at(x - 1, y)) ||
+                                        (x + 1 < resolution && !at(x + 1, y)) ||
+                                        (y > 0 && !at(x, y - 1)) ||
+                                        (y + 1 < resolution && !at(x, y + 1));
+                if escaping_neighbor {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Estimate the dimension of the Julia set of `function`, rendering in parallel on
+    /// `threads` threads. Resolutions with no boundary pixels are left out of the fit;
+    /// at least two must remain.
+    pub fn estimate(&self,
+                    function: &(dyn Fn(Complex64) -> Complex64 + Sync),
+                    bailout: &Bailout,
+                    threads: usize)
+                    -> Result<Estimate, String> {
+        let counts = self.resolutions
+            .iter()
+            .map(|&resolution| (resolution, self.count(function, bailout, resolution, threads)))
+            .collect::<Vec<_>>();
+        let extent = self.viewport.max_x - self.viewport.min_x;
+        let points = counts.iter()
+            .filter(|&&(_, count)| count > 0)
+            .map(|&(resolution, count)| ((resolution as f64 / extent).ln(), (count as f64).ln()))
+            .collect::<Vec<_>>();
+        let fit = least_squares(&points)
+            .ok_or_else(|| String::from("too few resolutions have any boundary to count"))?;
+        Ok(Estimate {
+            dimension: fit.slope,
+            standard_error: fit.standard_error,
+            r_squared: fit.r_squared,
+            counts,
+        })
+    }
+}
+
+/// A straight line fitted to some points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Fit {
+    slope: f64,
+    standard_error: f64,
+    r_squared: f64,
+}
+
+/// Fit a line to `points` by ordinary least squares, or `None` if there are fewer than two
+/// distinct x values.
+fn least_squares(points: &[(f64, f64)]) -> Option<Fit> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx = points.iter().map(|p| (p.0 - mean_x) * (p.0 - mean_x)).sum::<f64>();
+    let sxy = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum::<f64>();
+    let syy = points.iter().map(|p| (p.1 - mean_y) * (p.1 - mean_y)).sum::<f64>();
+    if points.len() < 2 || sxx <= 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    let residual = (syy - slope * sxy).max(0.0);
+    let standard_error = if points.len() > 2 {
+        (residual / (n - 2.0) / sxx).sqrt()
+    } else {
+        0.0
+    };
+    let r_squared = if syy > 0.0 { 1.0 - residual / syy } else { 1.0 };
+    Some(Fit {
+        slope,
+        standard_error,
+        r_squared,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_squares() {
+        let line = [(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)];
+        let fit = least_squares(&line).unwrap();
+        assert!((fit.slope - 2.0).abs() < 1e-12);
+        assert!(fit.standard_error < 1e-6);
+        assert!((fit.r_squared - 1.0).abs() < 1e-12);
+
+        let noisy = [(0.0, 0.0), (1.0, 1.5), (2.0, 1.5), (3.0, 3.0)];
+        let fit = least_squares(&noisy).unwrap();
+        assert!((fit.slope - 0.9).abs() < 1e-12);
+        assert!(fit.standard_error > 0.0 && fit.r_squared < 1.0);
+
+        assert_eq!(least_squares(&[(1.0, 1.0)]), None);
+        assert_eq!(least_squares(&[(1.0, 1.0), (1.0, 2.0)]), None);
+    }
+
+    #[test]
+    fn test_circle_and_basilica() {
+        let counting = BoxCounting {
+            resolutions: vec![64, 128, 256],
+            iterations: 500,
+            ..BoxCounting::new(Viewport::new(-2.0, 2.0, -2.0, 2.0))
+        };
+        let bailout = Bailout::default();
+
+        // for c = 0 the Julia set is the unit circle
+        let circle = counting.estimate(&|z| z * z, &bailout, 2).unwrap();
+        assert!((circle.dimension - 1.0).abs() < 0.05, "{:?}", circle);
+        let (low, high) = circle.interval();
+        assert!(low <= circle.dimension && circle.dimension <= high);
+
+        // the basilica, c = -1, has dimension about 1.27
+        let basilica = counting.estimate(&|z| z * z - 1.0, &bailout, 2).unwrap();
+        assert!(basilica.dimension > 1.1 && basilica.dimension < 1.45, "{:?}", basilica);
+    }
+}
@@ -0,0 +1,693 @@
+//! A small language for user-defined iteration functions.
+//!
+//! Expressions are written in ordinary infix notation over two complex variables: `z`,
+//! the value being iterated, and `c`, the parameter of the family. For example:
+//!
+//! ```text
+//! z^2 + c
+//! z^3 + c*sin(z)
+//! exp(z) * c
+//! (z^2 + c - 1) / (2z + c - 2)
+//! ```
+//!
+//! Supported syntax:
+//!
+//! - numbers: `2`, `0.5`, `1e-3`, and imaginary numbers: `0.713i`
+//! - constants: `i`, `pi`, `e`
+//! - operators, loosest to tightest: `+ -`, `* /`, unary `-`, `^` (right-associative);
+//!   a number directly followed by a name or parenthesis is multiplied by it: `2z`
+//! - functions: `exp sin cos tan sinh cosh tanh log sqrt conj abs re im`, each of one
+//!   argument, and `pow(a, b)`
+//!
+//! `abs`, `re` and `im` produce real values. They, and `conj`, are not holomorphic; the
+//! language doesn't mind.
+//!
+//! Parsing compiles an expression into a flat sequence of stack operations, folding
+//! constant subexpressions and turning constant integer powers into repeated
+//! multiplication, so that evaluating it in the inner loop of a render is cheap.
+
+use num::complex::Complex64;
+use std::error::Error;
+use std::f64::consts;
+use std::fmt;
+use std::str::FromStr;
+
+/// The deepest an expression's evaluation stack may grow.
+const MAX_DEPTH: usize = 32;
+/// The deepest an expression's tree may grow while parsing, counting parentheses, calls
+/// and chains of operators. Deeper trees would overflow the stack long before their
+/// evaluation stack could be checked.
+const MAX_NESTING: usize = 256;
+
+/// A built-in function of one argument
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Func {
+    Exp,
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Log,
+    Sqrt,
+    Conj,
+    Abs,
+    Re,
+    Im,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Func> {
+        Some(match name {
+            "exp" => Func::Exp,
+            "sin" => Func::Sin,
+            "cos" => Func::Cos,
+            "tan" => Func::Tan,
+            "sinh" => Func::Sinh,
+            "cosh" => Func::Cosh,
+            "tanh" => Func::Tanh,
+            "log" | "ln" => Func::Log,
+            "sqrt" => Func::Sqrt,
+            "conj" => Func::Conj,
+            "abs" => Func::Abs,
+            "re" => Func::Re,
+            "im" => Func::Im,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, v: Complex64) -> Complex64 {
+        match self {
+            Func::Exp => v.exp(),
+            Func::Sin => v.sin(),
+            Func::Cos => v.cos(),
+            Func::Tan => v.tan(),
+            Func::Sinh => v.sinh(),
+            Func::Cosh => v.cosh(),
+            Func::Tanh => v.tanh(),
+            Func::Log => v.ln(),
+            Func::Sqrt => v.sqrt(),
+            Func::Conj => v.conj(),
+            Func::Abs => Complex64::new(v.norm(), 0.0),
+            Func::Re => Complex64::new(v.re, 0.0),
+            Func::Im => Complex64::new(v.im, 0.0),
+        }
+    }
+}
+
+/// A single step of a compiled expression
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Const(Complex64),
+    Z,
+    C,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Pow,
+    PowInt(i32),
+    Call(Func),
+}
+
+/// A parsed expression tree
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Const(Complex64),
+    Z,
+    C,
+    Neg(Box<Node>),
+    Call(Func, Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+}
+
+impl Node {
+    /// Fold constant subtrees into single constants.
+    fn fold(self) -> Node {
+        match self {
+            Node::Neg(a) => {
+                match a.fold() {
+                    Node::Const(v) => Node::Const(-v),
+                    a => Node::Neg(Box::new(a)),
+                }
+            }
+            Node::Call(f, a) => {
+                match a.fold() {
+                    Node::Const(v) => Node::Const(f.apply(v)),
+                    a => Node::Call(f, Box::new(a)),
+                }
+            }
+            Node::Binary(op, a, b) => {
+                match (a.fold(), b.fold()) {
+                    (Node::Const(a), Node::Const(b)) => Node::Const(binary(op, a, b)),
+                    (a, b) => Node::Binary(op, Box::new(a), Box::new(b)),
+                }
+            }
+            leaf => leaf,
+        }
+    }
+
+    /// Emit stack operations for this tree, returning the stack depth it requires.
+    fn compile(&self, ops: &mut Vec<Op>) -> usize {
+        match *self {
+            Node::Const(v) => {
+                ops.push(Op::Const(v));
+                1
+            }
+            Node::Z => {
+                ops.push(Op::Z);
+                1
+            }
+            Node::C => {
+                ops.push(Op::C);
+                1
+            }
+            Node::Neg(ref a) => {
+                let depth = a.compile(ops);
+                ops.push(Op::Neg);
+                depth
+            }
+            Node::Call(f, ref a) => {
+                let depth = a.compile(ops);
+                ops.push(Op::Call(f));
+                depth
+            }
+            Node::Binary(Op::Pow, ref a, ref b) if integer_exponent(b).is_some() => {
+                let depth = a.compile(ops);
+                ops.push(Op::PowInt(integer_exponent(b).unwrap()));
+                depth
+            }
+            Node::Binary(op, ref a, ref b) => {
+                let left = a.compile(ops);
+                let right = b.compile(ops);
+                ops.push(op);
+                left.max(right + 1)
+            }
+        }
+    }
+}
+
+/// If this node is a small constant integer, return it.
+fn integer_exponent(node: &Node) -> Option<i32> {
+    match *node {
+        Node::Const(v) if v.im == 0.0 && v.re.fract() == 0.0 && v.re.abs() <= 64.0 => {
+            Some(v.re as i32)
+        }
+        _ => None,
+    }
+}
+
+fn binary(op: Op, a: Complex64, b: Complex64) -> Complex64 {
+    match op {
+        Op::Add => a + b,
+        Op::Sub => a - b,
+        Op::Mul => a * b,
+        Op::Div => a / b,
+        Op::Pow => pow(a, b),
+        _ => unreachable!(),
+    }
+}
+
+fn pow(a: Complex64, b: Complex64) -> Complex64 {
+    if b.im == 0.0 && b.re.fract() == 0.0 && b.re.abs() <= i32::MAX as f64 {
+        powi(a, b.re as i32)
+    } else if a == Complex64::new(0.0, 0.0) {
+        a
+    } else {
+        a.powc(b)
+    }
+}
+
+/// Raise `base` to an integer power by repeated squaring.
+pub fn powi(base: Complex64, exponent: i32) -> Complex64 {
+    let mut result = Complex64::new(1.0, 0.0);
+    let mut base = if exponent < 0 { base.inv() } else { base };
+    let mut n = exponent.unsigned_abs();
+    while n > 0 {
+        if n & 1 == 1 {
+            result *= base;
+        }
+        base *= base;
+        n >>= 1;
+    }
+    result
+}
+
+/// An error encountered while parsing an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// Byte offset in the source at which the error was found
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Imaginary(f64),
+    Name(String),
+    Symbol(char),
+    End,
+}
+
+/// Split the source into `(position, token)` pairs, ending with `Token::End`.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let ch = bytes[i] as char;
+        let start = i;
+        if ch.is_whitespace() {
+            i += 1;
+        } else if ch.is_ascii_digit() || ch == '.' {
+            while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                i += 1;
+            }
+            // an exponent, but only if it really is one: `2e` is two times e
+            if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+                let mut j = i + 1;
+                if j < bytes.len() && (bytes[j] == b'+' || bytes[j] == b'-') {
+                    j += 1;
+                }
+                if j < bytes.len() && bytes[j].is_ascii_digit() {
+                    i = j;
+                    while i < bytes.len() && bytes[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let value = f64::from_str(&source[start..i]).map_err(|_| {
+                ParseError {
+                    position: start,
+                    message: format!("invalid number '{}'", &source[start..i]),
+                }
+            })?;
+            // a trailing `i` not followed by more of a name makes the number imaginary
+            let imaginary = i < bytes.len() && bytes[i] == b'i' &&
+                            (i + 1 >= bytes.len() || !bytes[i + 1].is_ascii_alphanumeric());
+            if imaginary {
+                i += 1;
+                tokens.push((start, Token::Imaginary(value)));
+            } else {
+                tokens.push((start, Token::Number(value)));
+            }
+        } else if ch.is_ascii_alphabetic() || ch == '_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push((start, Token::Name(source[start..i].to_lowercase())));
+        } else if "+-*/^(),".contains(ch) {
+            i += 1;
+            tokens.push((start, Token::Symbol(ch)));
+        } else {
+            return Err(ParseError {
+                position: start,
+                message: format!("unexpected character '{}'", source[start..].chars().next().unwrap()),
+            });
+        }
+    }
+    tokens.push((source.len(), Token::End));
+    Ok(tokens)
+}
+
+/// A recursive-descent parser over a token list
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    /// How deep the tree being parsed has grown, at most
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].1
+    }
+
+    fn position(&self) -> usize {
+        self.tokens[self.index].0
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].1.clone();
+        if token != Token::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, ParseError> {
+        Err(ParseError {
+            position: self.position(),
+            message: String::from(message),
+        })
+    }
+
+    /// Go a level deeper into the tree, failing if that's too deep.
+    fn descend(&mut self) -> Result<(), ParseError> {
+        if self.depth >= MAX_NESTING {
+            return self.error("expression is too deeply nested");
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ParseError> {
+        if *self.peek() == Token::Symbol(symbol) {
+            self.next();
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", symbol))
+        }
+    }
+
+    /// expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut node = self.term()?;
+        loop {
+            let op = match *self.peek() {
+                Token::Symbol('+') => Op::Add,
+                Token::Symbol('-') => Op::Sub,
+                _ => {
+                    self.depth = depth;
+                    return Ok(node);
+                }
+            };
+            // each operator in a chain nests the ones before it a level deeper
+            self.descend()?;
+            self.next();
+            node = Node::Binary(op, Box::new(node), Box::new(self.term()?));
+        }
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut node = self.unary()?;
+        loop {
+            let op = match *self.peek() {
+                Token::Symbol('*') => Op::Mul,
+                Token::Symbol('/') => Op::Div,
+                _ => {
+                    self.depth = depth;
+                    return Ok(node);
+                }
+            };
+            self.descend()?;
+            self.next();
+            node = Node::Binary(op, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    /// unary := ('-' | '+') unary | power
+    ///
+    /// Every way of nesting an expression passes through here, so this is where the depth
+    /// of the tree is kept in check.
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        self.descend()?;
+        let node = match *self.peek() {
+            Token::Symbol('-') => {
+                self.next();
+                Node::Neg(Box::new(self.unary()?))
+            }
+            Token::Symbol('+') => {
+                self.next();
+                self.unary()?
+            }
+            _ => self.power()?,
+        };
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    /// power := implicit ('^' unary)?
+    fn power(&mut self) -> Result<Node, ParseError> {
+        let base = self.implicit()?;
+        if *self.peek() == Token::Symbol('^') {
+            self.next();
+            Ok(Node::Binary(Op::Pow, Box::new(base), Box::new(self.unary()?)))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// implicit := number (name | '(' ...)  | atom
+    ///
+    /// A number directly followed by a name or a parenthesis multiplies it, binding
+    /// tighter than `^` binds the right-hand side: `2z^2` is `2 * z^2`.
+    fn implicit(&mut self) -> Result<Node, ParseError> {
+        let atom = self.atom()?;
+        let is_number = matches!(self.tokens[self.index - 1].1,
+                                 Token::Number(_) | Token::Imaginary(_));
+        match *self.peek() {
+            Token::Name(_) | Token::Symbol('(') if is_number => {
+                Ok(Node::Binary(Op::Mul, Box::new(atom), Box::new(self.power()?)))
+            }
+            _ => Ok(atom),
+        }
+    }
+
+    /// atom := number | imaginary | name | name '(' arguments ')' | '(' expression ')'
+    fn atom(&mut self) -> Result<Node, ParseError> {
+        let position = self.position();
+        match self.next() {
+            Token::Number(v) => Ok(Node::Const(Complex64::new(v, 0.0))),
+            Token::Imaginary(v) => Ok(Node::Const(Complex64::new(0.0, v))),
+            Token::Symbol('(') => {
+                let node = self.expression()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Token::Name(name) => {
+                if *self.peek() == Token::Symbol('(') {
+                    self.next();
+                    let node = if name == "pow" {
+                        let base = self.expression()?;
+                        self.expect(',')?;
+                        let exponent = self.expression()?;
+                        Node::Binary(Op::Pow, Box::new(base), Box::new(exponent))
+                    } else {
+                        match Func::from_name(&name) {
+                            Some(f) => Node::Call(f, Box::new(self.expression()?)),
+                            None => {
+                                return Err(ParseError {
+                                    position,
+                                    message: format!("unknown function '{}'", name),
+                                })
+                            }
+                        }
+                    };
+                    self.expect(')')?;
+                    return Ok(node);
+                }
+                match name.as_str() {
+                    "z" => Ok(Node::Z),
+                    "c" => Ok(Node::C),
+                    "i" => Ok(Node::Const(Complex64::new(0.0, 1.0))),
+                    "pi" => Ok(Node::Const(Complex64::new(consts::PI, 0.0))),
+                    "e" => Ok(Node::Const(Complex64::new(consts::E, 0.0))),
+                    _ => {
+                        Err(ParseError {
+                            position,
+                            message: format!("unknown name '{}'", name),
+                        })
+                    }
+                }
+            }
+            Token::End => {
+                Err(ParseError {
+                    position,
+                    message: String::from("unexpected end of expression"),
+                })
+            }
+            Token::Symbol(ch) => {
+                Err(ParseError {
+                    position,
+                    message: format!("unexpected '{}'", ch),
+                })
+            }
+        }
+    }
+}
+
+/// A compiled iteration function of `z` and `c`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    source: String,
+    ops: Vec<Op>,
+}
+
+impl Expression {
+    /// Parse and compile an expression.
+    pub fn parse(source: &str) -> Result<Expression, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            index: 0,
+            depth: 0,
+        };
+        let node = parser.expression()?;
+        if *parser.peek() != Token::End {
+            return parser.error("unexpected trailing input");
+        }
+
+        let mut ops = Vec::new();
+        if node.fold().compile(&mut ops) > MAX_DEPTH {
+            return Err(ParseError {
+                position: 0,
+                message: String::from("expression is too deeply nested"),
+            });
+        }
+        Ok(Expression {
+            source: String::from(source.trim()),
+            ops,
+        })
+    }
+
+    /// The source text this expression was parsed from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// If this expression doesn't depend on `z` or `c`, its value.
+    pub fn constant(&self) -> Option<Complex64> {
+        match self.ops[..] {
+            [Op::Const(v)] => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Whether this expression refers to `c` at all.
+    pub fn uses_c(&self) -> bool {
+        self.ops.contains(&Op::C)
+    }
+
+    /// Evaluate this expression for the given `z` and `c`.
+    pub fn eval(&self, z: Complex64, c: Complex64) -> Complex64 {
+        let mut stack = [Complex64::new(0.0, 0.0); MAX_DEPTH];
+        let mut top = 0;
+        for op in &self.ops {
+            match *op {
+                Op::Const(v) => {
+                    stack[top] = v;
+                    top += 1;
+                }
+                Op::Z => {
+                    stack[top] = z;
+                    top += 1;
+                }
+                Op::C => {
+                    stack[top] = c;
+                    top += 1;
+                }
+                Op::Neg => stack[top - 1] = -stack[top - 1],
+                Op::PowInt(n) => stack[top - 1] = powi(stack[top - 1], n),
+                Op::Call(f) => stack[top - 1] = f.apply(stack[top - 1]),
+                op => {
+                    top -= 1;
+                    stack[top - 1] = binary(op, stack[top - 1], stack[top]);
+                }
+            }
+        }
+        stack[0]
+    }
+
+    /// Bind `c`, producing a function of `z` alone suitable for `applications_until`
+    /// and the renderers.
+    pub fn bind(&self, c: Complex64) -> impl Fn(Complex64) -> Complex64 + Send + Sync + 'static {
+        let expression = self.clone();
+        move |z| expression.eval(z, c)
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Expression, ParseError> {
+        Expression::parse(s)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{applications_until, default_julia};
+
+    fn eval(source: &str, z: Complex64, c: Complex64) -> Complex64 {
+        Expression::parse(source).unwrap().eval(z, c)
+    }
+
+    fn close(a: Complex64, b: Complex64) -> bool {
+        (a - b).norm() < 1e-12
+    }
+
+    #[test]
+    fn test_quadratic_matches_default_julia() {
+        let f = Expression::parse("z^2 - 0.221 - 0.713i").unwrap().bind(Complex64::new(0.0, 0.0));
+        for &(re, im) in &[(0.0, 0.0), (-1.0, 1.0), (0.0, 1.0), (1.0, -1.0)] {
+            let z = Complex64::new(re, im);
+            assert_eq!(applications_until(z, &f, 2.0, Some(256)),
+                       applications_until(z, &default_julia, 2.0, Some(256)));
+        }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let z = Complex64::new(0.5, -0.25);
+        let c = Complex64::new(-0.1, 0.3);
+        assert!(close(eval("z^3 + c*sin(z)", z, c), z * z * z + c * z.sin()));
+        assert!(close(eval("-z^2", z, c), -(z * z)));
+        assert!(close(eval("2^3^2", z, c), Complex64::new(512.0, 0.0)));
+        assert!(close(eval("2z^2 + c", z, c), z * z * 2.0 + c));
+        assert!(close(eval("(z + 1) / (z - 1)", z, c), (z + 1.0) / (z - 1.0)));
+        assert!(close(eval("pow(z, 0.5)", z, c), z.powf(0.5)));
+        assert!(close(eval("conj(z) + abs(c)", z, c), z.conj() + c.norm()));
+        assert!(close(eval("2e-1 + e", z, c), Complex64::new(0.2 + consts::E, 0.0)));
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let expr = Expression::parse("z^2 + (1 + 2) * exp(0)").unwrap();
+        assert_eq!(expr.ops,
+                   vec![Op::Z, Op::PowInt(2), Op::Const(Complex64::new(3.0, 0.0)), Op::Add]);
+        assert!(!expr.uses_c());
+        assert_eq!(expr.constant(), None);
+        assert_eq!(Expression::parse("-0.5 + sqrt(3)/2 * i").map(|e| e.constant()),
+                   Ok(Some(Complex64::new(-0.5, 3f64.sqrt() / 2.0))));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Expression::parse("z^2 + q").unwrap_err().position, 6);
+        assert!(Expression::parse("foo(z)").is_err());
+        assert!(Expression::parse("z +").is_err());
+        assert!(Expression::parse("(z").is_err());
+        assert!(Expression::parse("z z").is_err());
+        assert!(Expression::parse("z $ 2").is_err());
+        assert!(Expression::parse("z + é").is_err());
+
+        let nested = |n| format!("{}z{}", "(".repeat(n), ")".repeat(n));
+        assert!(Expression::parse(&nested(100)).is_ok());
+        assert!(Expression::parse(&nested(10_000)).is_err());
+        assert!(Expression::parse(&vec!["z"; 100].join("+")).is_ok());
+        assert!(Expression::parse(&vec!["z"; 10_000].join("*")).is_err());
+        assert!(Expression::parse(&"-".repeat(10_000)).is_err());
+        assert!(Expression::parse(&vec!["z"; 10_000].join("^")).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Families of iteration functions, parameterized by `c`.
+//!
+//! Besides arbitrary expressions and the multibrots, this provides the classic
+//! transcendental families `c·exp(z)`, `c·sin(z)` and `c·cos(z)`, each of which comes with
+//! the escape criterion appropriate to it, and the popular non-holomorphic variants of the
+//! quadratic family, which fold the plane with absolute values or reflect it by
+//! conjugation before squaring. Writing `z = x + iy`:
+//!
+//! - Burning Ship: `(|x| + i|y|)^2 + c`
+//! - Tricorn, or Mandelbar: `conj(z)^2 + c`
+//! - Celtic: `|Re(z^2)| + i Im(z^2) + c`
+//! - Buffalo: `|Re(z^2)| + i |Im(z^2)| + c`
+//!
+//! These are best known for their parameter planes, but their Julia sets are just as
+//! renderable; see `Plane`.
+
+use num::complex::Complex64;
+use std::f64::consts;
+use std::fmt;
+use std::str::FromStr;
+
+use bailout::Bailout;
+use expr::Expression;
+use multibrot::Multibrot;
+
+/// A family of functions `f_c(z)` whose Julia sets can be rendered.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Family {
+    /// A user-defined expression of `z` and `c`
+    Expression(Expression),
+    /// `z^d + c`
+    Multibrot(Multibrot),
+    /// `c·exp(z)`, whose orbits escape through the positive real part
+    Exponential,
+    /// `c·sin(z)`, whose orbits escape through the imaginary part
+    Sine,
+    /// `c·cos(z)`, whose orbits escape through the imaginary part
+    Cosine,
+    /// `(|x| + i|y|)^2 + c`
+    BurningShip,
+    /// `conj(z)^2 + c`
+    Tricorn,
+    /// `|Re(z^2)| + i Im(z^2) + c`
+    Celtic,
+    /// `|Re(z^2)| + i |Im(z^2)| + c`
+    Buffalo,
+}
+
+impl Family {
+    /// The names of the built-in transcendental families, as accepted by `from_str`.
+    pub const TRANSCENDENTAL: &'static [&'static str] = &["exp", "sin", "cos"];
+
+    /// The names of the built-in non-holomorphic families, as accepted by `from_str`.
+    pub const NON_ANALYTIC: &'static [&'static str] = &["burning-ship",
+                                                        "tricorn",
+                                                        "celtic",
+                                                        "buffalo"];
+
+    /// Beyond this, `exp` and friends overflow within an iteration or two anyway.
+    const TRANSCENDENTAL_RADIUS: f64 = 50.0;
+
+    /// The function for a particular `c`.
+    pub fn bind(&self, c: Complex64) -> Box<dyn Fn(Complex64) -> Complex64 + Send + Sync> {
+        match *self {
+            Family::Expression(ref expression) => Box::new(expression.bind(c)),
+            Family::Multibrot(multibrot) => multibrot.bind(c),
+            Family::Exponential => Box::new(move |z: Complex64| c * z.exp()),
+            Family::Sine => Box::new(move |z: Complex64| c * z.sin()),
+            Family::Cosine => Box::new(move |z: Complex64| c * z.cos()),
+            Family::BurningShip | Family::Tricorn | Family::Celtic | Family::Buffalo => {
+                let family = self.clone();
+                Box::new(move |z| family.eval(z, c))
+            }
+        }
+    }
+
+    /// Apply the function for `c` to `z`.
+    ///
+    /// When `c` is fixed, `bind` is usually faster.
+    pub fn eval(&self, z: Complex64, c: Complex64) -> Complex64 {
+        match *self {
+            Family::Expression(ref expression) => expression.eval(z, c),
+            Family::Multibrot(multibrot) => multibrot.eval(z, c),
+            Family::Exponential => c * z.exp(),
+            Family::Sine => c * z.sin(),
+            Family::Cosine => c * z.cos(),
+            Family::BurningShip => {
+                let folded = Complex64::new(z.re.abs(), z.im.abs());
+                folded * folded + c
+            }
+            Family::Tricorn => {
+                let conjugate = z.conj();
+                conjugate * conjugate + c
+            }
+            Family::Celtic => {
+                let square = z * z;
+                Complex64::new(square.re.abs(), square.im) + c
+            }
+            Family::Buffalo => {
+                let square = z * z;
+                Complex64::new(square.re.abs(), square.im.abs()) + c
+            }
+        }
+    }
+
+    /// The derivative of the function for `c` with respect to `z`, or `None` for the
+    /// non-holomorphic families, which don't have one.
+    ///
+    /// Arbitrary expressions are differentiated numerically, by central differences.
+    pub fn derivative(&self, z: Complex64, c: Complex64) -> Option<Complex64> {
+        match *self {
+            Family::Expression(ref expression) => {
+                let h = 1e-6 * z.norm().max(1.0);
+                Some((expression.eval(z + h, c) - expression.eval(z - h, c)) / (2.0 * h))
+            }
+            Family::Multibrot(multibrot) => Some(multibrot.derivative(z)),
+            Family::Exponential => Some(c * z.exp()),
+            Family::Sine => Some(c * z.cos()),
+            Family::Cosine => Some(-c * z.sin()),
+            Family::BurningShip | Family::Tricorn | Family::Celtic | Family::Buffalo => None,
+        }
+    }
+
+    /// The starting value for the orbits of the parameter plane.
+    ///
+    /// This is a critical point of the function, whose orbit determines whether the
+    /// corresponding Julia set is connected. `exp` has no critical points, so it starts
+    /// from its asymptotic value, 0, instead; arbitrary expressions also start from 0.
+    pub fn critical_point(&self) -> Complex64 {
+        match *self {
+            Family::Sine => Complex64::new(consts::FRAC_PI_2, 0.0),
+            _ => Complex64::new(0.0, 0.0),
+        }
+    }
+
+    /// This family as an expression of `z` and `c`, for recording in render parameters.
+    pub fn expression(&self) -> Expression {
+        match *self {
+            Family::Expression(ref expression) => expression.clone(),
+            Family::Multibrot(multibrot) => multibrot.expression(),
+            Family::Exponential => Expression::parse("c*exp(z)").unwrap(),
+            Family::Sine => Expression::parse("c*sin(z)").unwrap(),
+            Family::Cosine => Expression::parse("c*cos(z)").unwrap(),
+            Family::BurningShip => Expression::parse("(abs(re(z)) + i*abs(im(z)))^2 + c").unwrap(),
+            Family::Tricorn => Expression::parse("conj(z)^2 + c").unwrap(),
+            Family::Celtic => Expression::parse("abs(re(z^2)) + i*im(z^2) + c").unwrap(),
+            Family::Buffalo => Expression::parse("abs(re(z^2)) + i*abs(im(z^2)) + c").unwrap(),
+        }
+    }
+
+    /// A suitable escape criterion for a particular `c`.
+    ///
+    /// Nothing is known about arbitrary expressions, so they get the quadratic family's
+    /// modulus of 2.
+    pub fn bailout(&self, c: Complex64) -> Bailout {
+        match *self {
+            Family::Expression(_) |
+            Family::BurningShip |
+            Family::Tricorn |
+            Family::Celtic |
+            Family::Buffalo => Bailout::default(),
+            Family::Multibrot(multibrot) => Bailout::Modulus(multibrot.escape_radius(c)),
+            Family::Exponential => Bailout::RealPositive(Family::TRANSCENDENTAL_RADIUS),
+            Family::Sine | Family::Cosine => Bailout::Imaginary(Family::TRANSCENDENTAL_RADIUS),
+        }
+    }
+}
+
+impl Default for Family {
+    fn default() -> Family {
+        Family::Multibrot(Multibrot::new(2.0).unwrap())
+    }
+}
+
+impl FromStr for Family {
+    type Err = String;
+
+    /// Parse a family: a bare number `d` means the multibrot `z^d + c`, one of
+    /// `TRANSCENDENTAL` or `NON_ANALYTIC` means that family, and anything else is an
+    /// expression. The quadratic family is recognized when it's written out as `z^2 + c`,
+    /// so that it's treated the same however it's given.
+    fn from_str(s: &str) -> Result<Family, String> {
+        let name = s.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+        match name.as_str() {
+            "exp" => return Ok(Family::Exponential),
+            "sin" => return Ok(Family::Sine),
+            "cos" => return Ok(Family::Cosine),
+            "burning-ship" | "burningship" | "burning_ship" => return Ok(Family::BurningShip),
+            "tricorn" | "mandelbar" => return Ok(Family::Tricorn),
+            "celtic" => return Ok(Family::Celtic),
+            "buffalo" => return Ok(Family::Buffalo),
+            "z^2+c" => return Ok(Family::default()),
+            _ => {}
+        }
+        match f64::from_str(s.trim()) {
+            Ok(degree) => Multibrot::new(degree).map(Family::Multibrot),
+            Err(_) => Expression::parse(s).map(Family::Expression).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expression())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(Family::from_str("3"), Ok(Family::Multibrot(Multibrot::new(3.0).unwrap())));
+        assert!(Family::from_str("1").is_err());
+        match Family::from_str("z^3 + c*sin(z)") {
+            Ok(Family::Expression(expression)) => assert_eq!(expression.source(), "z^3 + c*sin(z)"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(Family::from_str("z^").is_err());
+        assert_eq!(Family::default().to_string(), "z^2 + c");
+        assert_eq!(Family::from_str(&Family::default().to_string()), Ok(Family::default()));
+        assert_eq!(Family::from_str(" z ^ 2+c"), Ok(Family::default()));
+        assert_eq!(Family::from_str("exp"), Ok(Family::Exponential));
+    }
+
+    #[test]
+    fn test_builtins_agree_with_expressions() {
+        let c = Complex64::new(0.3, -0.5);
+        for family in &[Family::Exponential,
+                        Family::Sine,
+                        Family::Cosine,
+                        Family::BurningShip,
+                        Family::Tricorn,
+                        Family::Celtic,
+                        Family::Buffalo] {
+            for &z in &[Complex64::new(0.4, 0.7), Complex64::new(-0.9, -0.2)] {
+                let direct = family.bind(c)(z);
+                let parsed = family.expression().eval(z, c);
+                assert!((direct - parsed).norm() < 1e-12, "{}", family);
+                assert_eq!(family.eval(z, c), direct);
+            }
+        }
+        assert_eq!(Family::from_str("Burning-Ship"), Ok(Family::BurningShip));
+        assert_eq!(Family::from_str("mandelbar"), Ok(Family::Tricorn));
+    }
+
+    #[test]
+    fn test_derivative() {
+        let c = Complex64::new(0.3, -0.5);
+        let z = Complex64::new(0.4, 0.7);
+        let h = 1e-6;
+        for family in &[Family::default(),
+                        Family::from_str("2.5").unwrap(),
+                        Family::from_str("z^3 + c*sin(z)").unwrap(),
+                        Family::Exponential,
+                        Family::Sine,
+                        Family::Cosine] {
+            let numeric = (family.eval(z + h, c) - family.eval(z - h, c)) / (2.0 * h);
+            let derivative = family.derivative(z, c).unwrap();
+            assert!((derivative - numeric).norm() < 1e-6, "{}", family);
+        }
+        assert_eq!(Family::Tricorn.derivative(z, c), None);
+    }
+
+    #[test]
+    fn test_burning_ship_folds() {
+        // folding into the first quadrant makes every quadrant behave alike
+        let c = Complex64::new(-1.7, -0.03);
+        for &z in &[Complex64::new(0.3, 0.2), Complex64::new(-0.3, -0.2)] {
+            assert_eq!(Family::BurningShip.eval(z, c),
+                       Family::BurningShip.eval(Complex64::new(0.3, 0.2), c));
+        }
+    }
+
+    #[test]
+    fn test_exponential_escapes_through_real_part() {
+        // for 0 < c < 1/e, the left half-plane lies in the basin of an attracting fixed
+        // point, while points far to the right escape
+        let family = Family::Exponential;
+        let c = Complex64::new(0.3, 0.0);
+        let f = family.bind(c);
+        let bailout = family.bailout(c);
+        let count = |z| ::applications_until_escape(z, &*f, &bailout, Some(100));
+        assert_eq!(count(Complex64::new(-5.0, 40.0)), 100);
+        assert!(count(Complex64::new(5.0, 0.0)) < 5);
+        // far to the left, exp(z) vanishes and the orbit falls into the basin too
+        assert_eq!(count(Complex64::new(-60.0, 0.5)), 100);
+    }
+}
@@ -0,0 +1,337 @@
+//! A deterministic, recolorable intermediate render format.
+//!
+//! Colorizing an image is cheap; computing the escape values behind it is not. An
+//! `EscapeField` keeps the per-pixel escape values of a render at full floating-point
+//! precision together with a header describing how they were produced, so that the same
+//! render can be saved once and re-colored as often as desired.
+//!
+//! The on-disk format is simple and stable: every value is little-endian and every float
+//! is stored by its exact bit pattern, so a field written on one machine reads back
+//! identically on any other.
+//!
+//! ```text
+//! magic       b"JSEF"
+//! version     u8
+//! width       u32
+//! height      u32
+//! viewport    4 * f64 (min_x, max_x, min_y, max_y)
+//! bailout     u8 kind (0 modulus, 1 real, 2 imaginary, 3 manhattan, 4 right), then
+//!             f64 radius
+//! iterations  u64
+//! function    u32 length, then that many bytes of UTF-8
+//! data        width * height * f64, row-major
+//! ```
+
+use image::{ImageBuffer, Luma};
+use num::complex::Complex64;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use bailout::Bailout;
+use metadata::{key, software, TextChunks};
+use stats::RenderStats;
+use super::{parallel_rows, smooth_applications_until_escape};
+use viewport::Viewport;
+
+const MAGIC: &[u8; 4] = b"JSEF";
+const VERSION: u8 = 1;
+/// The longest function description read back, in bytes
+const MAX_FUNCTION_LEN: usize = 64 * 1024;
+/// The most values to allocate room for ahead of reading them, since a corrupt header can
+/// claim any size
+const MAX_PREALLOCATED: usize = 1 << 20;
+
+/// Everything needed to describe how an `EscapeField` was produced.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldHeader {
+    pub width: u32,
+    pub height: u32,
+    /// The exact viewport used; any aspect-ratio correction has already been applied.
+    pub viewport: Viewport,
+    /// A human-readable description of the iterated function.
+    pub function: String,
+    /// The escape criterion; custom criteria can't be saved.
+    pub bailout: Bailout,
+    /// The maximum number of iterations computed per pixel.
+    pub iterations: usize,
+}
+
+impl FieldHeader {
+    /// The render mode recorded for images of a field's values. They hold smooth escape
+    /// values rather than escape counts, so they aren't escape-time renders.
+    pub const MODE: &'static str = "field";
+
+    /// Represent this header as PNG text chunks, for images of the field's values.
+    pub fn to_text(&self) -> TextChunks {
+        let vp = &self.viewport;
+        vec![software(),
+             (key("mode"), String::from(FieldHeader::MODE)),
+             (key("function"), self.function.clone()),
+             (key("size"), format!("{},{}", self.width, self.height)),
+             (key("viewport"), format!("{},{},{},{}", vp.min_x, vp.max_x, vp.min_y, vp.max_y)),
+             (key("bailout"), self.bailout.to_string()),
+             (key("iterations"), self.iterations.to_string())]
+    }
+}
+
+/// Per-pixel continuous escape values for a render, plus the header describing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EscapeField {
+    pub header: FieldHeader,
+    /// Row-major escape values, as produced by `smooth_applications_until_escape`.
+    pub data: Vec<f64>,
+}
+
+impl EscapeField {
+    /// Compute a new escape field in parallel on `threads` threads.
+    ///
+    /// `function` must be the function described by `header.function`; there is no way
+    /// to check this.
+    pub fn render(header: FieldHeader,
+                  function: &(dyn Fn(Complex64) -> Complex64 + Sync),
+                  threads: usize)
+                  -> EscapeField {
+        let data = {
+            let interpolate = header.viewport.interpolate(header.width, header.height);
+            parallel_rows(header.width, header.height, threads, &|x, y| {
+                smooth_applications_until_escape(interpolate(x, y),
+                                                 function,
+                                                 &header.bailout,
+                                                 Some(header.iterations))
+            })
+        };
+        EscapeField {
+            header,
+            data,
+        }
+    }
+
+    /// The escape value at the given pixel coordinate.
+    pub fn get(&self, x: u32, y: u32) -> f64 {
+        self.data[(y * self.header.width + x) as usize]
+    }
+
+    /// Map this field onto a grayscale image which any `Colorizer` can consume.
+    ///
+    /// The iteration limit maps onto full brightness. With an iteration limit of 255,
+    /// this produces exactly the image `parallel_image` would have.
+    pub fn to_image(&self) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let scale = u8::MAX as f64 / self.header.iterations.max(1) as f64;
+        ImageBuffer::from_fn(self.header.width, self.header.height, |x, y| {
+            Luma([(self.get(x, y) * scale).floor().clamp(0.0, u8::MAX as f64) as u8])
+        })
+    }
+
+    /// Gather statistics from the escape values. The integer part of each is its escape
+    /// count; fields don't record how long they took.
+    pub fn stats(&self) -> RenderStats {
+        let counts = self.data.iter().map(|&value| value.max(0.0) as usize).collect::<Vec<_>>();
+        RenderStats::from_counts(self.header.width,
+                                 self.header.height,
+                                 self.header.iterations.max(1),
+                                 &counts)
+    }
+
+    /// Serialize this field.
+    ///
+    /// Fails if the field was rendered with a custom bailout.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let header = &self.header;
+        let (kind, radius) = match header.bailout {
+            Bailout::Modulus(r) => (0, r),
+            Bailout::Real(r) => (1, r),
+            Bailout::Imaginary(r) => (2, r),
+            Bailout::Manhattan(r) => (3, r),
+            Bailout::RealPositive(r) => (4, r),
+            Bailout::Custom(_) => return Err(invalid_data("can't save a custom bailout")),
+        };
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&header.width.to_le_bytes())?;
+        writer.write_all(&header.height.to_le_bytes())?;
+        for value in &[header.viewport.min_x,
+                       header.viewport.max_x,
+                       header.viewport.min_y,
+                       header.viewport.max_y] {
+            write_f64(writer, *value)?;
+        }
+        writer.write_all(&[kind])?;
+        write_f64(writer, radius)?;
+        writer.write_all(&(header.iterations as u64).to_le_bytes())?;
+        writer.write_all(&(header.function.len() as u32).to_le_bytes())?;
+        writer.write_all(header.function.as_bytes())?;
+        for value in &self.data {
+            write_f64(writer, *value)?;
+        }
+        Ok(())
+    }
+
+    /// Deserialize a field previously written by `write`.
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<EscapeField> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not an escape field: bad magic bytes"));
+        }
+        let mut version = [0; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != VERSION {
+            return Err(invalid_data(&format!("unsupported escape field version {}", version[0])));
+        }
+
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let viewport = Viewport::new(read_f64(reader)?,
+                                     read_f64(reader)?,
+                                     read_f64(reader)?,
+                                     read_f64(reader)?);
+        let bailout = {
+            let mut kind = [0; 1];
+            reader.read_exact(&mut kind)?;
+            let radius = read_f64(reader)?;
+            match kind[0] {
+                0 => Bailout::Modulus(radius),
+                1 => Bailout::Real(radius),
+                2 => Bailout::Imaginary(radius),
+                3 => Bailout::Manhattan(radius),
+                4 => Bailout::RealPositive(radius),
+                k => return Err(invalid_data(&format!("unknown bailout kind {}", k))),
+            }
+        };
+        let iterations = read_u64(reader)? as usize;
+        let function = {
+            let len = read_u32(reader)? as usize;
+            if len > MAX_FUNCTION_LEN {
+                return Err(invalid_data(&format!("function is too long: {} bytes", len)));
+            }
+            let mut bytes = vec![0; len];
+            reader.read_exact(&mut bytes)?;
+            String::from_utf8(bytes).map_err(|_| invalid_data("function is not valid UTF-8"))?
+        };
+
+        let len = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or_else(|| invalid_data(&format!("field is too large: {}x{}", width, height)))?;
+        // a truncated field runs out of data long before it runs out of memory
+        let mut data = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+        for _ in 0..len {
+            data.push(read_f64(reader)?);
+        }
+
+        Ok(EscapeField {
+            header: FieldHeader {
+                width,
+                height,
+                viewport,
+                function,
+                bailout,
+                iterations,
+            },
+            data,
+        })
+    }
+
+    /// Write this field to a file at the given path.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Read a field from a file at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<EscapeField> {
+        EscapeField::read(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_f64<W: Write>(writer: &mut W, value: f64) -> io::Result<()> {
+    writer.write_all(&value.to_bits().to_le_bytes())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    read_u64(reader).map(f64::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{default_julia, parallel_image, DEFAULT_THREADS};
+
+    fn header(iterations: usize) -> FieldHeader {
+        FieldHeader {
+            width: 60,
+            height: 40,
+            viewport: Viewport::default().rectilinear(60, 40),
+            function: "z^2 - 0.221 - 0.713i".to_string(),
+            bailout: Bailout::Modulus(2.0),
+            iterations,
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let field = EscapeField::render(header(100), &default_julia, DEFAULT_THREADS);
+        let mut bytes = Vec::new();
+        field.write(&mut bytes).unwrap();
+        let read = EscapeField::read(&mut &bytes[..]).unwrap();
+        assert_eq!(field, read);
+
+        let mut custom = field.clone();
+        custom.header.bailout = Bailout::custom(|z| z.re > 1.0);
+        assert!(custom.write(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_image_matches_parallel_image() {
+        let header = header(255);
+        let field = EscapeField::render(header.clone(), &default_julia, DEFAULT_THREADS);
+        let interpolate = header.viewport.interpolate(header.width, header.height);
+        let image = parallel_image(header.width,
+                                   header.height,
+                                   &default_julia,
+                                   &*interpolate,
+                                   2.0);
+        assert!(field.to_image().pixels().zip(image.pixels()).all(|(f, i)| f == i));
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        assert!(EscapeField::read(&mut &b"PNG\x00\x01"[..]).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_sizes() {
+        let field = EscapeField::render(header(10), &default_julia, DEFAULT_THREADS);
+        let mut bytes = Vec::new();
+        field.write(&mut bytes).unwrap();
+        let function_len = 5 + 4 + 4 + 4 * 8 + 1 + 8 + 8;
+
+        let mut huge = bytes.clone();
+        huge[5..13].copy_from_slice(&[0xff; 8]);
+        assert!(EscapeField::read(&mut &huge[..]).is_err());
+
+        let mut long = bytes.clone();
+        long[function_len..function_len + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(EscapeField::read(&mut &long[..]).is_err());
+
+        bytes.truncate(bytes.len() - 8);
+        assert!(EscapeField::read(&mut &bytes[..]).is_err());
+    }
+}
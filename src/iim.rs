@@ -0,0 +1,244 @@
+//! Julia sets of `z^2 + c` by inverse iteration.
+//!
+//! Escape-time rendering finds the Julia set as the boundary between points which escape
+//! and points which don't. When `c` lies outside the Mandelbrot set, there's no such
+//! boundary to see: almost every point escapes, and the Julia set is a dust of measure
+//! zero which no pixel center ever lands on.
+//!
+//! Inverse iteration finds the set directly instead. The Julia set is repelling under
+//! `z ↦ z^2 + c`, so it's attracting under the inverse, `z ↦ ±sqrt(z - c)`; starting from
+//! a point on the set, every preimage is on the set too. Two methods are provided:
+//!
+//! - The random method (IIM) follows a single chain of preimages, choosing the sign at
+//!   random each step. It's simple, but visits the set very unevenly: the most strongly
+//!   repelling parts of the set are hardly ever reached.
+//! - The modified method (MIIM) explores the whole tree of preimages depth first, and
+//!   prunes any branch which lands on a pixel already hit `hit_limit` times. Sparse parts
+//!   of the set get filled in, and dense parts don't waste time.
+//!
+//! Both run on a single thread: each step depends on the last.
+
+use image::{ImageBuffer, Luma};
+use num::complex::Complex64;
+use std::fmt;
+use std::str::FromStr;
+
+use random::XorShift;
+use viewport::Viewport;
+
+/// How to choose which preimages to follow.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    /// Follow a random chain of this many preimages.
+    Random { points: usize },
+    /// Explore every preimage, pruning at pixels hit this many times.
+    Modified { hit_limit: u32 },
+}
+
+impl Method {
+    pub const DEFAULT_POINTS: usize = 1_000_000;
+    pub const DEFAULT_HIT_LIMIT: u32 = 8;
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Method::Random { points } => write!(f, "random:{}", points),
+            Method::Modified { hit_limit } => write!(f, "modified:{}", hit_limit),
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    /// Parse `random[:POINTS]` or `modified[:HIT_LIMIT]`.
+    fn from_str(s: &str) -> Result<Method, String> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap().trim().to_lowercase();
+        let count = parts.next();
+        let number = |default: usize| match count {
+            Some(n) => {
+                match usize::from_str(n.trim()) {
+                    Ok(n) if n > 0 => Ok(n),
+                    _ => Err(format!("invalid count in '{}'", s)),
+                }
+            }
+            None => Ok(default),
+        };
+        match kind.as_str() {
+            "random" => Ok(Method::Random { points: number(Method::DEFAULT_POINTS)? }),
+            "modified" => {
+                let hit_limit = number(Method::DEFAULT_HIT_LIMIT as usize)?;
+                Ok(Method::Modified { hit_limit: hit_limit.min(u32::MAX as usize) as u32 })
+            }
+            _ => {
+                Err(format!("unknown inverse iteration method '{}'; expected random or modified",
+                            kind))
+            }
+        }
+    }
+}
+
+/// The edge, in cells, of the grid which limits hits outside the viewport.
+const COARSE_EDGE: u32 = 1024;
+
+/// An inverse-iteration renderer for the Julia set of `z^2 + c`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InverseIteration {
+    pub c: Complex64,
+    pub method: Method,
+    /// The seed for the random method
+    pub seed: u64,
+    /// The modified method gives up on a branch after this many preimages
+    pub max_depth: usize,
+}
+
+impl InverseIteration {
+    pub fn new(c: Complex64, method: Method) -> InverseIteration {
+        InverseIteration {
+            c,
+            method,
+            seed: 0,
+            max_depth: 64,
+        }
+    }
+
+    /// A repelling fixed point, which is on the Julia set.
+    pub fn start(&self) -> Complex64 {
+        // the fixed points are (1 ± sqrt(1 - 4c)) / 2; they sum to 1, so the larger has
+        // modulus at least 1/2, and so multiplier |2z| at least 1
+        let root = (Complex64::new(1.0, 0.0) - self.c * 4.0).sqrt();
+        let (a, b) = ((root + 1.0) / 2.0, (-root + 1.0) / 2.0);
+        if a.norm_sqr() >= b.norm_sqr() { a } else { b }
+    }
+
+    /// Count how many times each pixel of a `width * height` image of `viewport` is hit,
+    /// in row-major order.
+    pub fn hits(&self, width: u32, height: u32, viewport: &Viewport) -> Vec<u32> {
+        let mut hits = vec![0u32; (width * height) as usize];
+        let index = |z| viewport.locate(z, width, height).map(|(x, y)| (y * width + x) as usize);
+        let c = self.c;
+        match self.method {
+            Method::Random { points } => {
+                let mut rng = XorShift::new(self.seed);
+                let mut z = self.start();
+                for _ in 0..points {
+                    z = (z - c).sqrt();
+                    if rng.next_bool() {
+                        z = -z;
+                    }
+                    if let Some(i) = index(z) {
+                        hits[i] = hits[i].saturating_add(1);
+                    }
+                }
+            }
+            Method::Modified { hit_limit } => {
+                // Branches outside the viewport have to be followed too, since their
+                // preimages may come back into view. They're pruned on a coarse grid over
+                // the disk containing the whole set, so that a zoomed-in render doesn't
+                // explore the entire tree. A coarse cell covers the length of the set of
+                // many pixels, so it allows proportionally more hits.
+                let radius = (1.0 + (1.0 + 4.0 * c.norm()).sqrt()) / 2.0;
+                let outside = Viewport::new(-radius, radius, -radius, radius);
+                let cell = 2.0 * radius / COARSE_EDGE as f64;
+                let pixel = (viewport.max_x - viewport.min_x) / width as f64;
+                let coarse_limit = (hit_limit as f64 * (cell / pixel).max(1.0))
+                    .min(u32::MAX as f64) as u32;
+                let mut coarse = vec![0; (COARSE_EDGE * COARSE_EDGE) as usize];
+                let coarse_index = |z| {
+                    outside.locate(z, COARSE_EDGE, COARSE_EDGE)
+                        .map(|(x, y)| (y * COARSE_EDGE + x) as usize)
+                };
+
+                let mut stack = vec![(self.start(), 0)];
+                while let Some((z, depth)) = stack.pop() {
+                    if let Some(i) = index(z) {
+                        if hits[i] >= hit_limit {
+                            continue;
+                        }
+                        hits[i] += 1;
+                    } else if let Some(i) = coarse_index(z) {
+                        if coarse[i] >= coarse_limit {
+                            continue;
+                        }
+                        coarse[i] += 1;
+                    }
+                    if depth < self.max_depth {
+                        let preimage = (z - c).sqrt();
+                        stack.push((-preimage, depth + 1));
+                        stack.push((preimage, depth + 1));
+                    }
+                }
+            }
+        }
+        hits
+    }
+
+    /// Render an image for the same colorizers as an escape-count image: pixels on the set
+    /// are brighter the more often they were hit, on a logarithmic scale, and the rest are
+    /// black.
+    pub fn render(&self,
+                  width: u32,
+                  height: u32,
+                  viewport: &Viewport)
+                  -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let hits = self.hits(width, height, viewport);
+        let scale = (1.0 + hits.iter().cloned().max().unwrap_or(0) as f64).ln().max(1.0);
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let count = hits[(y * width + x) as usize];
+            let luma = if count == 0 {
+                0
+            } else {
+                // never fully black, so that hits stay distinct from the background
+                ((1.0 + count as f64).ln() / scale * u8::MAX as f64).round().max(1.0) as u8
+            };
+            Luma([luma])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_points_are_on_the_set() {
+        // for c = 0 the Julia set is the unit circle
+        let viewport = Viewport::new(-1.5, 1.5, -1.5, 1.5);
+        for &method in &[Method::Random { points: 10_000 }, Method::Modified { hit_limit: 2 }] {
+            let iim = InverseIteration::new(Complex64::new(0.0, 0.0), method);
+            assert_eq!(iim.start(), Complex64::new(1.0, 0.0));
+            let hits = iim.hits(61, 61, &viewport);
+            assert!(hits.iter().any(|&n| n > 0));
+            for (i, &n) in hits.iter().enumerate() {
+                if n > 0 {
+                    let z = viewport.pixel(i as u32 % 61, i as u32 / 61, 61, 61);
+                    assert!((z.norm() - 1.0).abs() < 0.05, "{} hit {} times", z, n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hit_limit() {
+        let viewport = Viewport::new(-2.0, 2.0, -2.0, 2.0);
+        let method = Method::Modified { hit_limit: 3 };
+        let iim = InverseIteration::new(Complex64::new(-0.8, 0.2), method);
+        let hits = iim.hits(100, 100, &viewport);
+        assert!(hits.iter().all(|&n| n <= 3));
+        assert!(hits.iter().filter(|&&n| n > 0).count() > 100);
+    }
+
+    #[test]
+    fn test_method_from_str() {
+        assert_eq!(Method::from_str("random"),
+                   Ok(Method::Random { points: Method::DEFAULT_POINTS }));
+        assert_eq!(Method::from_str("modified:5"), Ok(Method::Modified { hit_limit: 5 }));
+        for method in &[Method::Random { points: 10 }, Method::Modified { hit_limit: 2 }] {
+            assert_eq!(&Method::from_str(&method.to_string()).unwrap(), method);
+        }
+        assert!(Method::from_str("modified:0").is_err());
+        assert!(Method::from_str("backward").is_err());
+    }
+}
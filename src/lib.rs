@@ -2,13 +2,51 @@ extern crate crossbeam;
 extern crate image;
 extern crate num;
 
-use image::ImageBuffer;
+use image::{DynamicImage, FilterType, ImageBuffer};
 use num::complex::Complex64;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
+pub mod attractor;
+pub mod bailout;
+pub mod buddhabrot;
+pub mod classify;
 pub mod colorize;
+pub mod contour;
+pub mod dimension;
+pub mod expr;
+pub mod family;
+pub mod field;
+pub mod font;
+pub mod iim;
 pub mod iter;
+pub mod metadata;
+pub mod multibrot;
+pub mod newton;
+pub mod output;
+pub mod overlay;
+pub mod periodic;
+pub mod phoenix;
+pub mod plane;
+pub mod quaternion;
+mod random;
+pub mod raymarch;
+pub mod sheet;
+pub mod state;
+pub mod stats;
+pub mod viewport;
+
+pub use bailout::Bailout;
+pub use plane::Plane;
+pub use state::State;
+pub use stats::RenderStats;
+pub use viewport::Viewport;
+
+use colorize::{Colorizer, HSLColorizer, InteriorColorizer};
+use metadata::RenderParameters;
+use overlay::draw_orbit;
+use state::WithParameter;
 
 /// A default julia set function chosen for its aesthetics
 pub fn default_julia(z: Complex64) -> Complex64 {
@@ -19,37 +57,105 @@ pub fn default_julia(z: Complex64) -> Complex64 {
 /// the state value of repeated applications of `function(value)`
 /// exceeds the threshold. If `bound` is set, don't iterate more than that number of times.
 pub fn applications_until(initial: Complex64,
-                          function: &Fn(Complex64) -> Complex64,
+                          function: &dyn Fn(Complex64) -> Complex64,
                           threshold: f64,
                           bound: Option<usize>)
                           -> usize {
-    let mut value = initial;
+    applications_until_escape(initial, function, &Bailout::Modulus(threshold), bound)
+}
+
+/// Count the number of applications of `function` required until the state value of
+/// repeated applications of `function(value)` escapes by the given criterion. If `bound`
+/// is set, don't iterate more than that number of times.
+pub fn applications_until_escape(initial: Complex64,
+                                 function: &dyn Fn(Complex64) -> Complex64,
+                                 bailout: &Bailout,
+                                 bound: Option<usize>)
+                                 -> usize {
+    applications_until_state(initial, function, bailout, bound)
+}
+
+/// Like `applications_until_escape`, but for orbits which carry more state than a single
+/// value. The escape criterion is applied to `State::value`.
+pub fn applications_until_state<S: State>(initial: S,
+                                          function: &dyn Fn(S) -> S,
+                                          bailout: &Bailout,
+                                          bound: Option<usize>)
+                                          -> usize {
+    iterate_until_escape(initial, function, bailout, bound).0
+}
+
+/// Apply `function` to `initial` repeatedly until its value escapes or `bound` is reached,
+/// returning the number of applications and the state they ended on. Every escape count
+/// comes from this loop.
+fn iterate_until_escape<S: State>(initial: S,
+                                  function: &dyn Fn(S) -> S,
+                                  bailout: &Bailout,
+                                  bound: Option<usize>)
+                                  -> (usize, S) {
+    let mut state = initial;
     let mut count = 0;
-    while count < bound.unwrap_or(std::usize::MAX) && value.norm_sqr() < (threshold * threshold) {
+    while count < bound.unwrap_or(usize::MAX) && !bailout.escaped(state.value()) {
         count += 1;
+        state = function(state);
+    }
+    (count, state)
+}
+
+/// Collect the orbit of `initial` under `function` for up to `bound` applications,
+/// stopping after the first value which escapes. `initial` itself comes first.
+pub fn orbit(initial: Complex64,
+             function: &dyn Fn(Complex64) -> Complex64,
+             bailout: &Bailout,
+             bound: usize)
+             -> Vec<Complex64> {
+    let mut value = initial;
+    let mut orbit = vec![value];
+    for _ in 0..bound {
+        if bailout.escaped(value) {
+            break;
+        }
         value = function(value);
+        orbit.push(value);
     }
-    count
+    orbit
 }
 
-/// Gets an appropriate complex value from a pixel coordinate
-/// in a given output size.
+/// Like `applications_until`, but produce a continuous escape value rather than a count.
 ///
-/// x, y: pixel coordinates
-/// width, height: size in pixels of the image
-/// min_x, max_x: inclusive range of the output x
-/// min_y, max_y: inclusive range of the output y
-fn interpolate_pixel(x: u32,
-                     y: u32,
-                     width: u32,
-                     height: u32,
-                     min_x: f64,
-                     max_x: f64,
-                     min_y: f64,
-                     max_y: f64)
-                     -> Complex64 {
-    Complex64::new(min_x + ((x as f64 / (width - 1) as f64) * (max_x - min_x)),
-                   min_y + ((y as f64 / (height - 1) as f64) * (max_y - min_y)))
+/// The integer part of the result is always the same as the count `applications_until`
+/// would return; the fractional part is the normalized iteration count, which estimates
+/// how far past the threshold the escaping value overshot. Points which never escape
+/// within `bound` have no fractional part.
+pub fn smooth_applications_until(initial: Complex64,
+                                 function: &dyn Fn(Complex64) -> Complex64,
+                                 threshold: f64,
+                                 bound: Option<usize>)
+                                 -> f64 {
+    smooth_applications_until_escape(initial, function, &Bailout::Modulus(threshold), bound)
+}
+
+/// Like `applications_until_escape`, but produce a continuous escape value rather than a
+/// count.
+///
+/// Only the modulus criterion has a meaningful normalized iteration count; under any
+/// other, the result is the plain count.
+pub fn smooth_applications_until_escape(initial: Complex64,
+                                        function: &dyn Fn(Complex64) -> Complex64,
+                                        bailout: &Bailout,
+                                        bound: Option<usize>)
+                                        -> f64 {
+    let (count, value) = iterate_until_escape(initial, function, bailout, bound);
+    let threshold = match *bailout {
+        Bailout::Modulus(threshold) => threshold,
+        _ => return count as f64,
+    };
+    if count >= bound.unwrap_or(usize::MAX) || threshold <= 1.0 || !value.norm().is_finite() {
+        return count as f64;
+    }
+    let nu = (value.norm().ln() / threshold.ln()).log2();
+    // keep the fraction in [0, 1) so that the integer part still matches the raw count
+    count as f64 + (1.0 - nu).clamp(0.0, 1.0 - f64::EPSILON)
 }
 
 /// Construct a closure which gets an appropriate complex value from a pixel coordinate
@@ -65,37 +171,14 @@ fn interpolate_pixel(x: u32,
 /// min_y, max_y: inclusive range of the output y
 pub fn interpolate_rectilinear(width: u32,
                                height: u32,
-                               mut min_x: f64,
-                               mut max_x: f64,
-                               mut min_y: f64,
-                               mut max_y: f64)
-                               -> Box<Fn(u32, u32) -> Complex64 + Send + Sync> {
-    let viewport_ratio = width as f64 / height as f64;
-    let range_ratio = (max_x - min_x) / (max_y - min_y);
-
-    {
-        let match_result = if viewport_ratio > range_ratio {
-            // Viewport is wider than the given domain.
-            // Need to increase the range of x.
-            Some((&mut min_x, &mut max_x))
-        } else if viewport_ratio < range_ratio {
-            // Viewport is narrower than the given domain
-            // Need to increase the range of y.
-            Some((&mut min_y, &mut max_y))
-        } else {
-            None
-        };
-
-        if let Some((mut min, mut max)) = match_result {
-            // adjust min and max here
-            let center = (*min + *max) / 2.0;
-            let arm = (*max - center) * viewport_ratio / range_ratio;
-            *min = center - arm;
-            *max = center + arm;
-        }
-    }
-
-    Box::new(move |x, y| interpolate_pixel(x, y, width, height, min_x, max_x, min_y, max_y))
+                               min_x: f64,
+                               max_x: f64,
+                               min_y: f64,
+                               max_y: f64)
+                               -> Box<dyn Fn(u32, u32) -> Complex64 + Send + Sync> {
+    Viewport::new(min_x, max_x, min_y, max_y)
+        .rectilinear(width, height)
+        .interpolate(width, height)
 }
 
 /// Construct a closure which gets an appropriate complex value from a pixel coordinate
@@ -114,15 +197,15 @@ pub fn interpolate_stretch(width: u32,
                            max_x: f64,
                            min_y: f64,
                            max_y: f64)
-                           -> Box<Fn(u32, u32) -> Complex64 + Send + Sync> {
-    Box::new(move |x, y| interpolate_pixel(x, y, width, height, min_x, max_x, min_y, max_y))
+                           -> Box<dyn Fn(u32, u32) -> Complex64 + Send + Sync> {
+    Viewport::new(min_x, max_x, min_y, max_y).interpolate(width, height)
 }
 
 /// Construct an image sequentially
 pub fn sequential_image(width: u32,
                         height: u32,
-                        function: &Fn(Complex64) -> Complex64,
-                        interpolate: &Fn(u32, u32) -> Complex64,
+                        function: &dyn Fn(Complex64) -> Complex64,
+                        interpolate: &dyn Fn(u32, u32) -> Complex64,
                         threshold: f64)
                         -> ImageBuffer<image::Luma<u8>, Vec<u8>> {
     ImageBuffer::from_fn(width, height, |x, y| {
@@ -132,20 +215,276 @@ pub fn sequential_image(width: u32,
     })
 }
 
+/// The number of worker threads used by the parallel renderers unless told otherwise.
+pub const DEFAULT_THREADS: usize = 4; // I'm on a four-real-core machine right now
+
 /// Construct an image in a parallel manner using row-chunking
 pub fn parallel_image(width: u32,
                       height: u32,
-                      function: &(Fn(Complex64) -> Complex64 + Sync),
-                      interpolate: &(Fn(u32, u32) -> Complex64 + Sync),
+                      function: &(dyn Fn(Complex64) -> Complex64 + Sync),
+                      interpolate: &(dyn Fn(u32, u32) -> Complex64 + Sync),
                       threshold: f64)
                       -> ImageBuffer<image::Luma<u8>, Vec<u8>> {
-    const THREADS: usize = 4; // I'm on a four-real-core machine right now
-    let image_backend = Arc::new(Mutex::new(vec![0_u8; (width * height) as usize]));
+    parallel_image_escape(width,
+                          height,
+                          function,
+                          interpolate,
+                          &Bailout::Modulus(threshold),
+                          255,
+                          DEFAULT_THREADS)
+}
+
+/// Like `parallel_image`, but with an arbitrary escape criterion, iteration limit and
+/// number of threads.
+///
+/// Escape counts are scaled so that `iterations` maps onto full brightness.
+pub fn parallel_image_escape(width: u32,
+                             height: u32,
+                             function: &(dyn Fn(Complex64) -> Complex64 + Sync),
+                             interpolate: &(dyn Fn(u32, u32) -> Complex64 + Sync),
+                             bailout: &Bailout,
+                             iterations: usize,
+                             threads: usize)
+                             -> ImageBuffer<image::Luma<u8>, Vec<u8>> {
+    parallel_image_state(width, height, function, interpolate, bailout, iterations, threads)
+}
+
+/// Like `parallel_image_escape`, but for a function of both `z` and `c`, drawn in either
+/// plane: `c` is the parameter of a Julia set, and is ignored in the parameter plane.
+#[allow(clippy::too_many_arguments)]
+pub fn parallel_image_plane(width: u32,
+                            height: u32,
+                            function: &(dyn Fn(Complex64, Complex64) -> Complex64 + Sync),
+                            plane: &Plane,
+                            c: Complex64,
+                            interpolate: &(dyn Fn(u32, u32) -> Complex64 + Sync),
+                            bailout: &Bailout,
+                            iterations: usize,
+                            threads: usize)
+                            -> ImageBuffer<image::Luma<u8>, Vec<u8>> {
+    // carry c along with z, so that both planes share a single step function
+    parallel_image_state(width,
+                         height,
+                         &|state: WithParameter| {
+                             WithParameter { z: function(state.z, state.c), ..state }
+                         },
+                         &|x, y| {
+                             let (z, c) = plane.orbit(interpolate(x, y), c);
+                             WithParameter { z, c }
+                         },
+                         bailout,
+                         iterations,
+                         threads)
+}
+
+/// Construct an escape-count image in parallel for orbits with arbitrary state, where
+/// `start` gives the initial state for each pixel.
+///
+/// Escape counts are scaled so that `iterations` maps onto full brightness. Every other
+/// escape-count renderer is built on this one, or on `parallel_image_stats`.
+pub fn parallel_image_state<S: State>(width: u32,
+                                      height: u32,
+                                      function: &(dyn Fn(S) -> S + Sync),
+                                      start: &(dyn Fn(u32, u32) -> S + Sync),
+                                      bailout: &Bailout,
+                                      iterations: usize,
+                                      threads: usize)
+                                      -> ImageBuffer<image::Luma<u8>, Vec<u8>> {
+    let iterations = iterations.max(1);
+    let (counts, _) =
+        parallel_escape_counts(width, height, function, start, bailout, iterations, threads);
+    escape_image(width, height, &counts, iterations)
+}
+
+/// Like `parallel_image_state`, but also gather statistics about the render.
+pub fn parallel_image_stats<S: State>(width: u32,
+                                      height: u32,
+                                      function: &(dyn Fn(S) -> S + Sync),
+                                      start: &(dyn Fn(u32, u32) -> S + Sync),
+                                      bailout: &Bailout,
+                                      iterations: usize,
+                                      threads: usize)
+                                      -> (ImageBuffer<image::Luma<u8>, Vec<u8>>, RenderStats) {
+    let iterations = iterations.max(1);
+    let begin = Instant::now();
+    let (counts, row_times) =
+        parallel_escape_counts(width, height, function, start, bailout, iterations, threads);
+    let stats = RenderStats {
+        row_times,
+        elapsed: begin.elapsed(),
+        ..RenderStats::from_counts(width, height, iterations, &counts)
+    };
+    (escape_image(width, height, &counts, iterations), stats)
+}
+
+/// The escape count of every pixel in row-major order, and how long each row took.
+fn parallel_escape_counts<S: State>(width: u32,
+                                    height: u32,
+                                    function: &(dyn Fn(S) -> S + Sync),
+                                    start: &(dyn Fn(u32, u32) -> S + Sync),
+                                    bailout: &Bailout,
+                                    iterations: usize,
+                                    threads: usize)
+                                    -> (Vec<usize>, Vec<Duration>) {
+    parallel_rows_timed(width, height, threads, &|x, y| {
+        applications_until_state(start(x, y), function, bailout, Some(iterations))
+    })
+}
+
+/// Scale escape counts so that `iterations` maps onto full brightness.
+fn escape_image(width: u32,
+                height: u32,
+                counts: &[usize],
+                iterations: usize)
+                -> ImageBuffer<image::Luma<u8>, Vec<u8>> {
+    let image_backend = counts.iter().map(|&count| (count * 255 / iterations) as u8).collect();
+    ImageBuffer::from_raw(width, height, image_backend).unwrap()
+}
+
+/// Render the image `params` describe on the given number of threads.
+///
+/// The number of threads has no effect on the output.
+pub fn render(params: &RenderParameters, threads: usize) -> DynamicImage {
+    render_escape(params, threads, false).0
+}
+
+/// Like `render`, but also gather statistics about the render.
+pub fn render_with_stats(params: &RenderParameters, threads: usize) -> (DynamicImage, RenderStats) {
+    let (image, stats) = render_escape(params, threads, true);
+    (image, stats.unwrap())
+}
+
+/// Render the image `params` describe by escape time, stopping early on interior points,
+/// coloring them, and drawing an orbit over the result as `params` ask. Statistics are
+/// only gathered if `gather` is set.
+fn render_escape(params: &RenderParameters,
+                 threads: usize,
+                 gather: bool)
+                 -> (DynamicImage, Option<RenderStats>) {
+    let (width, height) = (params.width * params.supersample,
+                           params.height * params.supersample);
+    let function = params.function.bind(params.c);
+    let interpolate = params.viewport.interpolate(width, height);
+    let fates = || {
+        let begin = Instant::now();
+        let (fates, row_times) = parallel_rows_timed(width, height, threads, &|x, y| {
+            match params.plane {
+                Plane::Julia => {
+                    attractor::fate(interpolate(x, y),
+                                    &function,
+                                    &params.bailout,
+                                    params.iterations,
+                                    &params.attractors)
+                }
+                Plane::Parameter(initial) => {
+                    let c = interpolate(x, y);
+                    attractor::fate(initial,
+                                    &|z| params.function.eval(z, c),
+                                    &params.bailout,
+                                    params.iterations,
+                                    &params.attractors)
+                }
+            }
+        });
+        let elapsed = begin.elapsed();
+        let stats = if gather {
+            Some(RenderStats {
+                row_times,
+                elapsed,
+                ..attractor::stats(&fates, width, height, params.iterations)
+            })
+        } else {
+            None
+        };
+        (fates, stats)
+    };
+    let (image, stats) = if params.interior {
+        let (fates, stats) = fates();
+        let image = attractor::to_image(&fates, width, height, params.iterations);
+        let colorizer = InteriorColorizer::new(HSLColorizer::from_name(&params.colorizer));
+        (DynamicImage::ImageRgb8(colorizer.colorize(&image)), stats)
+    } else {
+        let (image, stats) = if !params.plane.is_julia() || !params.attractors.is_none() {
+            let (fates, stats) = fates();
+            (attractor::to_escape_image(&fates, width, height, params.iterations), stats)
+        } else if gather {
+            let (image, stats) = parallel_image_stats(width,
+                                                      height,
+                                                      &function,
+                                                      &*interpolate,
+                                                      &params.bailout,
+                                                      params.iterations,
+                                                      threads);
+            (image, Some(stats))
+        } else {
+            (parallel_image_state(width,
+                                  height,
+                                  &function,
+                                  &*interpolate,
+                                  &params.bailout,
+                                  params.iterations,
+                                  threads),
+             None)
+        };
+        let image = match HSLColorizer::from_name(&params.colorizer) {
+            Some(colorizer) => DynamicImage::ImageRgb8(colorizer.colorize(&image)),
+            None => DynamicImage::ImageLuma8(image),
+        };
+        (image, stats)
+    };
+    let image = if params.supersample > 1 {
+        image.resize_exact(params.width, params.height, FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let image = match params.orbit {
+        Some(point) => {
+            let (z, c) = params.plane.orbit(point, params.c);
+            let points = orbit(z,
+                               &|z| params.function.eval(z, c),
+                               &params.bailout,
+                               params.iterations);
+            let mut image = image.to_rgb();
+            draw_orbit(&mut image, &params.viewport, &points);
+            DynamicImage::ImageRgb8(image)
+        }
+        None => image,
+    };
+    (image, stats)
+}
+
+/// Compute a value for every pixel in a `width * height` grid in a parallel manner
+/// using row-chunking, returning them in row-major order.
+///
+/// This is the engine behind `parallel_image`; it's exposed so that renderers which
+/// produce something other than an 8-bit escape count can share the same threading.
+pub fn parallel_rows<T>(width: u32,
+                        height: u32,
+                        threads: usize,
+                        pixel: &(dyn Fn(u32, u32) -> T + Sync))
+                        -> Vec<T>
+    where T: Clone + Default + Send
+{
+    parallel_rows_timed(width, height, threads, pixel).0
+}
+
+/// Like `parallel_rows`, but also return how long each row took to compute.
+pub fn parallel_rows_timed<T>(width: u32,
+                              height: u32,
+                              threads: usize,
+                              pixel: &(dyn Fn(u32, u32) -> T + Sync))
+                              -> (Vec<T>, Vec<Duration>)
+    where T: Clone + Default + Send
+{
+    let image_backend = Arc::new(Mutex::new(vec![T::default(); (width * height) as usize]));
+    let row_times = Arc::new(Mutex::new(vec![Duration::from_secs(0); height as usize]));
     let row_n = Arc::new(AtomicUsize::new(0));
 
     crossbeam::scope(|scope| {
-        for _ in 0..THREADS {
+        for _ in 0..threads.max(1) {
             let image_backend = image_backend.clone();
+            let row_times = row_times.clone();
             let row_n = row_n.clone();
 
             scope.spawn(move || {
@@ -160,18 +499,18 @@ pub fn parallel_image(width: u32,
 
                     row.clear();
 
-                    for x in 0..width as u32 {
-                        row.push(applications_until(interpolate(x, y),
-                                                    function,
-                                                    threshold,
-                                                    Some(255)) as u8);
+                    let begin = Instant::now();
+                    for x in 0..width {
+                        row.push(pixel(x, y));
                     }
+                    let time = begin.elapsed();
 
                     // insert the row into the output buffer
                     let idx_start = (y * width) as usize;
                     let idx_end = ((y + 1) * width) as usize;
                     {
                         image_backend.lock().unwrap()[idx_start..idx_end].clone_from_slice(&row);
+                        row_times.lock().unwrap()[y as usize] = time;
                     }
                 }
             });
@@ -180,14 +519,16 @@ pub fn parallel_image(width: u32,
 
     // Scoped threads take care of ensuring everything joins here
     // Now, unpack the shared backend
-    let image_backend = Arc::try_unwrap(image_backend).unwrap().into_inner().unwrap();
-    ImageBuffer::from_raw(width, height, image_backend).unwrap()
+    (Arc::try_unwrap(image_backend).ok().unwrap().into_inner().unwrap(),
+     Arc::try_unwrap(row_times).ok().unwrap().into_inner().unwrap())
 }
 
 #[cfg(test)]
 mod tests {
     use num::complex::Complex64;
     use super::*;
+    use attractor::AttractorTest;
+    use metadata::RenderParameters;
 
     /// Fixing the normalization function puts these back to expected values, yay!
     #[test]
@@ -212,6 +553,19 @@ mod tests {
                    1);
     }
 
+    #[test]
+    fn test_orbit_agrees_with_applications_until() {
+        let bailout = Bailout::Modulus(2.0);
+        for &(re, im) in &[(0.0, 1.0), (1.0, 1.0), (-1.0, 0.0)] {
+            let z = Complex64::new(re, im);
+            let points = orbit(z, &default_julia, &bailout, 256);
+            assert_eq!(points[0], z);
+            assert_eq!(points.len() - 1,
+                       applications_until_escape(z, &default_julia, &bailout, Some(256)));
+        }
+        assert_eq!(orbit(Complex64::new(0.0, 0.0), &default_julia, &bailout, 10).len(), 11);
+    }
+
     #[test]
     fn test_serial_parallel_agree() {
         let (width, height) = (200, 200);
@@ -224,4 +578,84 @@ mod tests {
                 .pixels())
             .all(|(p, s)| p == s));
     }
+
+    #[test]
+    fn test_planes() {
+        let (width, height) = (64, 48);
+        let interpolate = interpolate_stretch(width, height, -2.0, 1.0, -1.2, 1.2);
+        let quadratic = |z: Complex64, c: Complex64| z * z + c;
+        let c = Complex64::new(-0.221, -0.713);
+        let bailout = Bailout::default();
+
+        let julia = parallel_image_plane(width, height, &quadratic, &Plane::Julia, c, &*interpolate,
+                                         &bailout, 255, 2);
+        let bound = |z| quadratic(z, c);
+        let escape = parallel_image_escape(width, height, &bound, &*interpolate, &bailout, 255, 2);
+        assert!(julia.pixels().zip(escape.pixels()).all(|(p, q)| p == q));
+
+        // the Mandelbrot set contains the main cardioid, but not c = 1 - 1.2i
+        let origin = Plane::Parameter(Complex64::new(0.0, 0.0));
+        let mandelbrot = parallel_image_plane(width, height, &quadratic, &origin, c, &*interpolate,
+                                              &bailout, 255, 2);
+        assert_eq!(mandelbrot.get_pixel(42, 24)[0], 255);
+        assert!(mandelbrot.get_pixel(63, 0)[0] < 10);
+    }
+
+    fn params() -> RenderParameters {
+        RenderParameters {
+            width: 40,
+            height: 30,
+            supersample: 2,
+            viewport: Viewport::default().rectilinear(80, 60),
+            function: RenderParameters::default_function(),
+            c: Complex64::new(-0.221, -0.713),
+            plane: Plane::Julia,
+            bailout: Bailout::Modulus(2.0),
+            iterations: 255,
+            colorizer: String::from("hsl"),
+            attractors: AttractorTest::default(),
+            interior: false,
+            orbit: None,
+        }
+    }
+
+    #[test]
+    fn test_periodicity_is_exact() {
+        // cutting interior orbits short mustn't change the image
+        let fast = RenderParameters {
+            attractors: AttractorTest {
+                convergence: Some(AttractorTest::DEFAULT_TOLERANCE),
+                periodicity: Some(AttractorTest::DEFAULT_TOLERANCE),
+            },
+            ..params()
+        };
+        assert_eq!(render(&fast, 2).raw_pixels(), render(&params(), 2).raw_pixels());
+    }
+
+    #[test]
+    fn test_orbit_overlay() {
+        let traced = RenderParameters { orbit: Some(Complex64::new(0.1, 0.2)), ..params() };
+        assert!(render(&traced, 2).raw_pixels() != render(&params(), 2).raw_pixels());
+    }
+
+    #[test]
+    fn test_stats_agree_across_paths() {
+        // stopping early changes the work done, but not the escape counts; the basilica
+        // has plenty of interior to stop early in
+        let basilica = RenderParameters { c: Complex64::new(-1.0, 0.0), ..params() };
+        let (image, plain) = render_with_stats(&basilica, 2);
+        assert_eq!(image.raw_pixels(), render(&basilica, 2).raw_pixels());
+        let fast = RenderParameters {
+            attractors: AttractorTest {
+                convergence: None,
+                periodicity: Some(AttractorTest::DEFAULT_TOLERANCE),
+            },
+            ..basilica
+        };
+        let (_, early) = render_with_stats(&fast, 2);
+        assert_eq!(plain.histogram, early.histogram);
+        assert!(early.work < plain.work);
+        assert_eq!(plain.pixels(), 80 * 60);
+        assert_eq!(plain.row_times.len(), 60);
+    }
 }
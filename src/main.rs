@@ -1,10 +1,31 @@
+#[macro_use]
+extern crate clap;
 extern crate image;
 extern crate julia_set;
+extern crate num;
 
-use image::imageops::{resize, FilterType};
-use julia_set::{parallel_image, default_julia, interpolate_rectilinear};
-use julia_set::colorize::{Colorizer, HSLColorizer};
+use clap::{App, Arg, ArgGroup, ErrorKind};
+use image::{DynamicImage, FilterType, ImageBuffer, Luma};
+use julia_set::buddhabrot::{Buddhabrot, Channel};
+use julia_set::{parallel_image_plane, render, render_with_stats, Bailout, Plane, Viewport,
+                DEFAULT_THREADS};
+use julia_set::family::Family;
+use julia_set::contour::{save_svg, ContourOptions};
+use julia_set::attractor::AttractorTest;
+use julia_set::colorize::{Colorizer, HSLColorizer, RootColorizer};
+use julia_set::field::{EscapeField, FieldHeader};
+use julia_set::iim::{InverseIteration, Method};
+use julia_set::metadata::{key, software, RenderParameters};
+use julia_set::multibrot::Multibrot;
+use julia_set::newton::{parse_complex_list, Newton, Polynomial};
+use julia_set::output::{save_field, save_image, OutputFormat};
+use julia_set::phoenix::Phoenix;
+use julia_set::quaternion::{Quaternion, QuaternionJulia, Slice};
+use julia_set::raymarch::{RayMarcher, Vector3};
+use num::complex::Complex64;
 use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 enum JuliaResult {
@@ -16,81 +37,985 @@ enum JuliaResult {
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    std::process::exit(match args.len() {
-        0 => {
-            println!("No args found; we're done here.");
-            JuliaResult::UnknownSelfName
-        }
-        3 => generate_julia(&args[1], &args[2], None),
-        4 => generate_julia(&args[1], &args[2], Some(&args[3])),
-        _ => {
-            println!("Wrong number of arguments.\n\n\
-                      Usage: {} WIDTH HEIGHT [PATH]\n\
-                      Where WIDTH and HEIGHT are integers.\n\
-                      If PATH is not specified, defaults to 'julia_set.png'",
-                     args[0]);
-            JuliaResult::WrongNumberOfArguments
-        }
+    if env::args().next().is_none() {
+        println!("No args found; we're done here.");
+        std::process::exit(JuliaResult::UnknownSelfName as i32);
+    }
+
+    std::process::exit(match JuliaConfiguration::new() {
+        Ok(conf) => generate_julia(&conf),
+        Err(result) => result,
     } as i32)
 }
 
-fn generate_julia(width: &str, height: &str, path: Option<&str>) -> JuliaResult {
-    let width = {
-        if let Ok(w) = u32::from_str(width) {
-            w
-        } else {
-            println!("Couldn't parse '{}' as an integer; aborting.", width);
-            return JuliaResult::CantParseIntegerArguments;
-        }
+fn generate_julia(conf: &JuliaConfiguration) -> JuliaResult {
+    println!("Got parameters:");
+    println!("  dimensions:  {:?}", (conf.width, conf.height));
+    println!("  function:    {}", conf.family);
+    println!("  c:           {}", conf.c);
+    println!("  plane:       {}", conf.plane);
+    println!("  viewport:    {:?}", conf.viewport);
+    println!("  iterations:  {}", conf.iterations);
+    println!("  bailout:     {}", conf.bailout);
+    println!("  supersample: {}", conf.supersample);
+    println!("  colorizer:   {}", conf.colorizer);
+    println!("  threads:     {}", conf.threads);
+    println!("  path:        {}", conf.path.display());
+
+    let result = match conf.mode {
+        Mode::Escape => generate_escape(conf),
+        Mode::Newton(ref newton) => generate_newton(conf, newton),
+        Mode::Phoenix(ref phoenix) => generate_phoenix(conf, phoenix),
+        Mode::Buddhabrot(ref buddhabrot) => generate_buddhabrot(conf, buddhabrot),
+        Mode::InverseIteration(ref iim) => generate_iim(conf, iim),
+        Mode::Quaternion(ref c) => generate_quaternion(conf, c),
+        Mode::Raymarch(ref marcher) => generate_raymarch(conf, marcher),
     };
-    let height = {
-        if let Ok(h) = u32::from_str(height) {
-            h
-        } else {
-            println!("Couldn't parse '{}' as an integer; aborting.", height);
-            return JuliaResult::CantParseIntegerArguments;
+
+    match result {
+        Ok(_) => JuliaResult::Success,
+        Err(error) => {
+            println!("Encountered error: {}", error);
+            JuliaResult::IOError
         }
-    };
-    let path = match path {
-        None => {
-            let mut path = env::current_dir().unwrap();
-            path.push("julia_set.png");
-            path
+    }
+}
+
+/// Render and save the Julia set, or parameter plane, of `family` by escape time.
+fn generate_escape(conf: &JuliaConfiguration) -> io::Result<()> {
+    let c = conf.c;
+    let function = conf.family.bind(c);
+
+    match conf.output {
+        Output::Svg | Output::Field | Output::Image(OutputFormat::Png16) |
+        Output::Image(OutputFormat::Exr) if conf.orbit.is_some() => {
+            Err(io::Error::new(io::ErrorKind::InvalidInput,
+                               "orbits can only be drawn over images"))
         }
-        Some(path) => {
-            let mut path = std::path::PathBuf::from(path);
-            if path.file_name().is_none() {
-                path.set_file_name("julia_set.png");
-            } else if path.extension().is_none() ||
-               Some(String::from("png")) !=
-               path.extension().unwrap().to_str().map(|s| s.to_lowercase()) {
-                path.set_extension("png");
+        Output::Svg => {
+            let viewport = conf.viewport.rectilinear(conf.width, conf.height);
+            let image = parallel_image_plane(conf.width,
+                                             conf.height,
+                                             &|z, c| conf.family.eval(z, c),
+                                             &conf.plane,
+                                             c,
+                                             &*viewport.interpolate(conf.width, conf.height),
+                                             &conf.bailout,
+                                             conf.iterations,
+                                             conf.threads);
+            if conf.stats {
+                println!("Render statistics aren't gathered for SVG output.");
             }
-            path
+            save_svg(&image, &conf.path, &conf.contours)
+        }
+        Output::Field | Output::Image(OutputFormat::Png16) | Output::Image(OutputFormat::Exr)
+            if !conf.plane.is_julia() => {
+            Err(io::Error::new(io::ErrorKind::InvalidInput,
+                               "escape fields can only record Julia sets"))
         }
+        Output::Field | Output::Image(OutputFormat::Png16) | Output::Image(OutputFormat::Exr) => {
+            let header = FieldHeader {
+                width: conf.width,
+                height: conf.height,
+                viewport: conf.viewport.rectilinear(conf.width, conf.height),
+                function: format!("{} where c = {}", conf.family, c),
+                bailout: conf.bailout.clone(),
+                iterations: conf.iterations,
+            };
+            let field = EscapeField::render(header, &*function, conf.threads);
+            if conf.stats {
+                print!("{}", field.stats());
+            }
+            match conf.output {
+                Output::Image(format) => save_field(&field, &conf.path, format),
+                _ => field.save(&conf.path),
+            }
+        }
+        Output::Image(format) => {
+            let (width, height) = (conf.width * conf.supersample, conf.height * conf.supersample);
+            let params = RenderParameters {
+                width: conf.width,
+                height: conf.height,
+                supersample: conf.supersample,
+                viewport: conf.viewport.rectilinear(width, height),
+                function: conf.family.expression(),
+                c,
+                plane: conf.plane,
+                bailout: conf.bailout.clone(),
+                iterations: conf.iterations,
+                colorizer: conf.colorizer.clone(),
+                attractors: conf.attractors,
+                interior: conf.interior,
+                orbit: conf.orbit,
+            };
+            let image = if conf.stats {
+                let (image, stats) = render_with_stats(&params, conf.threads);
+                print!("{}", stats);
+                if conf.plane.is_julia() {
+                    println!("  area:        {}", stats.area(&params.viewport));
+                }
+                image
+            } else {
+                render(&params, conf.threads)
+            };
+            params.save(&image, &conf.path, format)
+        }
+    }
+}
+
+/// Render and save the basins of Newton's method, instead of a Julia set.
+fn generate_newton(conf: &JuliaConfiguration, newton: &Newton) -> io::Result<()> {
+    println!("  polynomial:  {}", newton.polynomial());
+    let format = match conf.output {
+        Output::Image(format) => format,
+        _ => return Err(io::Error::other("Newton renders can only be saved as images")),
     };
 
-    println!("Got parameters:");
-    println!("  width:  {}", width);
-    println!("  height: {}", height);
-    println!("  path:   {}", path.display());
+    let (width, height) = (conf.width * conf.supersample, conf.height * conf.supersample);
+    let viewport = conf.viewport.rectilinear(width, height);
+    let basins = newton.render_image(width, height, &viewport, conf.threads);
+    let image = DynamicImage::ImageRgb8(RootColorizer::new(newton.roots().len()).colorize(&basins));
+    let image = if conf.supersample > 1 {
+        image.resize_exact(conf.width, conf.height, FilterType::Lanczos3)
+    } else {
+        image
+    };
 
-    // julia sets are only really interesting in the region [-1...1]
-    let interpolate = interpolate_rectilinear(width * 2, height * 2, -1.0, 1.0, -1.0, 1.0);
+    let mut text = render_text(conf, "newton", Some(&viewport), Some(conf.iterations), "roots");
+    text.push((key("newton.polynomial"), newton.polynomial().to_string()));
+    save_image(&image, &conf.path, format, &text)
+}
 
-    let image = parallel_image(width * 2, height * 2, &default_julia, &*interpolate, 2.0);
-    let colorizer = HSLColorizer::new();
-    let image = resize(&colorizer.colorize(&image),
-                       width,
-                       height,
-                       FilterType::Lanczos3);
+/// Render and save a member of the Phoenix family, instead of a Julia set of `family`.
+fn generate_phoenix(conf: &JuliaConfiguration, phoenix: &Phoenix) -> io::Result<()> {
+    println!("  phoenix p:   {}", phoenix.p);
+    let format = match conf.output {
+        Output::Image(format) => format,
+        _ => return Err(io::Error::other("Phoenix renders can only be saved as images")),
+    };
 
-    match image.save(&*path.to_string_lossy()) {
-        Ok(_) => JuliaResult::Success,
-        Err(error) => {
-            println!("Encountered error: {}", error);
-            JuliaResult::IOError
+    let (width, height) = (conf.width * conf.supersample, conf.height * conf.supersample);
+    let viewport = conf.viewport.rectilinear(width, height);
+    let escapes = phoenix.render(width,
+                                 height,
+                                 &*viewport.interpolate(width, height),
+                                 &conf.plane,
+                                 conf.c,
+                                 &conf.bailout,
+                                 conf.iterations,
+                                 conf.threads);
+    let image = colorize_and_resize(conf, escapes);
+
+    let mut text = render_text(conf,
+                               "phoenix",
+                               Some(&viewport),
+                               Some(conf.iterations),
+                               &conf.colorizer);
+    text.extend(vec![(key("phoenix.p"), format!("{},{}", phoenix.p.re, phoenix.p.im)),
+                     (key("c"), format!("{},{}", conf.c.re, conf.c.im)),
+                     (key("plane"), conf.plane.to_string()),
+                     (key("bailout"), conf.bailout.to_string())]);
+    save_image(&image, &conf.path, format, &text)
+}
+
+/// Render and save the Buddhabrot, or a relative, of `family`.
+fn generate_buddhabrot(conf: &JuliaConfiguration, buddhabrot: &Buddhabrot) -> io::Result<()> {
+    let channels = buddhabrot.channels.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    println!("  samples:     {}", buddhabrot.samples);
+    println!("  channels:    {}", channels.join(","));
+    let format = match conf.output {
+        Output::Image(format) => format,
+        _ => return Err(io::Error::other("Buddhabrot renders can only be saved as images")),
+    };
+
+    let (width, height) = (conf.width * conf.supersample, conf.height * conf.supersample);
+    let viewport = conf.viewport.rectilinear(width, height);
+    let density = buddhabrot.render(width, height, &viewport, conf.threads);
+    let (image, colorizer) = if density.channels.len() > 1 {
+        let image = DynamicImage::ImageRgb8(density.to_rgb());
+        let image = if conf.supersample > 1 {
+            image.resize_exact(conf.width, conf.height, FilterType::Lanczos3)
+        } else {
+            image
+        };
+        // each channel is one of red, green and blue
+        (image, "channels")
+    } else {
+        (colorize_and_resize(conf, density.to_image()), &conf.colorizer[..])
+    };
+
+    // each channel has its own iteration limits
+    let mut text = render_text(conf, "buddhabrot", Some(&viewport), None, colorizer);
+    text.extend(vec![(key("buddhabrot.function"), buddhabrot.family.to_string()),
+                     (key("buddhabrot.samples"), buddhabrot.samples.to_string()),
+                     (key("buddhabrot.channels"), channels.join(",")),
+                     (key("buddhabrot.anti"), buddhabrot.anti.to_string())]);
+    save_image(&image, &conf.path, format, &text)
+}
+
+/// Plot and save the Julia set of `z^2 + c` by inverse iteration.
+fn generate_iim(conf: &JuliaConfiguration, iim: &InverseIteration) -> io::Result<()> {
+    println!("  method:      {}", iim.method);
+    let format = match conf.output {
+        Output::Image(format) => format,
+        _ => return Err(io::Error::other("inverse iteration can only be saved as an image")),
+    };
+
+    let (width, height) = (conf.width * conf.supersample, conf.height * conf.supersample);
+    let viewport = conf.viewport.rectilinear(width, height);
+    let image = colorize_and_resize(conf, iim.render(width, height, &viewport));
+
+    let mut text = render_text(conf, "iim", Some(&viewport), None, &conf.colorizer);
+    text.extend(vec![(key("iim.method"), iim.method.to_string()),
+                     (key("c"), format!("{},{}", iim.c.re, iim.c.im))]);
+    save_image(&image, &conf.path, format, &text)
+}
+
+/// Render and save a slice, or a stack of slices, of a quaternion Julia set.
+fn generate_quaternion(conf: &JuliaConfiguration, c: &Quaternion) -> io::Result<()> {
+    println!("  quaternion:  {}", c);
+    println!("  slice:       {}", conf.slice);
+    let format = match conf.output {
+        Output::Image(format) => format,
+        _ => return Err(io::Error::other("quaternion renders can only be saved as images")),
+    };
+
+    let (width, height) = (conf.width * conf.supersample, conf.height * conf.supersample);
+    let viewport = conf.viewport.rectilinear(width, height);
+    let interpolate = viewport.interpolate(width, height);
+    let julia = QuaternionJulia::new(*c);
+    let slices = match conf.voxels {
+        Some(depth) => conf.slice.stack(depth, (viewport.min_y, viewport.max_y)),
+        None => vec![conf.slice],
+    };
+    let layers = julia.render_voxels(width,
+                                     height,
+                                     &*interpolate,
+                                     &slices,
+                                     &conf.bailout,
+                                     conf.iterations,
+                                     conf.threads);
+
+    for (index, (layer, slice)) in layers.into_iter().zip(slices).enumerate() {
+        let image = colorize_and_resize(conf, layer);
+        let path = match conf.voxels {
+            Some(_) => {
+                let stem = conf.path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+                conf.path.with_file_name(format!("{}-{:03}.{}", stem, index, format.extension()))
+            }
+            None => conf.path.clone(),
+        };
+
+        let mut text = render_text(conf,
+                                   "quaternion",
+                                   Some(&viewport),
+                                   Some(conf.iterations),
+                                   &conf.colorizer);
+        text.extend(vec![(key("quaternion.c"), c.to_string()),
+                         (key("quaternion.slice"), slice.to_string()),
+                         (key("bailout"), conf.bailout.to_string())]);
+        save_image(&image, &path, format, &text)?;
+    }
+    Ok(())
+}
+
+/// Render and save a shaded 3D view of a quaternion Julia set.
+fn generate_raymarch(conf: &JuliaConfiguration, marcher: &RayMarcher) -> io::Result<()> {
+    println!("  quaternion:  {}", marcher.julia.c);
+    println!("  section:     {}", marcher.section);
+    println!("  camera:      {} looking at {}",
+             marcher.camera.position,
+             marcher.camera.target);
+    let format = match conf.output {
+        Output::Image(format) => format,
+        _ => return Err(io::Error::other("ray-marched renders can only be saved as images")),
+    };
+
+    let (width, height) = (conf.width * conf.supersample, conf.height * conf.supersample);
+    let shades = marcher.render(width, height, conf.threads);
+    let image = colorize_and_resize(conf, shades);
+
+    // the camera takes the place of a viewport
+    let mut text = render_text(conf,
+                               "quaternion-3d",
+                               None,
+                               Some(marcher.iterations),
+                               &conf.colorizer);
+    text.extend(vec![(key("quaternion.c"), marcher.julia.c.to_string()),
+                     (key("quaternion.section"), marcher.section.to_string()),
+                     (key("camera"), marcher.camera.position.to_string()),
+                     (key("camera.target"), marcher.camera.target.to_string()),
+                     (key("light"), marcher.lighting.direction.to_string()),
+                     (key("occlusion"), marcher.lighting.occlusion.to_string())]);
+    save_image(&image, &conf.path, format, &text)
+}
+
+/// The text chunks every mode writes, in the same order as `RenderParameters::to_text`:
+/// which mode rendered the image, at what size, over which region, how many iterations,
+/// and with which palette. Modes which have no viewport or iteration limit of their own
+/// pass `None` for them; mode-specific chunks follow. Only escape-time renders can be
+/// reproduced by `rerender`, which refuses the others by their mode.
+fn render_text(conf: &JuliaConfiguration,
+               mode: &str,
+               viewport: Option<&Viewport>,
+               iterations: Option<usize>,
+               colorizer: &str)
+               -> Vec<(String, String)> {
+    let mut text = vec![software(),
+                        (key("mode"), String::from(mode)),
+                        (key("size"), format!("{},{}", conf.width, conf.height)),
+                        (key("supersample"), conf.supersample.to_string())];
+    if let Some(vp) = viewport {
+        text.push((key("viewport"),
+                   format!("{},{},{},{}", vp.min_x, vp.max_x, vp.min_y, vp.max_y)));
+    }
+    if let Some(iterations) = iterations {
+        text.push((key("iterations"), iterations.to_string()));
+    }
+    text.push((key("colorizer"), String::from(colorizer)));
+    text
+}
+
+/// Apply the configured colorizer to a grayscale render, then downsample it.
+fn colorize_and_resize(conf: &JuliaConfiguration,
+                       image: ImageBuffer<Luma<u8>, Vec<u8>>)
+                       -> DynamicImage {
+    let image = match HSLColorizer::from_name(&conf.colorizer) {
+        Some(colorizer) => DynamicImage::ImageRgb8(colorizer.colorize(&image)),
+        None => DynamicImage::ImageLuma8(image),
+    };
+    if conf.supersample > 1 {
+        image.resize_exact(conf.width, conf.height, FilterType::Lanczos3)
+    } else {
+        image
+    }
+}
+
+/// What kind of file to produce
+enum Output {
+    Image(OutputFormat),
+    /// A recolorable escape field
+    Field,
+    /// Vector contours of the escape levels
+    Svg,
+}
+
+/// What to render; the options which choose each mode exclude one another.
+enum Mode {
+    /// The Julia set, or parameter plane, of `family` by escape time
+    Escape,
+    /// The basins of Newton's method for a polynomial
+    Newton(Newton),
+    /// A member of the Phoenix family
+    Phoenix(Phoenix),
+    /// The Buddhabrot of `family`
+    Buddhabrot(Buddhabrot),
+    /// The Julia set of z^2 + c, plotted by inverse iteration
+    InverseIteration(InverseIteration),
+    /// A slice, or a stack of slices, of the quaternion Julia set for this `c`
+    Quaternion(Quaternion),
+    /// A shaded 3D view of a quaternion Julia set
+    Raymarch(RayMarcher),
+}
+
+struct JuliaConfiguration {
+    width: u32,
+    height: u32,
+    family: Family,
+    c: Complex64,
+    plane: Plane,
+    viewport: Viewport,
+    iterations: usize,
+    bailout: Bailout,
+    supersample: u32,
+    colorizer: String,
+    attractors: AttractorTest,
+    interior: bool,
+    /// If set, draw the orbit of this point over the render
+    orbit: Option<Complex64>,
+    threads: usize,
+    /// Whether to print statistics about the render
+    stats: bool,
+    contours: ContourOptions,
+    mode: Mode,
+    /// The slice of a quaternion Julia set to render
+    slice: Slice,
+    /// If set, render this many quaternion slices instead of one
+    voxels: Option<u32>,
+    output: Output,
+    path: PathBuf,
+}
+
+impl JuliaConfiguration {
+    fn build_cli() -> App<'static, 'static> {
+        App::new("julia-set")
+           .about("renders an image of the julia set of f(z) = z^2 + c, or any other function")
+          // use crate_version! to pull the version number
+          .version(crate_version!())
+          .after_help("The output format is chosen by the extension of PATH unless --format is \
+                       given: one of png, jpg, tiff, webp or ppm, or png16 or exr for the \
+                       escape values of a Julia set, in 16-bit gray or unquantized float. If \
+                       PATH ends in '.field', saves a recolorable escape field instead. If \
+                       PATH ends in '.svg', saves vector contours of the escape levels.")
+          .arg(Arg::with_name("dimensions")
+                    .short("d")
+                    .long("dimensions")
+                    .value_names(&["WIDTH", "HEIGHT"])
+                    .use_delimiter(true)
+                    .default_value("800,600")
+                    .help("Set the dimensions of the output image.")
+                )
+          .arg(Arg::with_name("function")
+                    .short("F")
+                    .long("function")
+                    .value_names(&["EXPR"])
+                    .default_value(RenderParameters::DEFAULT_FUNCTION)
+                    .help("Iteration function of z and c, such as 'z^3 + c*sin(z)'. Supports + - \
+                           * / ^, i, pi, e, and exp sin cos tan sinh cosh tanh log sqrt pow \
+                           conj abs re im. The names exp, sin and cos alone select the families \
+                           c*exp(z), c*sin(z) and c*cos(z), with their own escape criteria, and \
+                           burning-ship, tricorn, celtic and buffalo select those variants of \
+                           z^2 + c.")
+                )
+          .arg(Arg::with_name("degree")
+                    .long("degree")
+                    .value_names(&["D"])
+                    .conflicts_with("function")
+                    .help("Use the multibrot function z^D + c; D may be fractional.")
+                )
+          .arg(Arg::with_name("newton")
+                    .long("newton")
+                    .value_names(&["COEFFICIENTS"])
+                    .allow_hyphen_values(true)
+                    .help("Render the basins of Newton's method for the polynomial with these \
+                           comma-separated coefficients, highest degree first: '1,0,0,-1' is \
+                           z^3 - 1. Coefficients may be complex, as in '1, 0.5i'.")
+                )
+          .arg(Arg::with_name("roots")
+                    .long("roots")
+                    .value_names(&["ROOTS"])
+                    .allow_hyphen_values(true)
+                    .help("Render the basins of Newton's method for the polynomial with these \
+                           comma-separated roots, such as '1, -0.5+0.866i, -0.5-0.866i'.")
+                )
+          .arg(Arg::with_name("phoenix")
+                    .long("phoenix")
+                    .value_names(&["RE", "IM"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .conflicts_with_all(&["function", "degree"])
+                    .help("Render the Phoenix family z' = z^2 + c + P*z_prev, where z_prev is \
+                           the previous value. The classic Phoenix set is '-0.5,0' with \
+                           c = 0.5667,0.")
+                )
+          .arg(Arg::with_name("buddhabrot")
+                    .long("buddhabrot")
+                    .value_names(&["SAMPLES"])
+                    .conflicts_with_all(&["plane", "phoenix", "iim", "quaternion"])
+                    .help("Render the Buddhabrot of the function instead: sample SAMPLES values \
+                           of c, and count how often the orbits which escape pass through each \
+                           pixel.")
+                )
+          .arg(Arg::with_name("anti")
+                    .long("anti")
+                    .requires("buddhabrot")
+                    .help("Count the orbits which never escape instead, for the anti-Buddhabrot.")
+                )
+          .arg(Arg::with_name("channels")
+                    .long("channels")
+                    .value_names(&["RANGES"])
+                    .requires("buddhabrot")
+                    .help("Comma-separated ranges of escape counts, MIN:MAX or just MAX, each \
+                           counted separately. Up to three are mapped onto red, green and blue; \
+                           'nebulabrot' means 0:5000,0:500,0:50. [default: 0:N for -i N]")
+                )
+          .arg(Arg::with_name("iim")
+                    .long("iim")
+                    .value_names(&["METHOD"])
+                    .conflicts_with_all(&["function", "degree", "phoenix", "plane"])
+                    .help("Plot the Julia set of z^2 + c by inverse iteration, which can show \
+                           even the dust-like sets of c outside the Mandelbrot set. METHOD is \
+                           random[:POINTS] to follow a random chain of preimages, or \
+                           modified[:HIT_LIMIT] to explore them all, stopping at pixels already \
+                           hit HIT_LIMIT times.")
+                )
+          .arg(Arg::with_name("quaternion")
+                    .long("quaternion")
+                    .value_names(&["W", "X", "Y", "Z"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .conflicts_with_all(&["function", "degree", "phoenix", "plane", "iim"])
+                    .help("Render a slice of the quaternion Julia set of q^2 + c for this c.")
+                )
+          .arg(Arg::with_name("slice")
+                    .long("slice")
+                    .value_names(&["AXES[:A,B]"])
+                    .allow_hyphen_values(true)
+                    .requires("quaternion")
+                    .help("The plane of a quaternion slice: two of the axes w, x, y, z to vary \
+                           horizontally and vertically, and optionally the values A and B of \
+                           the other two, which are otherwise 0. [default: wx]")
+                )
+          .arg(Arg::with_name("voxels")
+                    .long("voxels")
+                    .value_names(&["DEPTH"])
+                    .requires("quaternion")
+                    .help("Save a stack of DEPTH quaternion slices instead of one, stepping the \
+                           first fixed axis across the vertical extent of the viewport. Layer N \
+                           is saved as PATH with -N appended to its name.")
+                )
+          .arg(Arg::with_name("raymarch")
+                    .long("raymarch")
+                    .requires("quaternion")
+                    .conflicts_with_all(&["slice", "voxels"])
+                    .help("Render a shaded 3D view of the quaternion Julia set by ray marching, \
+                           instead of a slice. Space is the section where the k component of \
+                           q is fixed. -i sets the iterations of the distance estimator \
+                           [default: 16].")
+                )
+          .arg(Arg::with_name("section")
+                    .long("section")
+                    .value_names(&["K"])
+                    .allow_hyphen_values(true)
+                    .requires("raymarch")
+                    .help("The k component of every point in a ray-marched view. [default: 0]")
+                )
+          .arg(Arg::with_name("camera")
+                    .long("camera")
+                    .value_names(&["X", "Y", "Z"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .requires("raymarch")
+                    .help("Position of the camera for a ray-marched view. [default: 0,0,-3]")
+                )
+          .arg(Arg::with_name("look-at")
+                    .long("look-at")
+                    .value_names(&["X", "Y", "Z"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .requires("raymarch")
+                    .help("The point at the center of a ray-marched view. [default: 0,0,0]")
+                )
+          .arg(Arg::with_name("light")
+                    .long("light")
+                    .value_names(&["X", "Y", "Z"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .requires("raymarch")
+                    .help("The direction light travels in a ray-marched view. [default: 1,-1,1]")
+                )
+          .arg(Arg::with_name("no-occlusion")
+                    .long("no-occlusion")
+                    .requires("raymarch")
+                    .help("Don't darken the creases of a ray-marched view by ambient \
+                           occlusion.")
+                )
+          .group(ArgGroup::with_name("polynomial")
+                    .args(&["newton", "roots"])
+                    .conflicts_with_all(&["function",
+                                          "degree",
+                                          "constant",
+                                          "bailout",
+                                          "plane",
+                                          "phoenix",
+                                          "quaternion",
+                                          "iim",
+                                          "buddhabrot",
+                                          "orbit"]))
+          .arg(Arg::with_name("constant")
+                    .short("c")
+                    .long("constant")
+                    .value_names(&["RE", "IM"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .default_value("-0.221,-0.713")
+                    .help("Set the constant c in the iteration function.")
+                )
+          .arg(Arg::with_name("plane")
+                    .long("plane")
+                    .value_names(&["PLANE"])
+                    .allow_hyphen_values(true)
+                    .default_value("julia")
+                    .help("Draw the Julia set for c, or with 'parameter', the parameter plane: \
+                           pixels are values of c, and orbits start from a critical point of \
+                           the function. 'parameter:RE,IM' starts them from RE + IM i instead.")
+                )
+          .arg(Arg::with_name("viewport")
+                    .long("viewport")
+                    .value_names(&["MIN_X", "MAX_X", "MIN_Y", "MAX_Y"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .help("Set the region of the complex plane to render. [default: -1,1,-1,1]")
+                )
+          .arg(Arg::with_name("stats")
+                    .long("stats")
+                    .conflicts_with_all(&["polynomial",
+                                          "phoenix",
+                                          "buddhabrot",
+                                          "iim",
+                                          "quaternion"])
+                    .help("Print statistics about the render: the fraction of pixels in the \
+                           filled set, a histogram of escape counts, and timings.")
+                )
+          .arg(Arg::with_name("orbit")
+                    .long("orbit")
+                    .value_names(&["RE", "IM"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .conflicts_with_all(&["phoenix",
+                                          "buddhabrot",
+                                          "iim",
+                                          "quaternion"])
+                    .help("Draw the orbit of this point over the render, colored from red to \
+                           violet by step. In the parameter plane, the point is a value of c.")
+                )
+          .arg(Arg::with_name("center")
+                    .long("center")
+                    .value_names(&["RE", "IM"])
+                    .use_delimiter(true)
+                    .allow_hyphen_values(true)
+                    .help("Center the render on this point instead of setting a viewport.")
+                )
+          .arg(Arg::with_name("zoom")
+                    .short("z")
+                    .long("zoom")
+                    .value_names(&["ZOOM"])
+                    .requires("center")
+                    .help("Magnification about --center; 1 shows 1 unit in each direction.")
+                )
+          .group(ArgGroup::with_name("region").args(&["viewport", "center"]))
+          .arg(Arg::with_name("iterations")
+                    .short("i")
+                    .long("iterations")
+                    .value_names(&["N"])
+                    .default_value("255")
+                    .help("Maximum number of iterations per point.")
+                )
+          .arg(Arg::with_name("threshold")
+                    .short("t")
+                    .long("threshold")
+                    .value_names(&["T"])
+                    .help("Escape threshold for the modulus of z. [default: 2, or the escape \
+                           radius of z^D + c with --degree]")
+                )
+          .arg(Arg::with_name("bailout")
+                    .long("bailout")
+                    .value_names(&["KIND:RADIUS"])
+                    .conflicts_with("threshold")
+                    .help("Escape criterion: one of modulus, real, right, imag or manhattan, \
+                           with a radius, as in real:50; right only counts the real part \
+                           growing positive. [default: chosen by the function]")
+                )
+          .arg(Arg::with_name("supersample")
+                    .short("s")
+                    .long("supersample")
+                    .value_names(&["FACTOR"])
+                    .default_value("2")
+                    .help("Render at FACTOR times the output dimensions, then downsample.")
+                )
+          .arg(Arg::with_name("colorizer")
+                    .long("colorizer")
+                    .value_names(&["PALETTE"])
+                    .possible_values(&["hsl", "fire", "ice", "rainbow", "none"])
+                    .default_value("hsl")
+                    .help("Colorize the output with this palette, or not at all.")
+                )
+          .arg(Arg::with_name("convergence")
+                    .long("convergence")
+                    .value_names(&["EPSILON"])
+                    .help("Stop iterating once successive values are closer than EPSILON.")
+                )
+          .arg(Arg::with_name("periodicity")
+                    .long("periodicity")
+                    .value_names(&["EPSILON"])
+                    .min_values(0)
+                    .require_equals(true)
+                    .help("Stop iterating once the orbit returns within EPSILON of an earlier \
+                           value, detecting attracting cycles of any period. Speeds up renders \
+                           with large interiors. [default: --periodicity=1e-10]")
+                )
+          .arg(Arg::with_name("interior")
+                    .long("interior")
+                    .help("Color interior points by the period of the cycle which attracts \
+                           them. Implies --periodicity.")
+                )
+          .arg(Arg::with_name("threads")
+                    .short("j")
+                    .long("threads")
+                    .value_names(&["N"])
+                    .help("Number of worker threads. [default: 4]")
+                )
+          .arg(Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .value_names(&["FORMAT"])
+                    .help("Output image format: png, png16, jpeg[:QUALITY], tiff, webp, ppm or exr.")
+                )
+          .arg(Arg::with_name("levels")
+                    .long("levels")
+                    .value_names(&["N"])
+                    .default_value("8")
+                    .help("Number of contour levels in SVG output.")
+                )
+          .arg(Arg::with_name("smoothing")
+                    .long("smoothing")
+                    .value_names(&["ROUNDS"])
+                    .default_value("2")
+                    .help("Rounds of smoothing applied to contours in SVG output.")
+                )
+          .arg(Arg::with_name("path")
+                    .value_names(&["PATH"])
+                    .default_value("julia_set.png")
+                    .help("Output path.")
+                )
+    }
+
+    /// Construct a new configuration object by reading and parsing the command line.
+    ///
+    /// On failure, reports the error and returns the appropriate exit code.
+    fn new() -> Result<JuliaConfiguration, JuliaResult> {
+        let matches = match JuliaConfiguration::build_cli().get_matches_safe() {
+            Ok(matches) => matches,
+            Err(error) => {
+                println!("{}", error.message);
+                return Err(match error.kind {
+                    ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => JuliaResult::Success,
+                    ErrorKind::InvalidValue |
+                    ErrorKind::ValueValidation |
+                    ErrorKind::InvalidUtf8 => JuliaResult::CantParseIntegerArguments,
+                    _ => JuliaResult::WrongNumberOfArguments,
+                });
+            }
+        };
+        JuliaConfiguration::unpack_matches(&matches).map_err(|message| {
+            println!("{}", message);
+            JuliaResult::CantParseIntegerArguments
+        })
+    }
+
+    fn unpack_matches(matches: &clap::ArgMatches) -> Result<JuliaConfiguration, String> {
+        let dimensions = parse_values::<u32>(matches, "dimensions")?;
+        let (width, height) = (dimensions[0], dimensions[1]);
+        if width < 2 || height < 2 {
+            return Err(String::from("Both dimensions must be at least 2 pixels."));
+        }
+
+        let c = parse_finite(matches, "constant")?;
+        let c = Complex64::new(c[0], c[1]);
+        let multibrot = match matches.value_of("degree") {
+            Some(_) => Some(Multibrot::new(parse_finite(matches, "degree")?[0])?),
+            None => None,
+        };
+        let family = match multibrot {
+            Some(multibrot) => Family::Multibrot(multibrot),
+            None => {
+                let source = matches.value_of("function").unwrap();
+                Family::from_str(source)
+                    .map_err(|e| format!("Couldn't parse function '{}': {}; aborting.", source, e))?
+            }
+        };
+        let plane = match matches.value_of("plane").unwrap().trim() {
+            "parameter" => Plane::Parameter(family.critical_point()),
+            plane => Plane::from_str(plane)?,
+        };
+        let bailout = if matches.is_present("threshold") {
+            let threshold = parse_finite(matches, "threshold")?[0];
+            if threshold <= 0.0 {
+                return Err(String::from("Threshold must be positive."));
+            }
+            Bailout::Modulus(threshold)
+        } else if matches.is_present("bailout") {
+            parse_values::<Bailout>(matches, "bailout")?.remove(0)
+        } else if plane.is_julia() {
+            family.bailout(c)
+        } else {
+            // the orbits of interest start small, so their escape radius is that of c = 0
+            family.bailout(Complex64::new(0.0, 0.0))
+        };
+        let viewport = if matches.is_present("center") {
+            let center = parse_finite(matches, "center")?;
+            let zoom = match matches.value_of("zoom") {
+                Some(_) => parse_finite(matches, "zoom")?[0],
+                None => 1.0,
+            };
+            if zoom <= 0.0 {
+                return Err(String::from("Zoom must be positive."));
+            }
+            Viewport::from_center(Complex64::new(center[0], center[1]), zoom)
+        } else if matches.is_present("viewport") {
+            let vp = parse_finite(matches, "viewport")?;
+            if vp[0] >= vp[1] || vp[2] >= vp[3] {
+                return Err(String::from("Viewport minimums must be less than maximums."));
+            }
+            Viewport::new(vp[0], vp[1], vp[2], vp[3])
+        } else {
+            Viewport::default()
+        };
+
+        let iterations = parse_values::<usize>(matches, "iterations")?[0];
+        let mode = if let Some(coefficients) = matches.value_of("newton") {
+            let polynomial = Polynomial::new(parse_complex_list(coefficients)?)?;
+            Mode::Newton(Newton::new(polynomial, iterations))
+        } else if let Some(roots) = matches.value_of("roots") {
+            Mode::Newton(Newton::from_roots(&parse_complex_list(roots)?, iterations))
+        } else if matches.is_present("phoenix") {
+            let p = parse_finite(matches, "phoenix")?;
+            Mode::Phoenix(Phoenix::new(Complex64::new(p[0], p[1])))
+        } else if matches.is_present("buddhabrot") {
+            let samples = parse_values::<usize>(matches, "buddhabrot")?[0];
+            let channels = match matches.value_of("channels") {
+                Some("nebulabrot") => Channel::nebulabrot(),
+                Some(channels) => {
+                    channels.split(',')
+                        .map(Channel::from_str)
+                        .collect::<Result<Vec<_>, _>>()?
+                }
+                None => vec![Channel::new(0, iterations)],
+            };
+            let mut buddhabrot = Buddhabrot::new(family.clone(), channels, samples);
+            buddhabrot.anti = matches.is_present("anti");
+            Mode::Buddhabrot(buddhabrot)
+        } else if matches.is_present("iim") {
+            let method = parse_values::<Method>(matches, "iim")?.remove(0);
+            Mode::InverseIteration(InverseIteration::new(c, method))
+        } else if matches.is_present("quaternion") {
+            let q = parse_finite(matches, "quaternion")?;
+            let q = Quaternion::new(q[0], q[1], q[2], q[3]);
+            if matches.is_present("raymarch") {
+                let vector = |name, default| match matches.value_of(name) {
+                    Some(_) => {
+                        parse_finite(matches, name).map(|v| Vector3::new(v[0], v[1], v[2]))
+                    }
+                    None => Ok(default),
+                };
+                let mut marcher = RayMarcher::new(QuaternionJulia::new(q));
+                if matches.is_present("section") {
+                    marcher.section = parse_finite(matches, "section")?[0];
+                }
+                marcher.camera.position = vector("camera", marcher.camera.position)?;
+                marcher.camera.target = vector("look-at", marcher.camera.target)?;
+                if (marcher.camera.target - marcher.camera.position).length() == 0.0 {
+                    return Err(String::from("The camera can't look at its own position."));
+                }
+                marcher.lighting.direction = vector("light", marcher.lighting.direction)?;
+                if marcher.lighting.direction.length() == 0.0 {
+                    return Err(String::from("The light must have a direction."));
+                }
+                marcher.lighting.occlusion = !matches.is_present("no-occlusion");
+                if matches.occurrences_of("iterations") > 0 {
+                    marcher.iterations = iterations;
+                }
+                Mode::Raymarch(marcher)
+            } else {
+                Mode::Quaternion(q)
+            }
+        } else {
+            Mode::Escape
+        };
+        // these only make sense alongside --quaternion, so they can't have clap defaults,
+        // which would always be present and so always demand it
+        let slice = match matches.value_of("slice") {
+            Some(_) => parse_values::<Slice>(matches, "slice")?.remove(0),
+            None => Slice::default(),
+        };
+        let voxels = match matches.value_of("voxels") {
+            Some(_) => Some(parse_values::<u32>(matches, "voxels")?[0]),
+            None => None,
+        };
+        let supersample = parse_values::<u32>(matches, "supersample")?[0];
+        let attractors = AttractorTest {
+            convergence: match matches.value_of("convergence") {
+                Some(_) => Some(parse_finite(matches, "convergence")?[0]),
+                None => None,
+            },
+            periodicity: match matches.value_of("periodicity") {
+                Some(_) => Some(parse_finite(matches, "periodicity")?[0]),
+                None if matches.is_present("periodicity") || matches.is_present("interior") => {
+                    Some(AttractorTest::DEFAULT_TOLERANCE)
+                }
+                None => None,
+            },
+        };
+        let threads = match matches.value_of("threads") {
+            Some(_) => parse_values::<usize>(matches, "threads")?[0],
+            None => DEFAULT_THREADS,
+        };
+        if iterations == 0 || supersample == 0 || threads == 0 {
+            return Err(String::from("Iterations, supersampling and threads must be nonzero."));
         }
+
+        let path = PathBuf::from(matches.value_of("path").unwrap());
+        let output = match matches.value_of("format") {
+            Some(format) => Output::Image(OutputFormat::from_str(format)?),
+            None if has_extension(&path, "field") => Output::Field,
+            None if has_extension(&path, "svg") => Output::Svg,
+            None => Output::Image(OutputFormat::from_path(&path).unwrap_or_default()),
+        };
+        let path = match output {
+            Output::Image(format) if OutputFormat::from_path(&path) != Some(format) => {
+                path.with_extension(format.extension())
+            }
+            _ => path,
+        };
+
+        Ok(JuliaConfiguration {
+            width,
+            height,
+            family,
+            c,
+            plane,
+            viewport,
+            iterations,
+            bailout,
+            supersample,
+            colorizer: String::from(matches.value_of("colorizer").unwrap()),
+            attractors,
+            interior: matches.is_present("interior"),
+            stats: matches.is_present("stats"),
+            orbit: match matches.value_of("orbit") {
+                Some(_) => {
+                    let point = parse_finite(matches, "orbit")?;
+                    Some(Complex64::new(point[0], point[1]))
+                }
+                None => None,
+            },
+            threads,
+            contours: ContourOptions {
+                levels: parse_values::<usize>(matches, "levels")?[0],
+                smoothing: parse_values::<usize>(matches, "smoothing")?[0],
+                ..ContourOptions::default()
+            },
+            mode,
+            slice,
+            voxels,
+            output,
+            path,
+        })
     }
 }
+
+/// Parse every value of the named argument, reporting which one failed if any.
+fn parse_values<T: FromStr>(matches: &clap::ArgMatches, name: &str) -> Result<Vec<T>, String> {
+    matches.values_of(name)
+        .unwrap()
+        .map(|value| {
+            T::from_str(value.trim())
+                .map_err(|_| format!("Couldn't parse '{}' for --{}; aborting.", value, name))
+        })
+        .collect()
+}
+
+/// Like `parse_values`, but rejecting infinities and NaN, which no real-valued option
+/// makes sense of.
+fn parse_finite(matches: &clap::ArgMatches, name: &str) -> Result<Vec<f64>, String> {
+    let values = parse_values::<f64>(matches, name)?;
+    if values.iter().any(|value| !value.is_finite()) {
+        return Err(format!("Values for --{} must be finite; aborting.", name));
+    }
+    Ok(values)
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().and_then(|ext| ext.to_str()).map(|s| s.to_lowercase()) ==
+    Some(String::from(extension))
+}
@@ -0,0 +1,445 @@
+//! Render parameters embedded in PNG text chunks.
+//!
+//! An image on its own says nothing about how it was made. Every PNG this crate's
+//! binaries save carries its render parameters as `tEXt` chunks, so that any image can
+//! be identified, and single renders can be reproduced exactly, long after the command
+//! line that made them has been forgotten.
+//!
+//! The `image` crate has no way to write or read ancillary PNG chunks, so this module
+//! splices them into the encoded stream directly. This is easy: `tEXt` chunks may appear
+//! anywhere between `IHDR` and `IEND`, so we just insert them directly after `IHDR`.
+
+use image::{ColorType, DynamicImage, GenericImage};
+use image::png::PNGEncoder;
+use num::complex::Complex64;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use attractor::AttractorTest;
+use bailout::Bailout;
+use expr::Expression;
+use output::{save_image, OutputFormat};
+use plane::Plane;
+use viewport::Viewport;
+
+/// The eight bytes every PNG stream starts with.
+pub const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Prefix for every text chunk keyword this crate writes.
+pub const KEY_PREFIX: &str = "julia_set.";
+
+/// A list of `(keyword, text)` pairs to be stored in or read from PNG text chunks.
+pub type TextChunks = Vec<(String, String)>;
+
+/// Encode `image` as a PNG with the given text chunks.
+pub fn write_png<W: Write>(writer: &mut W,
+                           image: &DynamicImage,
+                           text: &[(String, String)])
+                           -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    write_png_raw(writer, &image.raw_pixels(), width, height, image.color(), text)
+}
+
+/// Encode raw pixel data as a PNG with the given text chunks.
+///
+/// Samples wider than 8 bits must be big-endian.
+pub fn write_png_raw<W: Write>(writer: &mut W,
+                               data: &[u8],
+                               width: u32,
+                               height: u32,
+                               color: ColorType,
+                               text: &[(String, String)])
+                               -> io::Result<()> {
+    let mut encoded = Vec::new();
+    PNGEncoder::new(&mut encoded).encode(data, width, height, color)?;
+
+    // signature, then IHDR: 4 length + 4 type + 13 data + 4 crc
+    let ihdr_end = PNG_SIGNATURE.len() + 25;
+    writer.write_all(&encoded[..ihdr_end])?;
+    for (keyword, value) in text {
+        write_text_chunk(writer, keyword, value)?;
+    }
+    writer.write_all(&encoded[ihdr_end..])
+}
+
+/// Save `image` as a PNG file with the given text chunks.
+pub fn save_png<P: AsRef<Path>>(image: &DynamicImage,
+                                path: P,
+                                text: &[(String, String)])
+                                -> io::Result<()> {
+    let mut buffer = Vec::new();
+    write_png(&mut buffer, image, text)?;
+    File::create(path)?.write_all(&buffer)
+}
+
+/// Read every text chunk from a PNG stream, in order.
+pub fn read_png_text<R: Read>(reader: &mut R) -> io::Result<TextChunks> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a PNG file"));
+    }
+
+    let mut text = Vec::new();
+    let mut position = PNG_SIGNATURE.len();
+    while position + 12 <= bytes.len() {
+        let mut length = [0; 4];
+        length.copy_from_slice(&bytes[position..position + 4]);
+        let length = u32::from_be_bytes(length) as usize;
+        let kind = &bytes[position + 4..position + 8];
+        let data_start = position + 8;
+        if data_start + length + 4 > bytes.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated PNG chunk"));
+        }
+        let data = &bytes[data_start..data_start + length];
+
+        if kind == b"tEXt" {
+            if let Some(split) = data.iter().position(|&b| b == 0) {
+                text.push((latin1(&data[..split]), latin1(&data[split + 1..])));
+            }
+        } else if kind == b"IEND" {
+            break;
+        }
+        position = data_start + length + 4;
+    }
+    Ok(text)
+}
+
+/// Read every text chunk from a PNG file.
+pub fn load_png_text<P: AsRef<Path>>(path: P) -> io::Result<TextChunks> {
+    read_png_text(&mut File::open(path)?)
+}
+
+/// The `Software` text chunk, naming this crate and its version.
+pub fn software() -> (String, String) {
+    (String::from("Software"), format!("julia_set {}", env!("CARGO_PKG_VERSION")))
+}
+
+/// Look up the value of a keyword in a list of text chunks.
+pub fn lookup<'a>(text: &'a [(String, String)], keyword: &str) -> Option<&'a str> {
+    text.iter().find(|(k, _)| k == keyword).map(|(_, v)| v.as_str())
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Write a single `tEXt` chunk.
+pub fn write_text_chunk<W: Write>(writer: &mut W, keyword: &str, value: &str) -> io::Result<()> {
+    let mut data = Vec::with_capacity(keyword.len() + 1 + value.len());
+    data.extend_from_slice(keyword.as_bytes());
+    data.push(0);
+    data.extend_from_slice(value.as_bytes());
+    write_chunk(writer, b"tEXt", &data)
+}
+
+/// Write a PNG chunk of any kind, with its length and CRC.
+pub fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()));
+    writer.write_all(&crc.to_be_bytes())
+}
+
+/// The CRC-32 used by PNG: ISO 3309, polynomial 0xedb88320, reflected.
+fn crc32<'a, I: Iterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = 0xffff_ffff_u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc ^ 0xffff_ffff
+}
+
+/// Everything needed to reproduce a single Julia set render exactly, by passing it to
+/// `render`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderParameters {
+    /// Output dimensions
+    pub width: u32,
+    pub height: u32,
+    /// The image is computed at `supersample` times the output dimensions, then resized.
+    pub supersample: u32,
+    /// The exact viewport used; any aspect-ratio correction has already been applied.
+    pub viewport: Viewport,
+    /// The iteration function, of `z` and `c`
+    pub function: Expression,
+    /// The parameter of the Julia set; unused in the parameter plane
+    pub c: Complex64,
+    /// Whether pixels are starting points or parameters
+    pub plane: Plane,
+    /// The escape criterion; custom criteria can't be recorded.
+    pub bailout: Bailout,
+    /// The maximum number of iterations computed per pixel
+    pub iterations: usize,
+    /// Name of the colorizer: one of `colorize::PALETTES`, or "none"
+    pub colorizer: String,
+    /// Tests for stopping early on interior points
+    pub attractors: AttractorTest,
+    /// Whether to color interior points by the period of their attracting cycle
+    pub interior: bool,
+    /// A point whose orbit is drawn over the render
+    pub orbit: Option<Complex64>,
+}
+
+impl RenderParameters {
+    /// The render mode these parameters describe; images made in any other mode record
+    /// their own mode instead, and can't be reproduced from their text chunks.
+    pub const MODE: &'static str = "escape";
+
+    /// The function used when none is given.
+    pub const DEFAULT_FUNCTION: &'static str = "z^2 + c";
+
+    /// The default iteration function, parsed.
+    pub fn default_function() -> Expression {
+        Expression::parse(RenderParameters::DEFAULT_FUNCTION).unwrap()
+    }
+
+    /// Save a rendered image along with these parameters.
+    ///
+    /// The parameters are only retained if `format` is PNG.
+    pub fn save<P: AsRef<Path>>(&self,
+                                image: &DynamicImage,
+                                path: P,
+                                format: OutputFormat)
+                                -> io::Result<()> {
+        save_image(image, path, format, &self.to_text())
+    }
+
+    /// Represent these parameters as PNG text chunks.
+    pub fn to_text(&self) -> TextChunks {
+        let vp = &self.viewport;
+        let mut text = vec![software(),
+             (key("mode"), String::from(RenderParameters::MODE)),
+             (key("function"), String::from(self.function.source())),
+             (key("c"), format!("{},{}", self.c.re, self.c.im)),
+             (key("size"), format!("{},{}", self.width, self.height)),
+             (key("supersample"), self.supersample.to_string()),
+             (key("viewport"), format!("{},{},{},{}", vp.min_x, vp.max_x, vp.min_y, vp.max_y)),
+             (key("bailout"), self.bailout.to_string()),
+             (key("iterations"), self.iterations.to_string()),
+             (key("colorizer"), self.colorizer.clone())];
+        if let Some(epsilon) = self.attractors.convergence {
+            text.push((key("convergence"), epsilon.to_string()));
+        }
+        if let Some(epsilon) = self.attractors.periodicity {
+            text.push((key("periodicity"), epsilon.to_string()));
+        }
+        if self.interior {
+            text.push((key("interior"), String::from("period")));
+        }
+        if !self.plane.is_julia() {
+            text.push((key("plane"), self.plane.to_string()));
+        }
+        if let Some(point) = self.orbit {
+            text.push((key("orbit"), format!("{},{}", point.re, point.im)));
+        }
+        text
+    }
+
+    /// Reconstruct render parameters from PNG text chunks.
+    pub fn from_text(text: &[(String, String)]) -> Result<RenderParameters, String> {
+        let get = |name: &str| {
+            lookup(text, &key(name)).ok_or_else(|| format!("missing text chunk '{}'", key(name)))
+        };
+
+        match get("mode")? {
+            RenderParameters::MODE => {}
+            mode => {
+                return Err(format!("this is a {} render; only escape-time renders can be \
+                                    re-rendered",
+                                   mode))
+            }
+        }
+        let function = get("function")?;
+        let function = Expression::parse(function)
+            .map_err(|e| format!("can't parse function '{}': {}", function, e))?;
+
+        let c = parse_list::<f64>(get("c")?, 2)?;
+        let size = parse_list::<u32>(get("size")?, 2)?;
+        let vp = parse_list::<f64>(get("viewport")?, 4)?;
+
+        Ok(RenderParameters {
+            width: size[0],
+            height: size[1],
+            supersample: parse_list::<u32>(get("supersample")?, 1)?[0],
+            viewport: Viewport::new(vp[0], vp[1], vp[2], vp[3]),
+            function,
+            c: Complex64::new(c[0], c[1]),
+            plane: match lookup(text, &key("plane")) {
+                Some(plane) => Plane::from_str(plane)?,
+                None => Plane::Julia,
+            },
+            bailout: Bailout::from_str(get("bailout")?)?,
+            iterations: parse_list::<usize>(get("iterations")?, 1)?[0],
+            colorizer: String::from(get("colorizer")?),
+            attractors: AttractorTest {
+                convergence: optional(text, "convergence")?,
+                periodicity: optional(text, "periodicity")?,
+            },
+            interior: lookup(text, &key("interior")).is_some(),
+            orbit: match lookup(text, &key("orbit")) {
+                Some(point) => {
+                    let point = parse_list::<f64>(point, 2)?;
+                    Some(Complex64::new(point[0], point[1]))
+                }
+                None => None,
+            },
+        })
+    }
+
+    /// Read render parameters from a PNG file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<RenderParameters, String> {
+        let text = load_png_text(path).map_err(|e| e.to_string())?;
+        RenderParameters::from_text(&text)
+    }
+}
+
+/// Qualify a keyword with this crate's prefix.
+pub fn key(name: &str) -> String {
+    format!("{}{}", KEY_PREFIX, name)
+}
+
+/// Parse the single value of a text chunk which may be absent.
+fn optional<T: FromStr>(text: &[(String, String)], name: &str) -> Result<Option<T>, String> {
+    match lookup(text, &key(name)) {
+        Some(value) => Ok(Some(parse_list::<T>(value, 1)?.remove(0))),
+        None => Ok(None),
+    }
+}
+
+fn parse_list<T: FromStr>(value: &str, count: usize) -> Result<Vec<T>, String> {
+    let items = value.split(',')
+        .map(|item| T::from_str(item.trim()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("couldn't parse '{}'", value))?;
+    if items.len() != count {
+        return Err(format!("expected {} values in '{}'", count, value));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use family::Family;
+    use image;
+    use render;
+
+    fn params() -> RenderParameters {
+        RenderParameters {
+            width: 40,
+            height: 30,
+            supersample: 2,
+            viewport: Viewport::default().rectilinear(80, 60),
+            function: RenderParameters::default_function(),
+            c: Complex64::new(-0.221, -0.713),
+            plane: Plane::Julia,
+            bailout: Bailout::Modulus(2.0),
+            iterations: 255,
+            colorizer: String::from("hsl"),
+            attractors: AttractorTest::default(),
+            interior: false,
+            orbit: None,
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        // the CRC of an IEND chunk is a well-known constant
+        assert_eq!(crc32(b"IEND".iter()), 0xae42_6082);
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        let params = params();
+        let image = render(&params, 2);
+        let mut bytes = Vec::new();
+        write_png(&mut bytes, &image, &params.to_text()).unwrap();
+
+        let text = read_png_text(&mut &bytes[..]).unwrap();
+        assert_eq!(RenderParameters::from_text(&text), Ok(params));
+
+        // the chunks mustn't disturb the image itself
+        let decoded = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(decoded.raw_pixels(), image.raw_pixels());
+    }
+
+    #[test]
+    fn test_rerender_is_exact() {
+        let params = params();
+        let text = params.to_text();
+        let again = RenderParameters::from_text(&text).unwrap();
+        assert_eq!(render(&params, 2).raw_pixels(), render(&again, 2).raw_pixels());
+    }
+
+    #[test]
+    fn test_attractors_roundtrip() {
+        let interior = RenderParameters {
+            attractors: AttractorTest {
+                convergence: Some(AttractorTest::DEFAULT_TOLERANCE),
+                periodicity: Some(AttractorTest::DEFAULT_TOLERANCE),
+            },
+            interior: true,
+            ..params()
+        };
+        assert_eq!(RenderParameters::from_text(&interior.to_text()), Ok(interior));
+    }
+
+    #[test]
+    fn test_other_modes_are_refused() {
+        let mut text = params().to_text();
+        text.retain(|(k, _)| *k != key("mode"));
+        assert!(RenderParameters::from_text(&text).unwrap_err().contains("julia_set.mode"));
+        text.push((key("mode"), String::from("newton")));
+        assert!(RenderParameters::from_text(&text).unwrap_err().contains("newton render"));
+    }
+
+    #[test]
+    fn test_custom_function_roundtrip() {
+        let params = RenderParameters {
+            function: Expression::parse("z^3 + c*sin(z)").unwrap(),
+            ..params()
+        };
+        let again = RenderParameters::from_text(&params.to_text()).unwrap();
+        assert_eq!(again.function.source(), "z^3 + c*sin(z)");
+        assert_eq!(render(&params, 2).raw_pixels(), render(&again, 2).raw_pixels());
+    }
+
+    #[test]
+    fn test_parameter_plane_roundtrip() {
+        let ship = RenderParameters {
+            function: Family::BurningShip.expression(),
+            plane: Plane::Parameter(Family::BurningShip.critical_point()),
+            interior: true,
+            attractors: AttractorTest {
+                convergence: None,
+                periodicity: Some(AttractorTest::DEFAULT_TOLERANCE),
+            },
+            ..params()
+        };
+        let again = RenderParameters::from_text(&ship.to_text()).unwrap();
+        assert_eq!(again, ship);
+        assert_eq!(render(&ship, 2).raw_pixels(), render(&again, 2).raw_pixels());
+        // the parameter plane doesn't depend on c
+        let elsewhere = RenderParameters { c: Complex64::new(0.3, 0.0), ..ship.clone() };
+        assert_eq!(render(&ship, 2).raw_pixels(), render(&elsewhere, 2).raw_pixels());
+    }
+
+    #[test]
+    fn test_orbit_overlay() {
+        let traced = RenderParameters { orbit: Some(Complex64::new(0.1, 0.2)), ..params() };
+        let again = RenderParameters::from_text(&traced.to_text()).unwrap();
+        assert_eq!(again, traced);
+        assert_eq!(render(&traced, 2).raw_pixels(), render(&again, 2).raw_pixels());
+    }
+}
@@ -0,0 +1,135 @@
+//! The multibrot family `f(z) = z^d + c`.
+//!
+//! For `d = 2` this is the ordinary quadratic family. Higher integer degrees produce sets
+//! with `d - 1`-fold rotational symmetry; non-integer degrees use the principal branch of
+//! `z^d`, and so have a seam along the negative real axis.
+
+use num::complex::Complex64;
+
+use expr::{powi, Expression};
+
+/// The Julia sets of `f(z) = z^d + c` for a fixed degree `d > 1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Multibrot {
+    degree: f64,
+}
+
+impl Multibrot {
+    /// Construct the family of the given degree, which must be finite and greater than 1.
+    pub fn new(degree: f64) -> Result<Multibrot, String> {
+        if degree.is_finite() && degree > 1.0 {
+            Ok(Multibrot { degree })
+        } else {
+            Err(format!("multibrot degree must be greater than 1, not {}", degree))
+        }
+    }
+
+    pub fn degree(&self) -> f64 {
+        self.degree
+    }
+
+    /// Whether the degree is a whole number, in which case `z^d` is computed by
+    /// repeated multiplication.
+    pub fn is_integral(&self) -> bool {
+        self.degree.fract() == 0.0 && self.degree <= i32::MAX as f64
+    }
+
+    /// The smallest radius beyond which every orbit of `z^d + c` is guaranteed to escape.
+    ///
+    /// If `|z| > max(|c|, 2^(1/(d-1)))`, then `|z^d + c| >= |z|^d - |c| > |z| (|z|^(d-1) - 1)
+    /// > |z|`, and the orbit grows without bound. For `d = 2` this is the familiar 2.
+    pub fn escape_radius(&self, c: Complex64) -> f64 {
+        c.norm().max(2f64.powf(1.0 / (self.degree - 1.0)))
+    }
+
+    /// The iteration function for a particular `c`.
+    pub fn bind(&self, c: Complex64) -> Box<dyn Fn(Complex64) -> Complex64 + Send + Sync> {
+        if self.is_integral() {
+            let degree = self.degree as i32;
+            Box::new(move |z| powi(z, degree) + c)
+        } else {
+            let degree = self.degree;
+            Box::new(move |z: Complex64| {
+                if z.re == 0.0 && z.im == 0.0 {
+                    c
+                } else {
+                    z.powf(degree) + c
+                }
+            })
+        }
+    }
+
+    /// Apply the function for `c` to `z`.
+    pub fn eval(&self, z: Complex64, c: Complex64) -> Complex64 {
+        if self.is_integral() {
+            powi(z, self.degree as i32) + c
+        } else if z.re == 0.0 && z.im == 0.0 {
+            c
+        } else {
+            z.powf(self.degree) + c
+        }
+    }
+
+    /// The derivative `d·z^(d-1)` with respect to `z`, which doesn't depend on `c`.
+    pub fn derivative(&self, z: Complex64) -> Complex64 {
+        if self.is_integral() {
+            powi(z, self.degree as i32 - 1) * self.degree
+        } else if z.re == 0.0 && z.im == 0.0 {
+            z
+        } else {
+            z.powf(self.degree - 1.0) * self.degree
+        }
+    }
+
+    /// This family as an expression of `z` and `c`, for recording in render parameters.
+    pub fn expression(&self) -> Expression {
+        Expression::parse(&format!("z^{} + c", self.degree)).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{applications_until, default_julia};
+
+    #[test]
+    fn test_degree_validation() {
+        assert!(Multibrot::new(1.0).is_err());
+        assert!(Multibrot::new(-3.0).is_err());
+        assert!(Multibrot::new(f64::NAN).is_err());
+        assert!(Multibrot::new(3.0).unwrap().is_integral());
+        assert!(!Multibrot::new(2.5).unwrap().is_integral());
+    }
+
+    #[test]
+    fn test_escape_radius() {
+        let quadratic = Multibrot::new(2.0).unwrap();
+        assert_eq!(quadratic.escape_radius(Complex64::new(-0.221, -0.713)), 2.0);
+        assert_eq!(quadratic.escape_radius(Complex64::new(3.0, 4.0)), 5.0);
+        let cubic = Multibrot::new(3.0).unwrap();
+        assert!((cubic.escape_radius(Complex64::new(0.0, 0.0)) - 2f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_quadratic_matches_default_julia() {
+        let c = Complex64::new(-0.221, -0.713);
+        let f = Multibrot::new(2.0).unwrap().bind(c);
+        for &(re, im) in &[(0.0, 0.0), (-1.0, 1.0), (0.0, 1.0), (1.0, -1.0)] {
+            let z = Complex64::new(re, im);
+            assert_eq!(applications_until(z, &*f, 2.0, Some(256)),
+                       applications_until(z, &default_julia, 2.0, Some(256)));
+        }
+    }
+
+    #[test]
+    fn test_bind_agrees_with_expression() {
+        let c = Complex64::new(0.3, -0.5);
+        let z = Complex64::new(0.4, 0.7);
+        for &degree in &[3.0, 5.0, 8.0, 2.5, 3.75] {
+            let family = Multibrot::new(degree).unwrap();
+            let direct = family.bind(c)(z);
+            let parsed = family.expression().eval(z, c);
+            assert!((direct - parsed).norm() < 1e-12, "degree {}", degree);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Newton's-method fractals.
+//!
+//! Newton's method for finding a root of a polynomial `p` iterates
+//! `z ↦ z - p(z) / p'(z)`. From most starting points this converges to one of the roots;
+//! which one depends on the starting point in an intricate way, and the boundaries
+//! between the basins of attraction of the roots form a fractal.
+//!
+//! Instead of counting iterations until escape, a Newton render records for each pixel
+//! the root it converges to and how many steps that took. `RootColorizer` then gives each
+//! root's basin its own color, shaded by the number of steps.
+
+use image::{ImageBuffer, LumaA};
+use num::complex::Complex64;
+use std::fmt;
+
+use expr::Expression;
+use super::parallel_rows;
+use viewport::Viewport;
+
+/// A polynomial with complex coefficients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    /// Coefficients from the highest degree down to the constant term; the first is
+    /// never zero.
+    coefficients: Vec<Complex64>,
+}
+
+impl Polynomial {
+    /// Construct a polynomial from its coefficients, highest degree first: `z^3 - 1` is
+    /// `[1, 0, 0, -1]`. Leading zeros are ignored; what's left must have degree at least 1.
+    pub fn new(coefficients: Vec<Complex64>) -> Result<Polynomial, String> {
+        let zero = Complex64::new(0.0, 0.0);
+        let coefficients = coefficients.into_iter().skip_while(|&a| a == zero).collect::<Vec<_>>();
+        if coefficients.len() < 2 {
+            return Err(String::from("polynomial must have degree at least 1"));
+        }
+        Ok(Polynomial { coefficients })
+    }
+
+    /// Construct the monic polynomial with exactly the given roots.
+    pub fn from_roots(roots: &[Complex64]) -> Polynomial {
+        let mut coefficients = vec![Complex64::new(1.0, 0.0)];
+        for &root in roots {
+            // multiply by (z - root)
+            coefficients.push(Complex64::new(0.0, 0.0));
+            for i in (1..coefficients.len()).rev() {
+                let previous = coefficients[i - 1];
+                coefficients[i] -= root * previous;
+            }
+        }
+        Polynomial { coefficients }
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    pub fn coefficients(&self) -> &[Complex64] {
+        &self.coefficients
+    }
+
+    /// Evaluate `p(z)` and `p'(z)` together by Horner's method.
+    pub fn eval_with_derivative(&self, z: Complex64) -> (Complex64, Complex64) {
+        let mut p = Complex64::new(0.0, 0.0);
+        let mut dp = Complex64::new(0.0, 0.0);
+        for &a in &self.coefficients {
+            dp = dp * z + p;
+            p = p * z + a;
+        }
+        (p, dp)
+    }
+
+    /// Approximate every root, with multiplicity, by the Durand–Kerner method.
+    pub fn roots(&self) -> Vec<Complex64> {
+        const MAX_ROUNDS: usize = 1000;
+        const EPSILON: f64 = 1e-14;
+
+        let leading = self.coefficients[0];
+        let monic = Polynomial {
+            coefficients: self.coefficients.iter().map(|&a| a / leading).collect(),
+        };
+        let eval = |z| monic.eval_with_derivative(z).0;
+
+        // the customary initial guesses: powers of a number which is neither real nor a
+        // root of unity
+        let seed = Complex64::new(0.4, 0.9);
+        let mut roots = Vec::with_capacity(self.degree());
+        let mut guess = Complex64::new(1.0, 0.0);
+        for _ in 0..self.degree() {
+            roots.push(guess);
+            guess *= seed;
+        }
+
+        for _ in 0..MAX_ROUNDS {
+            let mut change: f64 = 0.0;
+            for i in 0..roots.len() {
+                let mut denominator = Complex64::new(1.0, 0.0);
+                for (j, &other) in roots.iter().enumerate() {
+                    if i != j {
+                        denominator *= roots[i] - other;
+                    }
+                }
+                if denominator.norm_sqr() == 0.0 {
+                    continue;
+                }
+                let step = eval(roots[i]) / denominator;
+                roots[i] -= step;
+                change = change.max(step.norm());
+            }
+            if change < EPSILON {
+                break;
+            }
+        }
+        roots
+    }
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // adding zero turns -0 into 0
+        let terms = self.coefficients
+            .iter()
+            .map(|a| if a.im == 0.0 {
+                format!("{}", a.re + 0.0)
+            } else {
+                format!("{}{:+}i", a.re + 0.0, a.im)
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", terms.join(","))
+    }
+}
+
+/// Parse a comma-separated list of constant complex values, such as
+/// `1, -0.5+0.866i, -0.5-0.866i`. Each value may be any constant expression.
+pub fn parse_complex_list(list: &str) -> Result<Vec<Complex64>, String> {
+    list.split(',')
+        .map(|item| {
+            let expression = Expression::parse(item).map_err(|e| format!("'{}': {}", item, e))?;
+            expression.constant().ok_or_else(|| format!("'{}' is not a constant", item.trim()))
+        })
+        .collect()
+}
+
+/// Where a starting point ends up under Newton's method.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Basin {
+    /// The index of the root converged to, or `None` if it never did.
+    pub root: Option<usize>,
+    /// The number of steps taken to come within tolerance of the root.
+    pub steps: usize,
+}
+
+/// Newton's method applied to a particular polynomial.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Newton {
+    polynomial: Polynomial,
+    roots: Vec<Complex64>,
+    /// How close to a root counts as converged
+    pub tolerance: f64,
+    /// The maximum number of steps taken per point
+    pub iterations: usize,
+}
+
+impl Newton {
+    pub const DEFAULT_TOLERANCE: f64 = 1e-6;
+
+    /// Newton's method for `polynomial`, whose roots are found numerically.
+    pub fn new(polynomial: Polynomial, iterations: usize) -> Newton {
+        // Repeated roots share a basin, so keep only one of each. They're also found less
+        // accurately than simple roots, so this has to be fairly generous.
+        const SAME_ROOT: f64 = 1e-4;
+        let mut distinct: Vec<Complex64> = Vec::with_capacity(polynomial.degree());
+        for root in polynomial.roots() {
+            if distinct.iter().all(|&r| (r - root).norm() >= SAME_ROOT) {
+                distinct.push(root);
+            }
+        }
+        Newton {
+            polynomial,
+            roots: distinct,
+            tolerance: Newton::DEFAULT_TOLERANCE,
+            iterations,
+        }
+    }
+
+    /// Newton's method for the monic polynomial with exactly these roots.
+    pub fn from_roots(roots: &[Complex64], iterations: usize) -> Newton {
+        let mut distinct: Vec<Complex64> = Vec::with_capacity(roots.len());
+        for &root in roots {
+            if !distinct.contains(&root) {
+                distinct.push(root);
+            }
+        }
+        Newton {
+            polynomial: Polynomial::from_roots(roots),
+            roots: distinct,
+            tolerance: Newton::DEFAULT_TOLERANCE,
+            iterations,
+        }
+    }
+
+    pub fn polynomial(&self) -> &Polynomial {
+        &self.polynomial
+    }
+
+    /// The distinct roots, in the order `Basin::root` indexes them.
+    pub fn roots(&self) -> &[Complex64] {
+        &self.roots
+    }
+
+    /// Follow Newton's method from `z` until it comes within tolerance of a root.
+    pub fn basin(&self, z: Complex64) -> Basin {
+        let tolerance = self.tolerance * self.tolerance;
+        let mut z = z;
+        for steps in 0..self.iterations {
+            if let Some(root) = self.roots.iter().position(|&r| (z - r).norm_sqr() < tolerance) {
+                return Basin {
+                    root: Some(root),
+                    steps,
+                };
+            }
+            let (p, dp) = self.polynomial.eval_with_derivative(z);
+            if dp.norm_sqr() == 0.0 {
+                break;
+            }
+            z -= p / dp;
+        }
+        Basin {
+            root: None,
+            steps: self.iterations,
+        }
+    }
+
+    /// Compute the basin of every pixel in parallel on `threads` threads.
+    pub fn render(&self,
+                  width: u32,
+                  height: u32,
+                  viewport: &Viewport,
+                  threads: usize)
+                  -> Vec<Basin> {
+        let interpolate = viewport.interpolate(width, height);
+        parallel_rows(width, height, threads, &|x, y| self.basin(interpolate(x, y)))
+    }
+
+    /// Compute an image for `RootColorizer` in parallel on `threads` threads.
+    ///
+    /// The luma channel of each pixel is one more than the index of the root it converged
+    /// to, or 0 if it didn't; the alpha channel is a shade which falls off with the number
+    /// of steps taken.
+    pub fn render_image(&self,
+                        width: u32,
+                        height: u32,
+                        viewport: &Viewport,
+                        threads: usize)
+                        -> ImageBuffer<LumaA<u8>, Vec<u8>> {
+        let basins = self.render(width, height, viewport, threads);
+        // convergence is quick, so shade logarithmically to keep the detail visible
+        let scale = ((self.iterations + 1) as f64).ln();
+        ImageBuffer::from_fn(width, height, |x, y| {
+            let basin = basins[(y * width + x) as usize];
+            match basin.root {
+                Some(root) if root < u8::MAX as usize => {
+                    let shade = 1.0 - ((basin.steps + 1) as f64).ln() / scale;
+                    LumaA([root as u8 + 1, (shade * u8::MAX as f64).round() as u8])
+                }
+                _ => LumaA([0, 0]),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube_roots_of_unity() -> Vec<Complex64> {
+        (0..3)
+            .map(|k| Complex64::from_polar(&1.0, &(2.0 * ::std::f64::consts::PI * k as f64 / 3.0)))
+            .collect()
+    }
+
+    #[test]
+    fn test_from_roots() {
+        let p = Polynomial::from_roots(&cube_roots_of_unity());
+        let expected = [1.0, 0.0, 0.0, -1.0];
+        for (a, &e) in p.coefficients().iter().zip(expected.iter()) {
+            assert!((a - e).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_roots() {
+        let p = Polynomial::new(parse_complex_list("1, 0, 0, -1").unwrap()).unwrap();
+        let mut found = p.roots();
+        for root in cube_roots_of_unity() {
+            let nearest = found.iter()
+                .cloned()
+                .enumerate()
+                .min_by(|a, b| (a.1 - root).norm().partial_cmp(&(b.1 - root).norm()).unwrap())
+                .unwrap();
+            assert!((nearest.1 - root).norm() < 1e-9);
+            found.remove(nearest.0);
+        }
+        assert!(Polynomial::new(parse_complex_list("0, 0, 3").unwrap()).is_err());
+        assert_eq!(p.to_string(), "1,0,0,-1");
+        assert_eq!(Polynomial::new(parse_complex_list(&p.to_string()).unwrap()), Ok(p));
+        let q = Polynomial::new(vec![Complex64::new(1.0, 0.0),
+                                     Complex64::new(-0.0, -0.0),
+                                     Complex64::new(-0.0, -2.0)]);
+        assert_eq!(q.unwrap().to_string(), "1,0,0-2i");
+    }
+
+    #[test]
+    fn test_basin() {
+        let newton = Newton::from_roots(&cube_roots_of_unity(), 64);
+        assert_eq!(newton.basin(Complex64::new(1.0, 0.0)),
+                   Basin {
+                       root: Some(0),
+                       steps: 0,
+                   });
+        let basin = newton.basin(Complex64::new(2.0, 0.1));
+        assert_eq!(basin.root, Some(0));
+        assert!(basin.steps > 0);
+        // the origin is a critical point, where Newton's method can't even start
+        assert_eq!(newton.basin(Complex64::new(0.0, 0.0)).root, None);
+    }
+
+    #[test]
+    fn test_repeated_roots_share_a_basin() {
+        let newton = Newton::new(Polynomial::new(parse_complex_list("1, -2, 1").unwrap())
+                                     .unwrap(),
+                                 200);
+        assert_eq!(newton.roots().len(), 1);
+        assert_eq!(newton.basin(Complex64::new(3.0, 1.0)).root, Some(0));
+    }
+}
@@ -0,0 +1,636 @@
+//! Saving rendered images in a variety of formats.
+//!
+//! The `image` crate only knows how to pick an encoder from a file extension, and only
+//! has encoders for a handful of formats. This module adds an explicit `OutputFormat`
+//! which can be chosen either from an extension or by name, and fills in the formats
+//! which are missing:
+//!
+//! - PNG, with render parameters in its text chunks
+//! - JPEG, with a configurable quality
+//! - TIFF, uncompressed baseline
+//! - WebP, lossless
+//! - PPM
+//! - 16-bit grayscale PNG of the escape values of an `EscapeField`, 256 times finer than
+//!   an 8-bit render
+//! - OpenEXR, uncompressed 32-bit float scanlines; the only format which can hold the
+//!   continuous values of an `EscapeField` without quantizing them
+//!
+//! The last two only make sense for the escape values behind an image, which an 8-bit
+//! image has already lost, so they can only be written with `write_field`.
+//!
+//! PNG and TIFF can also be written a band of rows at a time with a `RowWriter`, for
+//! images too large to hold in memory.
+
+extern crate flate2;
+extern crate image_webp;
+
+use image::{ColorType, DynamicImage, GenericImage};
+use image::jpeg::JPEGEncoder;
+use image::ppm::PPMEncoder;
+use self::flate2::Compression;
+use self::flate2::write::ZlibEncoder;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+use std::str::FromStr;
+
+use field::EscapeField;
+use metadata::{write_chunk, write_png_raw, write_text_chunk, PNG_SIGNATURE};
+
+/// The JPEG quality used when none is specified.
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// A supported output image format.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    /// 16-bit grayscale PNG of escape values
+    Png16,
+    /// JPEG with the given quality in 1..100
+    Jpeg(u8),
+    Tiff,
+    WebP,
+    Ppm,
+    Exr,
+}
+
+impl OutputFormat {
+    /// Every format name accepted by `from_str`, for use in help messages.
+    pub const NAMES: &'static [&'static str] =
+        &["png", "png16", "jpeg[:QUALITY]", "tiff", "webp", "ppm", "exr"];
+
+    /// Choose a format from the extension of a path, if it has a recognized one.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<OutputFormat> {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| OutputFormat::from_str(ext).ok())
+    }
+
+    /// The canonical file extension for this format.
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            OutputFormat::Png | OutputFormat::Png16 => "png",
+            OutputFormat::Jpeg(_) => "jpg",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Ppm => "ppm",
+            OutputFormat::Exr => "exr",
+        }
+    }
+
+    /// Whether this format holds the escape values of an `EscapeField`, rather than the
+    /// pixels of an image.
+    pub fn holds_field(&self) -> bool {
+        matches!(*self, OutputFormat::Png16 | OutputFormat::Exr)
+    }
+
+    /// Whether images in this format can be written a band of rows at a time.
+    pub fn streams(&self) -> bool {
+        matches!(*self, OutputFormat::Png | OutputFormat::Tiff)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    /// Parse a format name or extension, case-insensitively.
+    ///
+    /// JPEG accepts an optional quality: `jpeg:85`.
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        let s = s.to_lowercase();
+        let mut parts = s.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let option = parts.next();
+
+        let format = match name {
+            "png" => OutputFormat::Png,
+            "png16" => OutputFormat::Png16,
+            "jpg" | "jpeg" => {
+                let quality = match option {
+                    Some(q) => {
+                        match u8::from_str(q) {
+                            Ok(q) if (1..=100).contains(&q) => q,
+                            _ => return Err(format!("JPEG quality must be in 1..100, not '{}'", q)),
+                        }
+                    }
+                    None => DEFAULT_JPEG_QUALITY,
+                };
+                return Ok(OutputFormat::Jpeg(quality));
+            }
+            "tif" | "tiff" => OutputFormat::Tiff,
+            "webp" => OutputFormat::WebP,
+            "ppm" => OutputFormat::Ppm,
+            "exr" => OutputFormat::Exr,
+            _ => {
+                return Err(format!("unknown output format '{}'; expected one of {}",
+                                   s,
+                                   OutputFormat::NAMES.join(", ")))
+            }
+        };
+        if option.is_some() {
+            return Err(format!("output format '{}' takes no options", name));
+        }
+        Ok(format)
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OutputFormat::Png16 => write!(f, "png16"),
+            OutputFormat::Jpeg(quality) => write!(f, "jpeg:{}", quality),
+            _ => write!(f, "{}", self.extension()),
+        }
+    }
+}
+
+/// Encode `image` in the given format, which mustn't be one which holds a field.
+///
+/// `text` is stored in the PNG text chunks if the format is PNG; other formats ignore it.
+pub fn write_image<W: Write>(writer: &mut W,
+                             image: &DynamicImage,
+                             format: OutputFormat,
+                             text: &[(String, String)])
+                             -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let color = image.color();
+    let data = image.raw_pixels();
+    let channels = data.len() / (width as usize * height as usize).max(1);
+
+    match format {
+        OutputFormat::Png => write_png_raw(writer, &data, width, height, color, text),
+        OutputFormat::Png16 | OutputFormat::Exr => {
+            Err(io::Error::new(io::ErrorKind::InvalidInput,
+                               format!("{} output holds the escape values of a Julia set, \
+                                        not an image",
+                                       format)))
+        }
+        OutputFormat::Jpeg(quality) => {
+            JPEGEncoder::new_with_quality(writer, quality).encode(&data, width, height, color)
+        }
+        OutputFormat::Tiff => write_tiff(writer, &data, width, height, channels),
+        OutputFormat::WebP => {
+            let color = match channels {
+                1 => image_webp::ColorType::L8,
+                2 => image_webp::ColorType::La8,
+                3 => image_webp::ColorType::Rgb8,
+                _ => image_webp::ColorType::Rgba8,
+            };
+            image_webp::WebPEncoder::new(writer)
+                .encode(&data, width, height, color)
+                .map_err(|e| io::Error::other(e.to_string()))
+        }
+        OutputFormat::Ppm => PPMEncoder::new(writer).encode(&data, width, height, color),
+    }
+}
+
+/// Save `image` to a file in the given format.
+///
+/// See `write_image` for details.
+pub fn save_image<P: AsRef<Path>>(image: &DynamicImage,
+                                  path: P,
+                                  format: OutputFormat,
+                                  text: &[(String, String)])
+                                  -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_image(&mut writer, image, format, text)?;
+    writer.flush()
+}
+
+/// Encode the escape values of `field` in the given format.
+///
+/// PNG16 maps the iteration limit onto full brightness, and EXR stores the values as they
+/// are, in a single channel. Any other format gets the grayscale image of
+/// `EscapeField::to_image`. PNGs carry the field's header in their text chunks.
+pub fn write_field<W: Write>(writer: &mut W,
+                             field: &EscapeField,
+                             format: OutputFormat)
+                             -> io::Result<()> {
+    let (width, height) = (field.header.width, field.header.height);
+    let text = field.header.to_text();
+    match format {
+        OutputFormat::Png16 => {
+            let scale = u16::MAX as f64 / field.header.iterations.max(1) as f64;
+            // PNG is big-endian
+            let data = field.data
+                .iter()
+                .flat_map(|&v| {
+                    ((v * scale).floor().clamp(0.0, u16::MAX as f64) as u16).to_be_bytes()
+                })
+                .collect::<Vec<_>>();
+            write_png_raw(writer, &data, width, height, ColorType::Gray(16), &text)
+        }
+        OutputFormat::Exr => {
+            let values = field.data.iter().map(|&v| v as f32).collect::<Vec<_>>();
+            write_exr(writer, width, height, &[("Y", &values)])
+        }
+        _ => write_image(writer, &DynamicImage::ImageLuma8(field.to_image()), format, &text),
+    }
+}
+
+/// Save the escape values of `field` to a file in the given format.
+///
+/// See `write_field` for details.
+pub fn save_field<P: AsRef<Path>>(field: &EscapeField,
+                                  path: P,
+                                  format: OutputFormat)
+                                  -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_field(&mut writer, field, format)?;
+    writer.flush()
+}
+
+/// Write an uncompressed, single-strip, little-endian baseline TIFF.
+fn write_tiff<W: Write>(writer: &mut W,
+                        data: &[u8],
+                        width: u32,
+                        height: u32,
+                        channels: usize)
+                        -> io::Result<()> {
+    write_tiff_header(writer, width, height, channels)?;
+    writer.write_all(data)
+}
+
+/// Whether an image with `channels` 8-bit samples per pixel is small enough for a
+/// classic TIFF, whose offsets are 32 bits.
+pub fn fits_tiff(width: u32, height: u32, channels: usize) -> bool {
+    write_tiff_header(&mut io::sink(), width, height, channels).is_ok()
+}
+
+/// Write everything in a TIFF before the pixel data. The data is uncompressed, so its
+/// length is known in advance, and it can follow in as many pieces as convenient.
+fn write_tiff_header<W: Write>(writer: &mut W,
+                               width: u32,
+                               height: u32,
+                               channels: usize)
+                               -> io::Result<()> {
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+
+    let too_large = || {
+        io::Error::new(io::ErrorKind::InvalidInput, "image too large for classic TIFF")
+    };
+    let data_len = (width as u64)
+        .checked_mul(height as u64)
+        .and_then(|len| len.checked_mul(channels as u64))
+        .ok_or_else(too_large)?;
+    let channels = channels as u32;
+    let photometric = if channels >= 3 { 2 } else { 1 }; // RGB or BlackIsZero
+    // tags must be in ascending order
+    let mut tags: Vec<(u16, u16, u32, u32)> = vec![(256, LONG, 1, width),
+                                                   (257, LONG, 1, height),
+                                                   (258, SHORT, channels, 8),
+                                                   (259, SHORT, 1, 1),
+                                                   (262, SHORT, 1, photometric),
+                                                   (273, LONG, 1, 0),
+                                                   (277, SHORT, 1, channels),
+                                                   (278, LONG, 1, height),
+                                                   (279, LONG, 1, 0),
+                                                   (284, SHORT, 1, 1)];
+    if channels == 2 || channels == 4 {
+        // ExtraSamples: unassociated alpha
+        tags.push((338, SHORT, 1, 2));
+    }
+
+    let ifd_len = 2 + 12 * tags.len() as u32 + 4;
+    // BitsPerSample only fits inline for up to two channels
+    let bits_offset = 8 + ifd_len;
+    let bits_len = if channels > 2 { 2 * channels } else { 0 };
+    let data_offset = bits_offset + bits_len;
+    // the whole file must be addressable, not just the data
+    if data_offset as u64 + data_len > u32::MAX as u64 {
+        return Err(too_large());
+    }
+
+    writer.write_all(b"II*\0")?;
+    writer.write_all(&8_u32.to_le_bytes())?;
+    writer.write_all(&(tags.len() as u16).to_le_bytes())?;
+    for &(tag, kind, count, value) in &tags {
+        writer.write_all(&tag.to_le_bytes())?;
+        writer.write_all(&kind.to_le_bytes())?;
+        writer.write_all(&count.to_le_bytes())?;
+        let value = match tag {
+            258 if channels > 2 => bits_offset,
+            273 => data_offset,
+            279 => data_len as u32,
+            _ => value,
+        };
+        if kind == SHORT && !(tag == 258 && channels > 2) {
+            // short values are left-justified within the four-byte value field
+            for _ in 0..count.min(2) {
+                writer.write_all(&(value as u16).to_le_bytes())?;
+            }
+            for _ in count.min(2)..2 {
+                writer.write_all(&[0, 0])?;
+            }
+        } else {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    writer.write_all(&0_u32.to_le_bytes())?;
+    for _ in 0..(bits_len / 2) {
+        writer.write_all(&8_u16.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// An encoder which takes an image a band of rows at a time, so that images too large to
+/// hold in memory can be saved as they're rendered. Only 8-bit PNG and TIFF can be written
+/// this way; see `OutputFormat::streams`.
+pub struct RowWriter<W: Write> {
+    writer: W,
+    row_len: usize,
+    height: u32,
+    rows: u32,
+    /// For PNG, the compressor for the image data, holding whatever it's compressed since
+    /// the last band
+    deflate: Option<ZlibEncoder<Vec<u8>>>,
+}
+
+impl<W: Write> RowWriter<W> {
+    /// Begin an image with `channels` 8-bit samples per pixel.
+    pub fn new(mut writer: W,
+               format: OutputFormat,
+               width: u32,
+               height: u32,
+               channels: usize)
+               -> io::Result<RowWriter<W>> {
+        let deflate = match format {
+            OutputFormat::Png => {
+                let color_type = match channels {
+                    1 => 0,
+                    2 => 4,
+                    3 => 2,
+                    _ => 6,
+                };
+                let mut header = Vec::with_capacity(13);
+                header.extend_from_slice(&width.to_be_bytes());
+                header.extend_from_slice(&height.to_be_bytes());
+                // 8 bits per sample; deflate, adaptive filtering and no interlacing
+                header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+                writer.write_all(PNG_SIGNATURE)?;
+                write_chunk(&mut writer, b"IHDR", &header)?;
+                Some(ZlibEncoder::new(Vec::new(), Compression::Default))
+            }
+            OutputFormat::Tiff => {
+                write_tiff_header(&mut writer, width, height, channels)?;
+                None
+            }
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("{} images can't be written a band at a \
+                                                   time",
+                                                  format)))
+            }
+        };
+        Ok(RowWriter {
+            writer,
+            row_len: width as usize * channels,
+            height,
+            rows: 0,
+            deflate,
+        })
+    }
+
+    /// Write the next rows of the image.
+    pub fn write_rows(&mut self, data: &[u8]) -> io::Result<()> {
+        let rows = (data.len() / self.row_len.max(1)) as u32;
+        if data.len() != rows as usize * self.row_len || self.rows + rows > self.height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "rows don't fit the rest of the image"));
+        }
+        match self.deflate {
+            Some(ref mut deflate) => {
+                for row in data.chunks(self.row_len) {
+                    // each PNG row starts with its filter type; 0 is unfiltered
+                    deflate.write_all(&[0])?;
+                    deflate.write_all(row)?;
+                }
+                let compressed = mem::take(deflate.get_mut());
+                if !compressed.is_empty() {
+                    write_chunk(&mut self.writer, b"IDAT", &compressed)?;
+                }
+            }
+            None => self.writer.write_all(data)?,
+        }
+        self.rows += rows;
+        Ok(())
+    }
+
+    /// Finish the image once every row has been written, and return the writer.
+    ///
+    /// `text` is stored in PNG text chunks, which may follow the image data; TIFF ignores
+    /// it. This way, the text can describe the image as it turned out.
+    pub fn finish(mut self, text: &[(String, String)]) -> io::Result<W> {
+        if self.rows != self.height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("only {} of {} rows were written",
+                                              self.rows,
+                                              self.height)));
+        }
+        if let Some(deflate) = self.deflate.take() {
+            write_chunk(&mut self.writer, b"IDAT", &deflate.finish()?)?;
+            for (keyword, value) in text {
+                write_text_chunk(&mut self.writer, keyword, value)?;
+            }
+            write_chunk(&mut self.writer, b"IEND", &[])?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Write an uncompressed scanline OpenEXR image of 32-bit float channels.
+///
+/// Channels may be given in any order; EXR requires them sorted by name.
+pub fn write_exr<W: Write>(writer: &mut W,
+                           width: u32,
+                           height: u32,
+                           channels: &[(&str, &[f32])])
+                           -> io::Result<()> {
+    const FLOAT: i32 = 2;
+
+    let mut channels = channels.to_vec();
+    channels.sort_by_key(|&(name, _)| name);
+
+    fn attribute<W: Write>(w: &mut W, name: &str, kind: &str, value: &[u8]) -> io::Result<()> {
+        w.write_all(name.as_bytes())?;
+        w.write_all(&[0])?;
+        w.write_all(kind.as_bytes())?;
+        w.write_all(&[0])?;
+        w.write_all(&(value.len() as i32).to_le_bytes())?;
+        w.write_all(value)
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&0x0131_2f76_u32.to_le_bytes());
+    header.extend_from_slice(&2_u32.to_le_bytes());
+
+    let mut chlist = Vec::new();
+    for &(name, _) in &channels {
+        chlist.extend_from_slice(name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&FLOAT.to_le_bytes());
+        chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear + reserved
+        chlist.extend_from_slice(&1_i32.to_le_bytes());
+        chlist.extend_from_slice(&1_i32.to_le_bytes());
+    }
+    chlist.push(0);
+    attribute(&mut header, "channels", "chlist", &chlist)?;
+    attribute(&mut header, "compression", "compression", &[0])?;
+    let window = [0_i32, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes().to_vec())
+        .collect::<Vec<_>>();
+    attribute(&mut header, "dataWindow", "box2i", &window)?;
+    attribute(&mut header, "displayWindow", "box2i", &window)?;
+    attribute(&mut header, "lineOrder", "lineOrder", &[0])?;
+    attribute(&mut header, "pixelAspectRatio", "float", &1_f32.to_le_bytes())?;
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8])?;
+    attribute(&mut header, "screenWindowWidth", "float", &1_f32.to_le_bytes())?;
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // one scanline per block: a line offset table, then the lines themselves
+    let line_bytes = (channels.len() * width as usize * 4) as u64;
+    let first_line = header.len() as u64 + 8 * height as u64;
+    for y in 0..height as u64 {
+        writer.write_all(&(first_line + y * (8 + line_bytes)).to_le_bytes())?;
+    }
+    for y in 0..height as usize {
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&(line_bytes as i32).to_le_bytes())?;
+        for &(_, values) in &channels {
+            let row = &values[y * width as usize..(y + 1) * width as usize];
+            for value in row {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::flate2::read::ZlibDecoder;
+    use bailout::Bailout;
+    use field::FieldHeader;
+    use image::{self, ImageBuffer, Rgb};
+    use metadata::read_png_text;
+    use std::io::Read;
+    use viewport::Viewport;
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(16, 8, |x, y| {
+            Rgb([(x * 16) as u8, (y * 32) as u8, 128])
+        }))
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        assert_eq!(OutputFormat::from_str("PNG"), Ok(OutputFormat::Png));
+        assert_eq!(OutputFormat::from_str("jpg"), Ok(OutputFormat::Jpeg(DEFAULT_JPEG_QUALITY)));
+        assert_eq!(OutputFormat::from_str("jpeg:75"), Ok(OutputFormat::Jpeg(75)));
+        assert!(OutputFormat::from_str("jpeg:0").is_err());
+        assert!(OutputFormat::from_str("tiff:3").is_err());
+        assert!(OutputFormat::from_str("bmp").is_err());
+        assert_eq!(OutputFormat::from_path("out/image.TIF"), Some(OutputFormat::Tiff));
+        assert_eq!(OutputFormat::from_path("out/image"), None);
+    }
+
+    #[test]
+    fn test_tiff_roundtrip() {
+        let image = gradient();
+        let mut bytes = Vec::new();
+        write_image(&mut bytes, &image, OutputFormat::Tiff, &[]).unwrap();
+        let decoded = image::load_from_memory_with_format(&bytes, image::ImageFormat::TIFF)
+            .unwrap();
+        assert_eq!(decoded.raw_pixels(), image.raw_pixels());
+    }
+
+    #[test]
+    fn test_png16_field() {
+        let field = EscapeField {
+            header: FieldHeader {
+                width: 1,
+                height: 3,
+                viewport: Viewport::default(),
+                function: String::from("z^2"),
+                bailout: Bailout::Modulus(2.0),
+                iterations: 1000,
+            },
+            data: vec![0.0, 1.5, 1000.0],
+        };
+        let mut bytes = Vec::new();
+        write_field(&mut bytes, &field, OutputFormat::Png16).unwrap();
+        // bit depth lives in the IHDR chunk
+        assert_eq!(bytes[24], 16);
+        assert!(image::load_from_memory(&bytes).is_ok());
+        assert_eq!(read_png_text(&mut &bytes[..]).unwrap(), field.header.to_text());
+
+        // each row is a filter type, then one big-endian sample; with a single pixel per
+        // row, filtering leaves it unchanged
+        let idat = bytes.windows(4).position(|w| w == b"IDAT").unwrap();
+        let mut len = [0; 4];
+        len.copy_from_slice(&bytes[idat - 4..idat]);
+        let compressed = &bytes[idat + 4..idat + 4 + u32::from_be_bytes(len) as usize];
+        let mut rows = Vec::new();
+        ZlibDecoder::new(compressed).read_to_end(&mut rows).unwrap();
+        let samples = rows.chunks(3).map(|row| u16::from_be_bytes([row[1], row[2]]));
+        // 1.5 escapes out of 1000 would be lost in 8 bits
+        assert_eq!(samples.collect::<Vec<_>>(), vec![0, 98, u16::MAX]);
+
+        assert!(write_image(&mut Vec::new(), &gradient(), OutputFormat::Png16, &[]).is_err());
+        assert!(write_image(&mut Vec::new(), &gradient(), OutputFormat::Exr, &[]).is_err());
+    }
+
+    #[test]
+    fn test_row_writer() {
+        let image = gradient();
+        let data = image.raw_pixels();
+        let row_len = 16 * 3;
+        let text = vec![(String::from("julia_set.test"), String::from("streamed"))];
+
+        let mut png = RowWriter::new(Vec::new(), OutputFormat::Png, 16, 8, 3).unwrap();
+        for band in data.chunks(3 * row_len) {
+            png.write_rows(band).unwrap();
+        }
+        let png = png.finish(&text).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().raw_pixels(), data);
+        assert_eq!(read_png_text(&mut &png[..]).unwrap(), text);
+
+        // a streamed TIFF is byte for byte the same as one written whole
+        let mut tiff = RowWriter::new(Vec::new(), OutputFormat::Tiff, 16, 8, 3).unwrap();
+        tiff.write_rows(&data[..row_len]).unwrap();
+        tiff.write_rows(&data[row_len..]).unwrap();
+        let mut whole = Vec::new();
+        write_image(&mut whole, &image, OutputFormat::Tiff, &[]).unwrap();
+        assert_eq!(tiff.finish(&[]).unwrap(), whole);
+
+        let mut short = RowWriter::new(Vec::new(), OutputFormat::Png, 16, 8, 3).unwrap();
+        assert!(short.write_rows(&data[..row_len + 1]).is_err());
+        short.write_rows(&data[..row_len]).unwrap();
+        assert!(short.finish(&[]).is_err());
+        assert!(RowWriter::new(Vec::new(), OutputFormat::Ppm, 16, 8, 3).is_err());
+        // over 4 GiB of pixels
+        assert!(fits_tiff(37_000, 38_000, 3));
+        assert!(!fits_tiff(38_000, 38_000, 3));
+        assert!(RowWriter::new(Vec::new(), OutputFormat::Tiff, 38_000, 38_000, 3).is_err());
+    }
+
+    #[test]
+    fn test_exr_layout() {
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, 3, 2, &[("Y", &[0.0, 0.5, 1.0, 1.5, 2.0, 2.5])]).unwrap();
+        assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        // the last scanline ends the file: y, size, then three floats
+        let tail = &bytes[bytes.len() - 20..];
+        assert_eq!(&tail[..4], &1_i32.to_le_bytes());
+        assert_eq!(&tail[8..12], &1.5_f32.to_le_bytes());
+    }
+}
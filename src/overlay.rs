@@ -0,0 +1,136 @@
+//! Drawing individual orbits over a render.
+//!
+//! An escape-count image shows how long each orbit took to escape, but not where it went.
+//! When investigating a parameter it helps to see the orbit itself: this draws it over a
+//! finished image, as dots joined by lines, colored by step from red through the spectrum
+//! to violet.
+
+extern crate hsl;
+
+use image::{Rgb, RgbImage};
+use num::complex::Complex64;
+use self::hsl::HSL;
+
+use viewport::Viewport;
+
+/// The radius in pixels of the dot at the start of an orbit; later dots are smaller.
+const START_RADIUS: f64 = 3.0;
+const DOT_RADIUS: f64 = 1.5;
+
+/// Draw `orbit` over `image`, which shows `viewport`. Points outside the image are left
+/// out, but the lines towards them are drawn as far as the edge.
+pub fn draw_orbit(image: &mut RgbImage, viewport: &Viewport, orbit: &[Complex64]) {
+    let (width, height) = image.dimensions();
+    let positions = orbit.iter()
+        .map(|&z| viewport.position(z, width, height))
+        .collect::<Vec<_>>();
+    let colors = (0..orbit.len()).map(|step| color(step, orbit.len())).collect::<Vec<_>>();
+
+    // lines first, so that the dots stay on top
+    for (step, pair) in positions.windows(2).enumerate() {
+        line(image, pair[0], pair[1], colors[step]);
+    }
+    for (step, &position) in positions.iter().enumerate() {
+        let radius = if step == 0 { START_RADIUS } else { DOT_RADIUS };
+        dot(image, position, radius, colors[step]);
+    }
+}
+
+/// The color for `step` of an orbit of `length` points.
+fn color(step: usize, length: usize) -> Rgb<u8> {
+    let t = step as f64 / (length.max(2) - 1) as f64;
+    let (r, g, b) = HSL {
+            h: 270.0 * t,
+            s: 1.0,
+            l: 0.5,
+        }
+        .to_rgb();
+    Rgb([r, g, b])
+}
+
+fn plot(image: &mut RgbImage, x: f64, y: f64, color: Rgb<u8>) {
+    let (width, height) = image.dimensions();
+    let (x, y) = (x.round(), y.round());
+    if x >= 0.0 && x < width as f64 && y >= 0.0 && y < height as f64 {
+        image.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+/// Draw a line by stepping one pixel at a time along its longer axis. Escaping orbits
+/// leap far outside the image, so the line is first clipped to a margin around it.
+fn line(image: &mut RgbImage, from: (f64, f64), to: (f64, f64), color: Rgb<u8>) {
+    let (width, height) = image.dimensions();
+    if ![from.0, from.1, to.0, to.1].iter().all(|v| v.is_finite()) {
+        return;
+    }
+    let (from, to) = match clip(from, to, width as f64 + 1.0, height as f64 + 1.0) {
+        Some(ends) => ends,
+        None => return,
+    };
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs()).ceil().max(1.0);
+    for i in 0..(steps as usize + 1) {
+        let t = i as f64 / steps;
+        plot(image, from.0 + t * dx, from.1 + t * dy, color);
+    }
+}
+
+/// Clip a line to the rectangle from `(-1, -1)` to `(max_x, max_y)` by the Liang–Barsky
+/// algorithm, or `None` if it misses entirely.
+fn clip(from: (f64, f64),
+        to: (f64, f64),
+        max_x: f64,
+        max_y: f64)
+        -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let (mut enter, mut exit) = (0.0f64, 1.0f64);
+    for &(p, q) in &[(-dx, from.0 + 1.0),
+                     (dx, max_x - from.0),
+                     (-dy, from.1 + 1.0),
+                     (dy, max_y - from.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            enter = enter.max(q / p);
+        } else {
+            exit = exit.min(q / p);
+        }
+    }
+    if enter > exit {
+        return None;
+    }
+    Some(((from.0 + enter * dx, from.1 + enter * dy), (from.0 + exit * dx, from.1 + exit * dy)))
+}
+
+fn dot(image: &mut RgbImage, center: (f64, f64), radius: f64, color: Rgb<u8>) {
+    let reach = radius.ceil() as i64;
+    for dy in -reach..(reach + 1) {
+        for dx in -reach..(reach + 1) {
+            if ((dx * dx + dy * dy) as f64) <= radius * radius {
+                plot(image, center.0.round() + dx as f64, center.1.round() + dy as f64, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageBuffer;
+
+    #[test]
+    fn test_draw_orbit() {
+        let mut image: RgbImage = ImageBuffer::new(21, 21);
+        let viewport = Viewport::new(-1.0, 1.0, -1.0, 1.0);
+        // from the center, out to a point far off the right edge
+        draw_orbit(&mut image,
+                   &viewport,
+                   &[Complex64::new(0.0, 0.0), Complex64::new(1e300, 0.0)]);
+        assert_eq!(*image.get_pixel(10, 10), color(0, 2));
+        assert_eq!(*image.get_pixel(20, 10), color(0, 2));
+        assert_eq!(*image.get_pixel(10, 0), Rgb([0, 0, 0]));
+        assert!(color(0, 2) != color(1, 2));
+    }
+}
@@ -0,0 +1,279 @@
+//! Periodic points, their cycles, and the critical orbit.
+//!
+//! A point of period `n` is a root of `g(z) = f^n(z) - z`. Its derivative is
+//! `g'(z) = (f^n)'(z) - 1`, and `(f^n)'` is the product of `f'` along the orbit, so
+//! Newton's method on `g` only needs the function and its derivative. Started from a grid
+//! of guesses, it finds the periodic points of a region one by one; those of exact period
+//! `n` are gathered into cycles, each with its multiplier `(f^n)'`, which decides whether
+//! the cycle attracts or repels nearby orbits.
+//!
+//! Only holomorphic families have multipliers; the non-holomorphic ones are refused.
+
+use num::complex::Complex64;
+use std::fmt;
+
+use family::Family;
+use super::orbit;
+use viewport::Viewport;
+
+/// How a cycle treats nearby orbits, by the modulus of its multiplier `λ`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stability {
+    /// `λ = 0`: the cycle passes through a critical point
+    Superattracting,
+    /// `|λ| < 1`
+    Attracting,
+    /// `|λ| = 1`, to within `Cycle::TOLERANCE`
+    Indifferent,
+    /// `|λ| > 1`
+    Repelling,
+}
+
+impl fmt::Display for Stability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Stability::Superattracting => "superattracting",
+            Stability::Attracting => "attracting",
+            Stability::Indifferent => "indifferent",
+            Stability::Repelling => "repelling",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A periodic cycle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cycle {
+    /// The points of the cycle in orbit order, starting from the one with the smallest
+    /// real part
+    pub points: Vec<Complex64>,
+    /// The derivative of `f^n` at any point of the cycle
+    pub multiplier: Complex64,
+}
+
+impl Cycle {
+    /// Points closer than this are taken to be the same.
+    pub const TOLERANCE: f64 = 1e-8;
+
+    pub fn period(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn stability(&self) -> Stability {
+        let modulus = self.multiplier.norm();
+        if modulus < Cycle::TOLERANCE {
+            Stability::Superattracting
+        } else if (modulus - 1.0).abs() < Cycle::TOLERANCE {
+            Stability::Indifferent
+        } else if modulus < 1.0 {
+            Stability::Attracting
+        } else {
+            Stability::Repelling
+        }
+    }
+
+    /// Whether `z` is one of the points of the cycle.
+    pub fn contains(&self, z: Complex64) -> bool {
+        self.points.iter().any(|&point| (point - z).norm() < Cycle::TOLERANCE)
+    }
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let points = self.points
+            .iter()
+            .map(|z| format!("{}{:+}i", z.re, z.im))
+            .collect::<Vec<_>>();
+        write!(f,
+               "period {} {}, multiplier {}{:+}i: {}",
+               self.period(),
+               self.stability(),
+               self.multiplier.re,
+               self.multiplier.im,
+               points.join(", "))
+    }
+}
+
+/// Follow the orbit of the critical point for `c` for up to `steps` steps, stopping after
+/// the first point which escapes. The critical point itself comes first.
+pub fn critical_orbit(family: &Family, c: Complex64, steps: usize) -> Vec<Complex64> {
+    orbit(family.critical_point(), &*family.bind(c), &family.bailout(c), steps)
+}
+
+/// Refine `guess` to a point of period dividing `period` by Newton's method on
+/// `f^period(z) - z`, or `None` if it doesn't converge.
+pub fn refine(family: &Family,
+              c: Complex64,
+              guess: Complex64,
+              period: usize)
+              -> Result<Option<Complex64>, String> {
+    const MAX_ROUNDS: usize = 100;
+
+    let mut z = guess;
+    for _ in 0..MAX_ROUNDS {
+        let (image, derivative) = iterate(family, c, z, period)?;
+        let slope = derivative - 1.0;
+        if !image.re.is_finite() || !image.im.is_finite() || slope.norm_sqr() == 0.0 {
+            return Ok(None);
+        }
+        let step = (image - z) / slope;
+        z -= step;
+        if !z.re.is_finite() || !z.im.is_finite() {
+            return Ok(None);
+        }
+        if step.norm() < 1e-14 * z.norm().max(1.0) {
+            return Ok(Some(z));
+        }
+    }
+    Ok(None)
+}
+
+/// The cycle through a periodic point `z`, if its exact period is `period`.
+pub fn cycle_through(family: &Family,
+                     c: Complex64,
+                     z: Complex64,
+                     period: usize)
+                     -> Result<Option<Cycle>, String> {
+    let function = family.bind(c);
+    let mut points = vec![z];
+    for _ in 1..period {
+        let next = function(*points.last().unwrap());
+        if (next - z).norm() < Cycle::TOLERANCE {
+            // a point of some smaller period
+            return Ok(None);
+        }
+        points.push(next);
+    }
+    if (function(*points.last().unwrap()) - z).norm() >= Cycle::TOLERANCE {
+        return Ok(None);
+    }
+
+    let (_, multiplier) = iterate(family, c, z, period)?;
+    let first = (0..points.len())
+        .min_by(|&i, &j| points[i].re.partial_cmp(&points[j].re).unwrap())
+        .unwrap();
+    points.rotate_left(first);
+    Ok(Some(Cycle { points, multiplier }))
+}
+
+/// Find the cycles of exact period `period` by Newton's method from the centers of a
+/// `grid * grid` grid of guesses over `region`. Cycles are sorted by their first points.
+///
+/// A fine enough grid over a region containing the Julia set finds every cycle; polynomials of degree `d` have
+/// `d^n` points of period dividing `n`, which is a useful check.
+pub fn cycles(family: &Family,
+              c: Complex64,
+              period: usize,
+              region: &Viewport,
+              grid: u32)
+              -> Result<Vec<Cycle>, String> {
+    if period == 0 {
+        return Err(String::from("period must be at least 1"));
+    }
+    let mut cycles: Vec<Cycle> = Vec::new();
+    for y in 0..grid {
+        for x in 0..grid {
+            let guess = region.pixel(x, y, grid, grid);
+            let z = match refine(family, c, guess, period)? {
+                Some(z) => z,
+                None => continue,
+            };
+            if cycles.iter().any(|cycle| cycle.contains(z)) {
+                continue;
+            }
+            if let Some(cycle) = cycle_through(family, c, z, period)? {
+                cycles.push(cycle);
+            }
+        }
+    }
+    cycles.sort_by(|a, b| {
+        (a.points[0].re, a.points[0].im).partial_cmp(&(b.points[0].re, b.points[0].im)).unwrap()
+    });
+    Ok(cycles)
+}
+
+/// The fixed points which lie in `region`, as cycles of period 1.
+pub fn fixed_points(family: &Family,
+                    c: Complex64,
+                    region: &Viewport,
+                    grid: u32)
+                    -> Result<Vec<Cycle>, String> {
+    cycles(family, c, 1, region, grid)
+}
+
+/// Apply the function for `c` to `z` `n` times, returning the result and the derivative
+/// of the composition at `z`.
+fn iterate(family: &Family,
+           c: Complex64,
+           z: Complex64,
+           n: usize)
+           -> Result<(Complex64, Complex64), String> {
+    let mut z = z;
+    let mut derivative = Complex64::new(1.0, 0.0);
+    for _ in 0..n {
+        derivative *= family.derivative(z, c)
+            .ok_or_else(|| format!("{} has no derivative, and so no multipliers", family))?;
+        z = family.eval(z, c);
+    }
+    Ok((z, derivative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region() -> Viewport {
+        Viewport::new(-2.0, 2.0, -2.0, 2.0)
+    }
+
+    #[test]
+    fn test_quadratic_fixed_points() {
+        // the fixed points of z^2 + c are (1 ± sqrt(1 - 4c)) / 2, with multipliers 2z
+        let c = Complex64::new(-0.221, -0.713);
+        let fixed = fixed_points(&Family::default(), c, &region(), 16).unwrap();
+        assert_eq!(fixed.len(), 2);
+        let root = (Complex64::new(1.0, 0.0) - c * 4.0).sqrt();
+        for &expected in &[(root + 1.0) / 2.0, (-root + 1.0) / 2.0] {
+            let cycle = fixed.iter().find(|cycle| cycle.contains(expected)).unwrap();
+            assert!((cycle.multiplier - expected * 2.0).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_cycle_counts() {
+        // z^2 + c has 2^n points of period dividing n; for the rabbit, one of the two
+        // 3-cycles attracts the critical point
+        let family = Family::default();
+        let c = Complex64::new(-0.12, 0.75);
+        let found = cycles(&family, c, 3, &region(), 48).unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|cycle| cycle.period() == 3));
+        assert_eq!(found.iter().filter(|cycle| cycle.stability() == Stability::Attracting).count(),
+                   1);
+        assert_eq!(cycles(&family, c, 2, &region(), 32).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_superattracting() {
+        let c = Complex64::new(-1.0, 0.0);
+        let found = cycles(&Family::default(), c, 2, &region(), 32).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].stability(), Stability::Superattracting);
+        assert!(found[0].contains(Complex64::new(0.0, 0.0)));
+        assert!(found[0].contains(Complex64::new(-1.0, 0.0)));
+        assert_eq!(critical_orbit(&Family::default(), c, 4),
+                   vec![Complex64::new(0.0, 0.0),
+                        Complex64::new(-1.0, 0.0),
+                        Complex64::new(0.0, 0.0),
+                        Complex64::new(-1.0, 0.0),
+                        Complex64::new(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_escaping_critical_orbit_stops() {
+        // 0, 1, 2, and 2 is already at the escape radius
+        let orbit = critical_orbit(&Family::default(), Complex64::new(1.0, 0.0), 100);
+        assert_eq!(orbit.len(), 3);
+        assert!(cycles(&Family::Tricorn, Complex64::new(0.0, 0.0), 1, &region(), 4).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! The Phoenix family.
+//!
+//! Each step of the Phoenix iteration depends on the previous value as well as the
+//! current one:
+//!
+//! ```text
+//! z_{n+1} = z_n^2 + c + p·z_{n-1}
+//! ```
+//!
+//! starting from `z_{-1} = 0`. The classic Phoenix set, discovered by Shigehiro Ushiki, is
+//! the Julia set for `c = 0.5667` and `p = -0.5`. With `p = 0` this is just the quadratic
+//! family.
+
+use image::{ImageBuffer, Luma};
+use num::complex::Complex64;
+
+use bailout::Bailout;
+use plane::Plane;
+use state::State;
+use super::parallel_image_state;
+
+/// The state of a Phoenix orbit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhoenixState {
+    pub z: Complex64,
+    pub previous: Complex64,
+    pub c: Complex64,
+}
+
+impl State for PhoenixState {
+    #[inline]
+    fn value(&self) -> Complex64 {
+        self.z
+    }
+}
+
+/// The Phoenix family for a particular coefficient `p` of the previous value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Phoenix {
+    pub p: Complex64,
+}
+
+impl Phoenix {
+    /// The `c` of the classic Phoenix set.
+    pub const CLASSIC_C: f64 = 0.5667;
+    /// The `p` of the classic Phoenix set.
+    pub const CLASSIC_P: f64 = -0.5;
+
+    pub fn new(p: Complex64) -> Phoenix {
+        Phoenix { p }
+    }
+
+    /// The initial state for `pixel`, when the fixed parameter of a Julia set would be `c`.
+    pub fn start(&self, pixel: Complex64, plane: &Plane, c: Complex64) -> PhoenixState {
+        let (z, c) = plane.orbit(pixel, c);
+        PhoenixState {
+            z,
+            previous: Complex64::new(0.0, 0.0),
+            c,
+        }
+    }
+
+    /// Take a single step.
+    #[inline]
+    pub fn step(&self, state: PhoenixState) -> PhoenixState {
+        PhoenixState {
+            z: state.z * state.z + state.c + self.p * state.previous,
+            previous: state.z,
+            c: state.c,
+        }
+    }
+
+    /// Construct an escape-count image in parallel on `threads` threads, as
+    /// `parallel_image_escape` would.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(&self,
+                  width: u32,
+                  height: u32,
+                  interpolate: &(dyn Fn(u32, u32) -> Complex64 + Sync),
+                  plane: &Plane,
+                  c: Complex64,
+                  bailout: &Bailout,
+                  iterations: usize,
+                  threads: usize)
+                  -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        parallel_image_state(width,
+                             height,
+                             &|state| self.step(state),
+                             &|x, y| self.start(interpolate(x, y), plane, c),
+                             bailout,
+                             iterations,
+                             threads)
+    }
+}
+
+impl Default for Phoenix {
+    fn default() -> Phoenix {
+        Phoenix::new(Complex64::new(Phoenix::CLASSIC_P, 0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{interpolate_stretch, parallel_image_escape};
+
+    #[test]
+    fn test_step() {
+        let phoenix = Phoenix::default();
+        let c = Complex64::new(Phoenix::CLASSIC_C, 0.0);
+        let state = phoenix.start(Complex64::new(1.0, 0.0), &Plane::Julia, c);
+        let state = phoenix.step(state);
+        assert_eq!(state.z, Complex64::new(1.5667, 0.0));
+        assert_eq!(state.previous, Complex64::new(1.0, 0.0));
+        let state = phoenix.step(state);
+        assert!((state.z - Complex64::new(1.5667 * 1.5667 + 0.5667 - 0.5, 0.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_zero_p_is_quadratic() {
+        let (width, height) = (48, 36);
+        let interpolate = interpolate_stretch(width, height, -1.6, 1.6, -1.2, 1.2);
+        let c = Complex64::new(-0.221, -0.713);
+        let bailout = Bailout::default();
+        let phoenix = Phoenix::new(Complex64::new(0.0, 0.0)).render(width,
+                                                                    height,
+                                                                    &*interpolate,
+                                                                    &Plane::Julia,
+                                                                    c,
+                                                                    &bailout,
+                                                                    255,
+                                                                    2);
+        let quadratic = |z: Complex64| z * z + c;
+        let expected = parallel_image_escape(width,
+                                             height,
+                                             &quadratic,
+                                             &*interpolate,
+                                             &bailout,
+                                             255,
+                                             2);
+        assert!(phoenix.pixels().zip(expected.pixels()).all(|(p, q)| p == q));
+    }
+}
@@ -0,0 +1,89 @@
+//! Which plane a render is drawn in.
+//!
+//! A Julia set fixes the parameter `c` and varies the starting point `z` across the image.
+//! The parameter plane does the opposite: it fixes the starting point, usually a critical
+//! point of the family, and varies `c`. For `z^2 + c` started from 0 this draws the
+//! Mandelbrot set; for the Burning Ship family it draws the ship itself.
+
+use num::complex::Complex64;
+use std::fmt;
+use std::str::FromStr;
+
+/// The plane a render is drawn in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Plane {
+    /// Pixels are starting points, and `c` is fixed.
+    #[default]
+    Julia,
+    /// Pixels are values of `c`, and every orbit starts from this point.
+    Parameter(Complex64),
+}
+
+impl Plane {
+    /// The starting point and parameter of the orbit drawn at `pixel`, when the fixed
+    /// parameter of a Julia set would be `c`.
+    #[inline]
+    pub fn orbit(&self, pixel: Complex64, c: Complex64) -> (Complex64, Complex64) {
+        match *self {
+            Plane::Julia => (pixel, c),
+            Plane::Parameter(start) => (start, pixel),
+        }
+    }
+
+    pub fn is_julia(&self) -> bool {
+        *self == Plane::Julia
+    }
+}
+
+impl fmt::Display for Plane {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Plane::Julia => write!(f, "julia"),
+            Plane::Parameter(start) => write!(f, "parameter:{},{}", start.re, start.im),
+        }
+    }
+}
+
+impl FromStr for Plane {
+    type Err = String;
+
+    /// Parse `julia`, or `parameter:RE,IM` for the parameter plane started from `RE + IM i`.
+    /// `parameter` alone starts from 0.
+    fn from_str(s: &str) -> Result<Plane, String> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap().trim().to_lowercase();
+        match (kind.as_str(), parts.next()) {
+            ("julia", None) => Ok(Plane::Julia),
+            ("parameter", None) => Ok(Plane::Parameter(Complex64::new(0.0, 0.0))),
+            ("parameter", Some(start)) => {
+                let start = start.split(',')
+                    .map(|part| f64::from_str(part.trim()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("invalid starting point in '{}'", s))?;
+                if start.len() != 2 {
+                    return Err(format!("starting point in '{}' needs two parts", s));
+                }
+                Ok(Plane::Parameter(Complex64::new(start[0], start[1])))
+            }
+            _ => Err(format!("unknown plane '{}'; expected julia or parameter", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        for plane in &[Plane::Julia,
+                       Plane::Parameter(Complex64::new(0.0, 0.0)),
+                       Plane::Parameter(Complex64::new(1.25, -0.25))] {
+            assert_eq!(&Plane::from_str(&plane.to_string()).unwrap(), plane);
+        }
+        assert_eq!(Plane::from_str("parameter"),
+                   Ok(Plane::Parameter(Complex64::new(0.0, 0.0))));
+        assert!(Plane::from_str("parameter:1").is_err());
+        assert!(Plane::from_str("dynamical").is_err());
+    }
+}
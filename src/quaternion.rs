@@ -0,0 +1,357 @@
+//! Quaternion Julia sets.
+//!
+//! Iterating `q^2 + c` over the quaternions gives a four-dimensional Julia set. We can't
+//! look at it directly, but any plane through it is an ordinary image: pick two axes to
+//! vary across the image, and fix the other two. A stack of such slices, stepping one of
+//! the fixed axes, is a voxel grid of a three-dimensional section.
+//!
+//! Escape is judged by modulus, via `State::value`: a quaternion `w + v`, with `v` its
+//! vector part, is represented by the complex number `w + |v|i`. Squaring commutes with
+//! this, so the modulus criterion behaves exactly as it does for `z^2 + c`.
+
+use image::{ImageBuffer, Luma};
+use num::complex::Complex64;
+use std::fmt;
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
+use bailout::Bailout;
+use state::State;
+use super::parallel_image_state;
+
+/// A quaternion `w + xi + yj + zk`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Quaternion {
+        Quaternion { w, x, y, z }
+    }
+
+    /// The unit quaternion along one of the four axes, numbered `w, x, y, z`.
+    fn axis(index: usize) -> Quaternion {
+        let mut components = [0.0; 4];
+        components[index] = 1.0;
+        Quaternion::from(components)
+    }
+
+    pub fn norm_sqr(&self) -> f64 {
+        self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.norm_sqr().sqrt()
+    }
+
+    /// `self * self`, which is cheaper than the general product.
+    #[inline]
+    pub fn square(&self) -> Quaternion {
+        let double = 2.0 * self.w;
+        Quaternion {
+            w: self.w * self.w - self.x * self.x - self.y * self.y - self.z * self.z,
+            x: double * self.x,
+            y: double * self.y,
+            z: double * self.z,
+        }
+    }
+
+    fn scale(&self, factor: f64) -> Quaternion {
+        Quaternion::new(self.w * factor, self.x * factor, self.y * factor, self.z * factor)
+    }
+}
+
+impl From<[f64; 4]> for Quaternion {
+    fn from(components: [f64; 4]) -> Quaternion {
+        Quaternion::new(components[0], components[1], components[2], components[3])
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, other: Quaternion) -> Quaternion {
+        Quaternion::new(self.w + other.w, self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Quaternion {
+    type Output = Quaternion;
+
+    fn sub(self, other: Quaternion) -> Quaternion {
+        Quaternion::new(self.w - other.w, self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    /// The Hamilton product, which doesn't commute.
+    fn mul(self, other: Quaternion) -> Quaternion {
+        let (a, b) = (self, other);
+        Quaternion {
+            w: a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+            x: a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            y: a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            z: a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+        }
+    }
+}
+
+impl State for Quaternion {
+    #[inline]
+    fn value(&self) -> Complex64 {
+        Complex64::new(self.w, (self.x * self.x + self.y * self.y + self.z * self.z).sqrt())
+    }
+}
+
+impl fmt::Display for Quaternion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.w, self.x, self.y, self.z)
+    }
+}
+
+impl FromStr for Quaternion {
+    type Err = String;
+
+    /// Parse four comma-separated components, `W,X,Y,Z`.
+    fn from_str(s: &str) -> Result<Quaternion, String> {
+        let components = s.split(',')
+            .map(|part| f64::from_str(part.trim()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid quaternion '{}'", s))?;
+        if components.len() != 4 {
+            return Err(format!("quaternion '{}' needs four components", s));
+        }
+        Ok(Quaternion::new(components[0], components[1], components[2], components[3]))
+    }
+}
+
+const AXIS_NAMES: [char; 4] = ['w', 'x', 'y', 'z'];
+
+/// A plane through quaternion space, spanned by two of the axes, with the other two held
+/// at fixed offsets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slice {
+    /// The axes varying horizontally and vertically, numbered `w, x, y, z`
+    axes: (usize, usize),
+    /// The values of the two remaining axes, in axis order
+    pub offsets: (f64, f64),
+}
+
+impl Slice {
+    /// The slice through axes named by two of `w, x, y, z`, such as `"wx"`, with the other
+    /// axes held at `offsets`.
+    pub fn new(axes: &str, offsets: (f64, f64)) -> Result<Slice, String> {
+        let indices = axes.trim()
+            .to_lowercase()
+            .chars()
+            .map(|name| AXIS_NAMES.iter().position(|&axis| axis == name))
+            .collect::<Option<Vec<_>>>();
+        match indices {
+            Some(ref indices) if indices.len() == 2 && indices[0] != indices[1] => {
+                Ok(Slice {
+                    axes: (indices[0], indices[1]),
+                    offsets,
+                })
+            }
+            _ => Err(format!("slice axes '{}' must be two different axes of w, x, y, z", axes)),
+        }
+    }
+
+    /// The two axes which don't vary across the slice, in axis order.
+    pub fn fixed_axes(&self) -> (usize, usize) {
+        let mut fixed = (0..4).filter(|&axis| axis != self.axes.0 && axis != self.axes.1);
+        (fixed.next().unwrap(), fixed.next().unwrap())
+    }
+
+    /// The quaternion at position `(a, b)` in the slice.
+    pub fn point(&self, a: f64, b: f64) -> Quaternion {
+        let (first, second) = self.fixed_axes();
+        Quaternion::axis(self.axes.0).scale(a) + Quaternion::axis(self.axes.1).scale(b) +
+        Quaternion::axis(first).scale(self.offsets.0) +
+        Quaternion::axis(second).scale(self.offsets.1)
+    }
+
+    /// The parallel slice with the first fixed axis moved to `offset`.
+    pub fn at_depth(&self, offset: f64) -> Slice {
+        Slice { offsets: (offset, self.offsets.1), ..*self }
+    }
+
+    /// `depth` parallel slices, with the first fixed axis stepped evenly from `low` to
+    /// `high` inclusive.
+    pub fn stack(&self, depth: u32, (low, high): (f64, f64)) -> Vec<Slice> {
+        let step = if depth > 1 {
+            (high - low) / (depth - 1) as f64
+        } else {
+            0.0
+        };
+        (0..depth).map(|layer| self.at_depth(low + step * layer as f64)).collect()
+    }
+}
+
+impl Default for Slice {
+    fn default() -> Slice {
+        Slice::new("wx", (0.0, 0.0)).unwrap()
+    }
+}
+
+impl fmt::Display for Slice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}{}:{},{}",
+               AXIS_NAMES[self.axes.0],
+               AXIS_NAMES[self.axes.1],
+               self.offsets.0,
+               self.offsets.1)
+    }
+}
+
+impl FromStr for Slice {
+    type Err = String;
+
+    /// Parse `AXES[:A,B]`, such as `wx` or `yz:0.2,-0.1`; the offsets default to 0.
+    fn from_str(s: &str) -> Result<Slice, String> {
+        let mut parts = s.splitn(2, ':');
+        let axes = parts.next().unwrap();
+        let offsets = match parts.next() {
+            Some(offsets) => {
+                let offsets = offsets.split(',')
+                    .map(|part| f64::from_str(part.trim()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("invalid slice offsets in '{}'", s))?;
+                if offsets.len() != 2 {
+                    return Err(format!("slice '{}' needs two offsets", s));
+                }
+                (offsets[0], offsets[1])
+            }
+            None => (0.0, 0.0),
+        };
+        Slice::new(axes, offsets)
+    }
+}
+
+/// The Julia set of `q^2 + c` for a quaternion `c`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuaternionJulia {
+    pub c: Quaternion,
+}
+
+impl QuaternionJulia {
+    pub fn new(c: Quaternion) -> QuaternionJulia {
+        QuaternionJulia { c }
+    }
+
+    #[inline]
+    pub fn step(&self, q: Quaternion) -> Quaternion {
+        q.square() + self.c
+    }
+
+    /// Construct an escape-count image of a slice in parallel on `threads` threads, as
+    /// `parallel_image_escape` would. `interpolate` maps pixels to positions in the slice.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_slice(&self,
+                        width: u32,
+                        height: u32,
+                        interpolate: &(dyn Fn(u32, u32) -> Complex64 + Sync),
+                        slice: &Slice,
+                        bailout: &Bailout,
+                        iterations: usize,
+                        threads: usize)
+                        -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        parallel_image_state(width,
+                             height,
+                             &|q| self.step(q),
+                             &|x, y| {
+                                 let position = interpolate(x, y);
+                                 slice.point(position.re, position.im)
+                             },
+                             bailout,
+                             iterations,
+                             threads)
+    }
+
+    /// Construct a voxel grid as a stack of slices, one layer per slice; see
+    /// `Slice::stack`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_voxels(&self,
+                         width: u32,
+                         height: u32,
+                         interpolate: &(dyn Fn(u32, u32) -> Complex64 + Sync),
+                         slices: &[Slice],
+                         bailout: &Bailout,
+                         iterations: usize,
+                         threads: usize)
+                         -> Vec<ImageBuffer<Luma<u8>, Vec<u8>>> {
+        slices.iter()
+            .map(|slice| {
+                self.render_slice(width, height, interpolate, slice, bailout, iterations, threads)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{interpolate_stretch, parallel_image_escape};
+
+    #[test]
+    fn test_arithmetic() {
+        let i = Quaternion::axis(1);
+        let j = Quaternion::axis(2);
+        let k = Quaternion::axis(3);
+        let minus_one = Quaternion::new(-1.0, 0.0, 0.0, 0.0);
+        assert_eq!(i * i, minus_one);
+        assert_eq!(i * j * k, minus_one);
+        assert_eq!(i * j, k);
+        assert_eq!(j * i, k.scale(-1.0));
+        let q = Quaternion::new(0.5, -0.25, 0.75, 1.5);
+        assert_eq!(q.square(), q * q);
+        assert_eq!(q.value().norm_sqr(), q.norm_sqr());
+    }
+
+    #[test]
+    fn test_slice() {
+        let slice = Slice::from_str("yz:0.5,-1").unwrap();
+        assert_eq!(slice.fixed_axes(), (0, 1));
+        assert_eq!(slice.point(2.0, 3.0), Quaternion::new(0.5, -1.0, 2.0, 3.0));
+        assert_eq!(Slice::from_str(&slice.to_string()), Ok(slice));
+        assert!(Slice::from_str("ww").is_err());
+        assert!(Slice::from_str("wq").is_err());
+        assert!(Slice::from_str("wx:1").is_err());
+        let stack = slice.stack(3, (-1.0, 1.0));
+        assert_eq!(stack.iter().map(|s| s.offsets.0).collect::<Vec<_>>(), [-1.0, 0.0, 1.0]);
+        assert!(stack.iter().all(|s| s.offsets.1 == -1.0));
+    }
+
+    #[test]
+    fn test_complex_slice_is_julia_set() {
+        // with c and the slice both in the wx plane, this is the ordinary Julia set
+        let (width, height) = (48, 36);
+        let interpolate = interpolate_stretch(width, height, -1.6, 1.6, -1.2, 1.2);
+        let c = Complex64::new(-0.221, -0.713);
+        let bailout = Bailout::default();
+        let julia = QuaternionJulia::new(Quaternion::new(c.re, c.im, 0.0, 0.0));
+        let slice = julia.render_slice(width,
+                                       height,
+                                       &*interpolate,
+                                       &Slice::default(),
+                                       &bailout,
+                                       255,
+                                       2);
+        let quadratic = |z: Complex64| z * z + c;
+        let expected = parallel_image_escape(width,
+                                             height,
+                                             &quadratic,
+                                             &*interpolate,
+                                             &bailout,
+                                             255,
+                                             2);
+        assert!(slice.pixels().zip(expected.pixels()).all(|(p, q)| p == q));
+    }
+}
@@ -0,0 +1,388 @@
+//! Shaded 3D renders of quaternion Julia sets by ray marching.
+//!
+//! A three-dimensional section of a quaternion Julia set has no closed form, but its
+//! distance estimator does: for a point outside the set, iterating `q^2 + c` along with
+//! the derivative `q' ↦ 2qq'` gives a lower bound
+//!
+//! ```text
+//! d = |q| ln|q| / (2|q'|)
+//! ```
+//!
+//! on the distance to the surface. A ray can therefore step forward by `d` each time
+//! without ever passing through the surface, and it has hit once `d` is negligible.
+//! Surface normals come from the gradient of the estimator, and ambient occlusion from
+//! how quickly the estimator grows away from the surface.
+
+use image::{ImageBuffer, Luma};
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+use quaternion::{Quaternion, QuaternionJulia};
+use super::parallel_rows;
+
+/// A point or direction in three dimensions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vector3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Vector3 {
+        Vector3 { x, y, z }
+    }
+
+    pub fn dot(&self, other: Vector3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: Vector3) -> Vector3 {
+        Vector3::new(self.y * other.z - self.z * other.y,
+                     self.z * other.x - self.x * other.z,
+                     self.x * other.y - self.y * other.x)
+    }
+
+    pub fn length(&self) -> f64 {
+        self.dot(*self).sqrt()
+    }
+
+    /// This vector scaled to unit length.
+    pub fn normalize(&self) -> Vector3 {
+        *self * (1.0 / self.length())
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Vector3;
+
+    fn sub(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Vector3 {
+    type Output = Vector3;
+
+    fn mul(self, factor: f64) -> Vector3 {
+        Vector3::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+}
+
+impl Neg for Vector3 {
+    type Output = Vector3;
+
+    fn neg(self) -> Vector3 {
+        Vector3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl fmt::Display for Vector3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}", self.x, self.y, self.z)
+    }
+}
+
+impl FromStr for Vector3 {
+    type Err = String;
+
+    /// Parse three comma-separated components, `X,Y,Z`.
+    fn from_str(s: &str) -> Result<Vector3, String> {
+        let components = s.split(',')
+            .map(|part| f64::from_str(part.trim()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid vector '{}'", s))?;
+        if components.len() != 3 {
+            return Err(format!("vector '{}' needs three components", s));
+        }
+        Ok(Vector3::new(components[0], components[1], components[2]))
+    }
+}
+
+/// A pinhole camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vector3,
+    /// The point at the center of the image
+    pub target: Vector3,
+    /// Which way is up; it needn't be perpendicular to the view direction, and if it's
+    /// parallel to it, another axis is used instead
+    pub up: Vector3,
+    /// The vertical field of view, in degrees
+    pub field_of_view: f64,
+}
+
+impl Camera {
+    /// The direction of the ray through pixel `(x, y)` of a `width * height` image.
+    fn ray(&self, x: u32, y: u32, width: u32, height: u32) -> Vector3 {
+        let forward = (self.target - self.position).normalize();
+        let mut right = self.up.cross(forward);
+        if right.length() < 1e-9 * self.up.length() {
+            // looking straight up or down, so any horizontal direction will do
+            let axis = if forward.x.abs() < 0.9 {
+                Vector3::new(1.0, 0.0, 0.0)
+            } else {
+                Vector3::new(0.0, 0.0, 1.0)
+            };
+            right = forward.cross(axis).cross(forward);
+        }
+        let right = right.normalize();
+        let up = forward.cross(right);
+        let scale = (self.field_of_view.to_radians() / 2.0).tan();
+        // through the center of the pixel, with y increasing downward
+        let u = (2.0 * (x as f64 + 0.5) / height as f64 - width as f64 / height as f64) * scale;
+        let v = (1.0 - 2.0 * (y as f64 + 0.5) / height as f64) * scale;
+        (forward + right * u + up * v).normalize()
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera {
+            position: Vector3::new(0.0, 0.0, -3.0),
+            target: Vector3::default(),
+            up: Vector3::new(0.0, 1.0, 0.0),
+            field_of_view: 45.0,
+        }
+    }
+}
+
+/// How a ray-marched surface is lit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lighting {
+    /// The direction light travels in
+    pub direction: Vector3,
+    /// Brightness of surfaces facing away from the light, in [0, 1]
+    pub ambient: f64,
+    /// Strength of the specular highlight, in [0, 1]
+    pub specular: f64,
+    /// Whether to darken creases by ambient occlusion
+    pub occlusion: bool,
+}
+
+impl Default for Lighting {
+    fn default() -> Lighting {
+        Lighting {
+            // from the upper left, behind the camera
+            direction: Vector3::new(1.0, -1.0, 1.0),
+            ambient: 0.2,
+            specular: 0.3,
+            occlusion: true,
+        }
+    }
+}
+
+/// A ray marcher for a three-dimensional section of a quaternion Julia set.
+///
+/// The section is the hyperplane where the `z` component of the quaternion equals
+/// `section`; a point `(x, y, z)` in space is the quaternion `x + yi + zj + section·k`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayMarcher {
+    pub julia: QuaternionJulia,
+    pub section: f64,
+    pub camera: Camera,
+    pub lighting: Lighting,
+    /// Iterations of the distance estimator; more give a finer surface
+    pub iterations: usize,
+    /// The maximum number of steps along each ray
+    pub steps: usize,
+    /// A ray hits once the estimated distance is less than this fraction of the distance
+    /// travelled, so distant surfaces are resolved no more finely than pixels can show
+    pub epsilon: f64,
+}
+
+impl RayMarcher {
+    pub const DEFAULT_ITERATIONS: usize = 16;
+
+    /// Everything of interest lies within this radius of the origin.
+    const BOUND: f64 = 2.0;
+
+    pub fn new(julia: QuaternionJulia) -> RayMarcher {
+        RayMarcher {
+            julia,
+            section: 0.0,
+            camera: Camera::default(),
+            lighting: Lighting::default(),
+            iterations: RayMarcher::DEFAULT_ITERATIONS,
+            steps: 256,
+            epsilon: 1e-3,
+        }
+    }
+
+    fn quaternion(&self, point: Vector3) -> Quaternion {
+        Quaternion::new(point.x, point.y, point.z, self.section)
+    }
+
+    /// A lower bound on the distance from `point` to the surface; 0 inside the set.
+    pub fn distance(&self, point: Vector3) -> f64 {
+        let mut q = self.quaternion(point);
+        let mut derivative = 1.0;
+        for _ in 0..self.iterations {
+            derivative *= 2.0 * q.norm();
+            q = self.julia.step(q);
+            if q.norm_sqr() > 256.0 {
+                break;
+            }
+        }
+        let norm = q.norm();
+        if norm <= 1.0 || derivative == 0.0 {
+            return 0.0;
+        }
+        0.5 * norm * norm.ln() / derivative
+    }
+
+    /// The surface normal at `point`, from the gradient of the distance estimator.
+    fn normal(&self, point: Vector3, h: f64) -> Vector3 {
+        let dx = Vector3::new(h, 0.0, 0.0);
+        let dy = Vector3::new(0.0, h, 0.0);
+        let dz = Vector3::new(0.0, 0.0, h);
+        let gradient = Vector3::new(self.distance(point + dx) - self.distance(point - dx),
+                                    self.distance(point + dy) - self.distance(point - dy),
+                                    self.distance(point + dz) - self.distance(point - dz));
+        if gradient.length() == 0.0 {
+            // deep inside the set; face the light so the point isn't lost
+            -self.lighting.direction.normalize()
+        } else {
+            gradient.normalize()
+        }
+    }
+
+    /// How exposed `point` is, in [0, 1], by sampling the estimator along the normal.
+    fn occlusion(&self, point: Vector3, normal: Vector3) -> f64 {
+        const SAMPLES: usize = 5;
+        const SPACING: f64 = 0.02;
+        let mut occluded = 0.0;
+        let mut weight = 1.0;
+        for sample in 1..SAMPLES + 1 {
+            let offset = SPACING * sample as f64;
+            occluded += weight * (offset - self.distance(point + normal * offset)).max(0.0);
+            weight *= 0.5;
+        }
+        (1.0 - 5.0 * occluded).clamp(0.0, 1.0)
+    }
+
+    /// March a ray from the camera in `direction`, returning the point it hits and the
+    /// distance travelled, if it hits anything.
+    fn march(&self, direction: Vector3) -> Option<(Vector3, f64)> {
+        let origin = self.camera.position;
+        // skip straight to the bounding sphere
+        let b = origin.dot(direction);
+        let discriminant = b * b - origin.dot(origin) + RayMarcher::BOUND * RayMarcher::BOUND;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let far = -b + discriminant.sqrt();
+        let mut travelled = (-b - discriminant.sqrt()).max(0.0);
+        for _ in 0..self.steps {
+            if travelled > far {
+                return None;
+            }
+            let point = origin + direction * travelled;
+            let distance = self.distance(point);
+            if distance < self.epsilon * travelled.max(self.epsilon) {
+                return Some((point, travelled));
+            }
+            travelled += distance;
+        }
+        None
+    }
+
+    /// The brightness of a single pixel, in [0, 1]; misses are 0.
+    fn shade(&self, x: u32, y: u32, width: u32, height: u32) -> f64 {
+        let direction = self.camera.ray(x, y, width, height);
+        let (point, travelled) = match self.march(direction) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        let normal = self.normal(point, self.epsilon * travelled.max(self.epsilon));
+        let light = -self.lighting.direction.normalize();
+        let diffuse = normal.dot(light).max(0.0);
+        let halfway = (light - direction).normalize();
+        let specular = normal.dot(halfway).max(0.0).powi(32);
+        let exposure = if self.lighting.occlusion {
+            self.occlusion(point, normal)
+        } else {
+            1.0
+        };
+        let ambient = self.lighting.ambient;
+        // never fully black, so that hits stay distinct from the background
+        let shade = (ambient + (1.0 - ambient) * diffuse) * exposure +
+                    self.lighting.specular * specular;
+        shade.clamp(1.0 / 255.0, 1.0)
+    }
+
+    /// Render a grayscale image of the surface in parallel on `threads` threads.
+    ///
+    /// Brightness is the shading of the surface; the background is black, and the surface
+    /// never is, so the result suits the same colorizers as an escape-count image.
+    pub fn render(&self, width: u32, height: u32, threads: usize) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        let shades = parallel_rows(width, height, threads, &|x, y| {
+            (self.shade(x, y, width, height) * u8::MAX as f64).round() as u8
+        });
+        ImageBuffer::from_raw(width, height, shades).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_bounds_sphere() {
+        // for c = 0 the set is the unit ball, and the estimator approaches the true
+        // distance from outside
+        let marcher = RayMarcher {
+            iterations: 64,
+            ..RayMarcher::new(QuaternionJulia::new(Quaternion::default()))
+        };
+        for &r in &[1.01, 1.1, 1.5] {
+            let distance = marcher.distance(Vector3::new(0.0, r, 0.0));
+            assert!(distance > 0.0 && distance <= r - 1.0 + 1e-9, "{} at {}", distance, r);
+        }
+        assert_eq!(marcher.distance(Vector3::new(0.1, 0.2, 0.3)), 0.0);
+    }
+
+    #[test]
+    fn test_render_sphere() {
+        let marcher = RayMarcher {
+            iterations: 64,
+            ..RayMarcher::new(QuaternionJulia::new(Quaternion::default()))
+        };
+        let image = marcher.render(41, 41, 2);
+        // the unit ball fills the middle of the view and misses the corners
+        assert!(image.get_pixel(20, 20)[0] > 0);
+        assert_eq!(image.get_pixel(0, 0)[0], 0);
+        // lit from the upper left
+        assert!(image.get_pixel(16, 16)[0] > image.get_pixel(24, 24)[0]);
+    }
+
+    #[test]
+    fn test_camera_along_up() {
+        // looking straight down the up axis leaves no way to tell right from left
+        let camera = Camera {
+            position: Vector3::new(0.0, 3.0, 0.0),
+            ..Camera::default()
+        };
+        for &(x, y) in &[(0, 0), (20, 20), (40, 7)] {
+            let ray = camera.ray(x, y, 41, 41);
+            assert!(ray.x.is_finite() && ray.y.is_finite() && ray.z.is_finite());
+        }
+        let marcher = RayMarcher {
+            iterations: 64,
+            camera,
+            ..RayMarcher::new(QuaternionJulia::new(Quaternion::default()))
+        };
+        let image = marcher.render(41, 41, 2);
+        assert!(image.get_pixel(20, 20)[0] > 0);
+        assert_eq!(image.get_pixel(0, 0)[0], 0);
+    }
+}
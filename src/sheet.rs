@@ -0,0 +1,607 @@
+//! Laying out contact sheets: grids of tiles with axes, captions and a legend.
+//!
+//! A grid of renders is hard to read without knowing what each tile shows. A `Sheet`
+//! places tiles in a grid with optional gutters between them, and can frame the grid with
+//! axes whose ticks label the value of each column and row, caption each tile, and add a
+//! legend mapping the palette back to escape counts. All text is drawn with the embedded
+//! bitmap font, so sheets look the same everywhere.
+//!
+//! A sheet can also carry a `Map` of the region under the grid, such as the parameter
+//! plane when each tile shows the Julia set for the `c` at its center. It's drawn either
+//! behind the tiles or as an inset beside the grid, with the position of each tile marked.
+//!
+//! Sheets can be drawn a band of rows at a time, so that big ones can be written out as
+//! they're rendered rather than held in memory whole.
+
+use image::{ImageBuffer, Rgb, RgbImage};
+use num::complex::Complex64;
+use std::fmt;
+
+use font::{draw_text, text_height, text_width, ADVANCE};
+
+/// Tick values for the axes of a sheet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Axes {
+    /// The value at each column, from left to right
+    pub real: Vec<f64>,
+    /// The value at each row, from top to bottom
+    pub imag: Vec<f64>,
+}
+
+/// Where a map is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapPlacement {
+    /// Behind the grid, showing through the gutters and blended into the tiles with the
+    /// given opacity, from 0 to 1
+    Backdrop(f64),
+    /// Beside the grid, with the given width in pixels
+    Inset(u32),
+}
+
+impl fmt::Display for MapPlacement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MapPlacement::Backdrop(opacity) => write!(f, "backdrop:{}", opacity),
+            MapPlacement::Inset(width) => write!(f, "inset:{}", width),
+        }
+    }
+}
+
+/// A map of the region under the grid, stretched over the whole grid from corner to
+/// corner. It should be rendered at `Sheet::map_size`, but any size will do.
+#[derive(Clone)]
+pub struct Map {
+    pub image: RgbImage,
+    pub placement: MapPlacement,
+}
+
+impl fmt::Debug for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "Map {{ image: {:?}, placement: {:?} }}",
+               self.image.dimensions(),
+               self.placement)
+    }
+}
+
+/// The layout of a contact sheet.
+#[derive(Clone, Debug)]
+pub struct Sheet {
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// The space between neighboring tiles, in pixels
+    pub gutter: u32,
+    pub axes: Option<Axes>,
+    /// Whether to leave room for a caption below each tile
+    pub captions: bool,
+    /// The iteration limit, if a legend should show the palette
+    pub legend: Option<usize>,
+    pub map: Option<Map>,
+    /// The size of each font pixel, in pixels
+    pub scale: u32,
+    pub background: Rgb<u8>,
+    pub foreground: Rgb<u8>,
+}
+
+impl Sheet {
+    const AXIS_TITLES: (&'static str, &'static str) = ("Re(c)", "Im(c)");
+    const LEGEND_TITLE: &'static str = "escape count";
+    /// The widest the legend bar gets, in unscaled pixels
+    const LEGEND_WIDTH: u32 = 256;
+    /// The longest side of a backdrop map worth rendering; bigger grids stretch it
+    const BACKDROP_EDGE: u32 = 1024;
+
+    /// A bare grid of tiles, with no gutters or decorations.
+    pub fn new(columns: u32, rows: u32, tile_width: u32, tile_height: u32) -> Sheet {
+        Sheet {
+            columns,
+            rows,
+            tile_width,
+            tile_height,
+            gutter: 0,
+            axes: None,
+            captions: false,
+            legend: None,
+            map: None,
+            scale: 1,
+            background: Rgb([48, 48, 48]),
+            foreground: Rgb([224, 224, 224]),
+        }
+    }
+
+    fn padding(&self) -> u32 {
+        4 * self.scale
+    }
+
+    fn tick(&self) -> u32 {
+        3 * self.scale
+    }
+
+    fn caption_height(&self) -> u32 {
+        if self.captions {
+            text_height(self.scale) + self.padding()
+        } else {
+            0
+        }
+    }
+
+    /// The dimensions of the grid of tiles, without the margins around it.
+    pub fn grid_dimensions(&self) -> (u32, u32) {
+        let cell_height = self.tile_height + self.caption_height();
+        (self.columns * self.tile_width + self.columns.saturating_sub(1) * self.gutter,
+         self.rows * cell_height + self.rows.saturating_sub(1) * self.gutter)
+    }
+
+    fn legend_width(&self) -> u32 {
+        self.grid_dimensions().0.min(Sheet::LEGEND_WIDTH * self.scale)
+    }
+
+    /// The size at which to render a map to be drawn at `placement`.
+    pub fn map_size(&self, placement: MapPlacement) -> (u32, u32) {
+        let (grid_width, grid_height) = self.grid_dimensions();
+        let (width, height) = match placement {
+            MapPlacement::Backdrop(_) => {
+                let shrink = (Sheet::BACKDROP_EDGE as f64 / grid_width.max(grid_height) as f64)
+                    .min(1.0);
+                ((grid_width as f64 * shrink) as u32, (grid_height as f64 * shrink) as u32)
+            }
+            // no wider than the grid, so no taller either
+            MapPlacement::Inset(width) => {
+                let width = width.min(grid_width);
+                (width, width * grid_height / grid_width)
+            }
+        };
+        (width.max(2), height.max(2))
+    }
+
+    fn inset_size(&self) -> Option<(u32, u32)> {
+        match self.map {
+            Some(Map { placement: placement @ MapPlacement::Inset(_), .. }) => {
+                Some(self.map_size(placement))
+            }
+            _ => None,
+        }
+    }
+
+    fn backdrop_opacity(&self) -> Option<f64> {
+        match self.map {
+            Some(Map { placement: MapPlacement::Backdrop(opacity), .. }) => Some(opacity),
+            _ => None,
+        }
+    }
+
+    /// The margins around the grid: left, top, right and bottom.
+    fn margins(&self) -> (u32, u32, u32, u32) {
+        let (padding, line) = (self.padding(), text_height(self.scale));
+        let mut margins = (0, 0, 0, 0);
+        if let Some(ref axes) = self.axes {
+            let widest = |values: &[f64]| {
+                values.iter().map(|&v| text_width(&format_value(v), self.scale)).max().unwrap_or(0)
+            };
+            margins.0 = padding + widest(&axes.imag) + padding / 2 + self.tick();
+            margins.1 = padding + line + padding;
+            margins.2 = padding + widest(&axes.real) / 2;
+            margins.3 = self.tick() + padding / 2 + line + padding / 2 + line;
+        }
+        if self.legend.is_some() {
+            margins.0 = margins.0.max(padding);
+            margins.1 = margins.1.max(padding);
+            margins.2 = margins.2.max(padding);
+            margins.3 += padding + 2 * line + padding / 2 + line;
+        }
+        if let Some((inset_width, _)) = self.inset_size() {
+            margins.0 = margins.0.max(padding);
+            margins.1 = margins.1.max(padding);
+            margins.2 = margins.2.max(padding) + inset_width + padding;
+        }
+        if self.axes.is_some() || self.legend.is_some() || self.inset_size().is_some() {
+            margins.3 += padding;
+        }
+        margins
+    }
+
+    /// The position of the top left corner of the inset map, if there is one.
+    fn inset_origin(&self) -> Option<(u32, u32)> {
+        let (inset_width, _) = self.inset_size()?;
+        let (left, top, right, _) = self.margins();
+        Some((left + self.grid_dimensions().0 + right - self.padding() - inset_width, top))
+    }
+
+    /// The dimensions of the whole sheet.
+    pub fn dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.grid_dimensions();
+        let (left, top, right, bottom) = self.margins();
+        (left + width + right, top + height + bottom)
+    }
+
+    /// The position of the top left corner of the tile in `column` and `row`, counting
+    /// rows from the top.
+    pub fn tile_origin(&self, column: u32, row: u32) -> (u32, u32) {
+        let (left, top, _, _) = self.margins();
+        (left + column * (self.tile_width + self.gutter),
+         top + row * (self.tile_height + self.caption_height() + self.gutter))
+    }
+
+    /// The rows of the sheet which hold the tiles of `row`, as the first row and the
+    /// number of rows. The first band includes the top margin, the last band the bottom
+    /// margin, and every other band the gutter below it; together they cover the sheet.
+    pub fn band(&self, row: u32) -> (u32, u32) {
+        let start = if row == 0 { 0 } else { self.tile_origin(0, row).1 };
+        let end = if row + 1 >= self.rows {
+            self.dimensions().1
+        } else {
+            self.tile_origin(0, row + 1).1
+        };
+        (start, end - start)
+    }
+
+    /// An empty sheet, with its axes and legend drawn. The legend shows `palette`, which
+    /// maps the gray level of a render to its color.
+    pub fn canvas(&self, palette: &dyn Fn(u8) -> Rgb<u8>) -> RgbImage {
+        self.window(palette, 0, self.dimensions().1)
+    }
+
+    /// The `height` rows of an empty sheet starting from row `first`, as for `canvas`.
+    pub fn window(&self, palette: &dyn Fn(u8) -> Rgb<u8>, first: u32, height: u32) -> RgbImage {
+        let width = self.dimensions().0;
+        let mut canvas = ImageBuffer::from_pixel(width, height, self.background);
+        let shift = |y: u32| y as i64 - first as i64;
+        let (left, top, _, _) = self.margins();
+        let (grid_width, grid_height) = self.grid_dimensions();
+        let (scale, padding, line) = (self.scale, self.padding(), text_height(self.scale));
+        let mut bottom = top + grid_height;
+
+        if let (Some(ref map), Some(_)) = (&self.map, self.backdrop_opacity()) {
+            draw_map(&mut canvas, first, &map.image, (left, top), (grid_width, grid_height));
+        }
+        if let (Some(ref map), Some((x, y)), Some((width, height))) =
+            (&self.map, self.inset_origin(), self.inset_size()) {
+            let color = self.foreground;
+            draw_map(&mut canvas, first, &map.image, (x, y), (width, height));
+            fill(&mut canvas, x - 1, shift(y - 1), width + 2, 1, color);
+            fill(&mut canvas, x - 1, shift(y + height), width + 2, 1, color);
+            fill(&mut canvas, x - 1, shift(y - 1), 1, height + 2, color);
+            fill(&mut canvas, x + width, shift(y - 1), 1, height + 2, color);
+            // mark where the center of each tile falls on the map
+            for row in 0..self.rows {
+                for column in 0..self.columns {
+                    let (tile_x, tile_y) = self.tile_origin(column, row);
+                    let center_x = (tile_x - left) as f64 + self.tile_width as f64 / 2.0;
+                    let center_y = (tile_y - top) as f64 + self.tile_height as f64 / 2.0;
+                    mark(&mut canvas,
+                         x as i64 + (center_x * width as f64 / grid_width as f64) as i64,
+                         shift(y) + (center_y * height as f64 / grid_height as f64) as i64,
+                         self.scale);
+                }
+            }
+        }
+
+        if let Some(ref axes) = self.axes {
+            let color = self.foreground;
+            // the frame, just outside the grid
+            fill(&mut canvas, left - 1, shift(top - 1), grid_width + 2, 1, color);
+            fill(&mut canvas, left - 1, shift(bottom), grid_width + 2, 1, color);
+            fill(&mut canvas, left - 1, shift(top - 1), 1, grid_height + 2, color);
+            fill(&mut canvas, left + grid_width, shift(top - 1), 1, grid_height + 2, color);
+
+            // ticks below each column, skipping labels which would overlap
+            let label_y = bottom + 1 + self.tick() + padding / 2;
+            let mut free = 0i64;
+            for (column, &value) in axes.real.iter().enumerate().take(self.columns as usize) {
+                let (x, _) = self.tile_origin(column as u32, 0);
+                let center = x + self.tile_width / 2;
+                fill(&mut canvas, center, shift(bottom + 1), 1, self.tick(), color);
+                let label = format_value(value);
+                let label_x = center as i64 - text_width(&label, scale) as i64 / 2;
+                if label_x >= free {
+                    draw_text(&mut canvas, label_x, shift(label_y), &label, scale, color);
+                    free = label_x + (text_width(&label, scale) + ADVANCE * scale) as i64;
+                }
+            }
+            let title = Sheet::AXIS_TITLES.0;
+            draw_text(&mut canvas,
+                      (left + grid_width / 2) as i64 - text_width(title, scale) as i64 / 2,
+                      shift(label_y + line + padding / 2),
+                      title,
+                      scale,
+                      color);
+
+            // ticks beside each row
+            let mut free = 0i64;
+            for (row, &value) in axes.imag.iter().enumerate().take(self.rows as usize) {
+                let (_, y) = self.tile_origin(0, row as u32);
+                let center = y + self.tile_height / 2;
+                fill(&mut canvas, left - 1 - self.tick(), shift(center), self.tick(), 1, color);
+                let label = format_value(value);
+                let label_y = center as i64 - line as i64 / 2;
+                if label_y >= free {
+                    let label_x = (left - 1 - self.tick() - padding / 2) as i64 -
+                                  text_width(&label, scale) as i64;
+                    draw_text(&mut canvas, label_x, label_y - first as i64, &label, scale, color);
+                    free = label_y + (line + 2 * scale) as i64;
+                }
+            }
+            draw_text(&mut canvas,
+                      padding as i64,
+                      shift(padding),
+                      Sheet::AXIS_TITLES.1,
+                      scale,
+                      color);
+
+            bottom = label_y + line + padding / 2 + line;
+        }
+
+        if let Some(iterations) = self.legend {
+            let bar_y = bottom + padding;
+            let bar_width = self.legend_width();
+            for x in 0..bar_width {
+                let level = (x * 255 / (bar_width - 1).max(1)) as u8;
+                fill(&mut canvas, left + x, shift(bar_y), 1, 2 * line, palette(level));
+            }
+            let label_y = shift(bar_y + 2 * line + padding / 2);
+            let color = self.foreground;
+            draw_text(&mut canvas, left as i64, label_y, "0", scale, color);
+            let high = iterations.to_string();
+            draw_text(&mut canvas,
+                      (left + bar_width) as i64 - text_width(&high, scale) as i64,
+                      label_y,
+                      &high,
+                      scale,
+                      color);
+            let title = Sheet::LEGEND_TITLE;
+            let title_x = (left + bar_width / 2) as i64 - text_width(title, scale) as i64 / 2;
+            // narrow legends have no room for the title between the labels
+            if title_x > (left + text_width("0", scale) + ADVANCE * scale) as i64 {
+                draw_text(&mut canvas, title_x, label_y, title, scale, color);
+            }
+        }
+
+        canvas
+    }
+
+    /// Copy `tile` into its place on `canvas`, which holds the rows of the sheet from
+    /// `first` on, with a caption below it if the sheet has room for one. Captions too wide
+    /// for the tile are cut short.
+    pub fn place(&self,
+                 canvas: &mut RgbImage,
+                 first: u32,
+                 column: u32,
+                 row: u32,
+                 tile: &RgbImage,
+                 caption: Option<&str>) {
+        let (x, y) = self.tile_origin(column, row);
+        let y = y as i64 - first as i64;
+        let (width, height) = canvas.dimensions();
+        let opacity = self.backdrop_opacity();
+        for (tx, ty, pixel) in tile.enumerate_pixels() {
+            let (px, py) = (x + tx, y + ty as i64);
+            if tx < self.tile_width && ty < self.tile_height && px < width && py >= 0 &&
+               py < height as i64 {
+                let pixel = match opacity {
+                    // let the backdrop show through
+                    Some(opacity) => blend(*pixel, *canvas.get_pixel(px, py as u32), opacity),
+                    None => *pixel,
+                };
+                canvas.put_pixel(px, py as u32, pixel);
+            }
+        }
+        if opacity.is_some() {
+            mark(canvas,
+                 (x + self.tile_width / 2) as i64,
+                 y + (self.tile_height / 2) as i64,
+                 self.scale);
+        }
+
+        if let (true, Some(caption)) = (self.captions, caption) {
+            let fits = ((self.tile_width + self.scale) / (ADVANCE * self.scale)) as usize;
+            let caption = caption.chars().take(fits).collect::<String>();
+            let caption_x = (x + self.tile_width / 2) as i64 -
+                            text_width(&caption, self.scale) as i64 / 2;
+            draw_text(canvas,
+                      caption_x,
+                      y + (self.tile_height + self.padding() / 2) as i64,
+                      &caption,
+                      self.scale,
+                      self.foreground);
+        }
+    }
+}
+
+/// Format a value for a label: to four decimal places, without trailing zeros.
+pub fn format_value(value: f64) -> String {
+    let text = format!("{:.4}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    if text == "-0" {
+        String::from("0")
+    } else {
+        String::from(text)
+    }
+}
+
+/// Format a complex number for a label, as in `-0.5+1i`.
+pub fn format_complex(z: Complex64) -> String {
+    let sign = if format_value(z.im).starts_with('-') { "-" } else { "+" };
+    format!("{}{}{}i", format_value(z.re), sign, format_value(z.im.abs()))
+}
+
+/// Mix `over` with `under`, taking `opacity` of `under`.
+fn blend(over: Rgb<u8>, under: Rgb<u8>, opacity: f64) -> Rgb<u8> {
+    let mix = |a: u8, b: u8| (a as f64 * (1.0 - opacity) + b as f64 * opacity).round() as u8;
+    Rgb([mix(over[0], under[0]), mix(over[1], under[1]), mix(over[2], under[2])])
+}
+
+/// Stretch `map` over the rectangle at `origin` of the given `size` on `canvas`, which
+/// holds the rows of the sheet from `first` on, interpolating bilinearly.
+fn draw_map(canvas: &mut RgbImage,
+            first: u32,
+            map: &RgbImage,
+            origin: (u32, u32),
+            size: (u32, u32)) {
+    let (width, height) = canvas.dimensions();
+    let (map_width, map_height) = map.dimensions();
+    let scale = |to: u32, from: u32| (from.max(2) - 1) as f64 / (to.max(2) - 1) as f64;
+    let (scale_x, scale_y) = (scale(size.0, map_width), scale(size.1, map_height));
+
+    let rows = origin.1.max(first)..(origin.1 + size.1).min(first + height);
+    for y in rows {
+        let v = (y - origin.1) as f64 * scale_y;
+        let (v0, dv) = (v.floor() as u32, v.fract());
+        let v1 = (v0 + 1).min(map_height - 1);
+        for x in origin.0..(origin.0 + size.0).min(width) {
+            let u = (x - origin.0) as f64 * scale_x;
+            let (u0, du) = (u.floor() as u32, u.fract());
+            let u1 = (u0 + 1).min(map_width - 1);
+            let top = blend(*map.get_pixel(u0, v0), *map.get_pixel(u1, v0), du);
+            let bottom = blend(*map.get_pixel(u0, v1), *map.get_pixel(u1, v1), du);
+            canvas.put_pixel(x, y - first, blend(top, bottom, dv));
+        }
+    }
+}
+
+/// Mark a point with a small cross, scaled like the text. The cross inverts whatever's
+/// under it, so that it shows up on both the inside and the outside of a set.
+fn mark(canvas: &mut RgbImage, x: i64, y: i64, scale: u32) {
+    let arm = 2 * scale as i64;
+    let (width, height) = canvas.dimensions();
+    let mut points = (-arm..(arm + 1)).map(|offset| (x + offset, y)).collect::<Vec<_>>();
+    points.extend((-arm..(arm + 1)).filter(|&offset| offset != 0).map(|offset| (x, y + offset)));
+    for (px, py) in points {
+        if px >= 0 && py >= 0 && px < width as i64 && py < height as i64 {
+            let under = *canvas.get_pixel(px as u32, py as u32);
+            canvas.put_pixel(px as u32,
+                             py as u32,
+                             Rgb([255 - under[0], 255 - under[1], 255 - under[2]]));
+        }
+    }
+}
+
+fn fill(image: &mut RgbImage, x: u32, y: i64, width: u32, height: u32, color: Rgb<u8>) {
+    let (image_width, image_height) = image.dimensions();
+    let rows = y.max(0)..(y + height as i64).min(image_height as i64);
+    for py in rows {
+        for px in x..(x + width).min(image_width) {
+            image.put_pixel(px, py as u32, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(level: u8) -> Rgb<u8> {
+        Rgb([level, level, level])
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_value(-0.30000000000000004), "-0.3");
+        assert_eq!(format_value(2.0), "2");
+        assert_eq!(format_value(-0.00001), "0");
+        assert_eq!(format_complex(Complex64::new(-0.5, 1.0)), "-0.5+1i");
+        assert_eq!(format_complex(Complex64::new(0.25, -0.125)), "0.25-0.125i");
+    }
+
+    #[test]
+    fn test_bare_layout() {
+        // without decorations, a sheet is just the tiles side by side
+        let mut sheet = Sheet::new(3, 2, 10, 8);
+        assert_eq!(sheet.dimensions(), (30, 16));
+        assert_eq!(sheet.tile_origin(2, 1), (20, 8));
+
+        sheet.gutter = 2;
+        assert_eq!(sheet.dimensions(), (34, 18));
+        assert_eq!(sheet.tile_origin(2, 1), (24, 10));
+    }
+
+    #[test]
+    fn test_decorated_layout() {
+        let mut sheet = Sheet::new(3, 2, 40, 30);
+        sheet.axes = Some(Axes {
+            real: vec![-1.0, -0.5, 0.0],
+            imag: vec![0.5, 0.0],
+        });
+        sheet.captions = true;
+        sheet.legend = Some(100);
+        let (width, height) = sheet.dimensions();
+        let (x, y) = sheet.tile_origin(0, 0);
+        assert!(x > 0 && y > 0);
+        let (last_x, last_y) = sheet.tile_origin(2, 1);
+        assert!(last_x + 40 < width && last_y + 30 + sheet.caption_height() < height);
+
+        let mut canvas = sheet.canvas(&gray);
+        assert_eq!(canvas.dimensions(), (width, height));
+        // the frame surrounds the grid
+        assert_eq!(*canvas.get_pixel(x - 1, y - 1), sheet.foreground);
+        assert_eq!(*canvas.get_pixel(x, y), sheet.background);
+
+        let tile = ImageBuffer::from_pixel(40, 30, gray(0));
+        sheet.place(&mut canvas, 0, 2, 1, &tile, Some("-0.5+1i"));
+        assert_eq!(*canvas.get_pixel(last_x, last_y), gray(0));
+        let caption_row = (last_x..last_x + 40)
+            .map(|px| *canvas.get_pixel(px, last_y + 30 + sheet.padding() / 2 + 3))
+            .collect::<Vec<_>>();
+        assert!(caption_row.contains(&sheet.foreground));
+
+        // drawn a band at a time, the sheet comes out the same
+        let mut banded = Vec::new();
+        for row in 0..2 {
+            let (first, height) = sheet.band(row);
+            let mut window = sheet.window(&gray, first, height);
+            for column in 0..3 {
+                sheet.place(&mut window, first, column, row, &tile, Some("-0.5+1i"));
+            }
+            banded.extend(window.into_raw());
+        }
+        let mut whole = sheet.canvas(&gray);
+        for row in 0..2 {
+            for column in 0..3 {
+                sheet.place(&mut whole, 0, column, row, &tile, Some("-0.5+1i"));
+            }
+        }
+        assert_eq!(banded, whole.into_raw());
+    }
+
+    #[test]
+    fn test_maps() {
+        let mut sheet = Sheet::new(3, 2, 40, 30);
+        sheet.gutter = 4;
+        let bare = sheet.dimensions();
+        let tile = ImageBuffer::from_pixel(40, 30, gray(0));
+
+        // a backdrop shows through the gutters, and is blended into the tiles
+        let placement = MapPlacement::Backdrop(0.5);
+        let (width, height) = sheet.map_size(placement);
+        assert_eq!((width, height), sheet.grid_dimensions());
+        sheet.map = Some(Map {
+            image: ImageBuffer::from_pixel(width, height, gray(200)),
+            placement,
+        });
+        assert_eq!(sheet.dimensions(), bare);
+        let mut canvas = sheet.canvas(&gray);
+        sheet.place(&mut canvas, 0, 0, 0, &tile, None);
+        assert_eq!(*canvas.get_pixel(41, 0), gray(200));
+        assert_eq!(*canvas.get_pixel(0, 0), gray(100));
+        assert_eq!(*canvas.get_pixel(20, 15), gray(155));
+
+        // an inset sits beside the grid, with a mark for each tile
+        let placement = MapPlacement::Inset(64);
+        let (width, height) = sheet.map_size(placement);
+        assert_eq!((width, height), (64, 64 * 64 / 128));
+        sheet.map = Some(Map {
+            image: ImageBuffer::from_pixel(width, height, gray(200)),
+            placement,
+        });
+        let (x, y) = sheet.inset_origin().unwrap();
+        assert!(x > bare.0 && sheet.dimensions().0 > x + width);
+        let canvas = sheet.canvas(&gray);
+        assert_eq!(*canvas.get_pixel(x, y), gray(200));
+        assert_eq!(*canvas.get_pixel(x - 1, y), sheet.foreground);
+        let mark_x = x + (20.0 * 64.0 / 128.0) as u32;
+        let mark_y = y + (15.0 * 32.0 / 64.0) as u32;
+        assert_eq!(*canvas.get_pixel(mark_x, mark_y), gray(55));
+        assert_eq!(*canvas.get_pixel(mark_x + 3, mark_y + 3), gray(200));
+    }
+}
@@ -0,0 +1,37 @@
+//! Per-pixel iteration state.
+//!
+//! Most families carry nothing from one step to the next but the current value `z`. Some
+//! carry more: the Phoenix family needs the previous value as well, and a parameter-plane
+//! render of any family could carry `c` along with `z`. The iteration engine is generic
+//! over the state, so such families reuse the same escape test, iteration limit and
+//! threading as everything else.
+
+use num::complex::Complex64;
+
+/// The state of a single orbit.
+pub trait State: Copy + Send {
+    /// The value the escape criterion is applied to.
+    fn value(&self) -> Complex64;
+}
+
+impl State for Complex64 {
+    #[inline]
+    fn value(&self) -> Complex64 {
+        *self
+    }
+}
+
+/// A value together with the parameter of its orbit, for renders where `c` varies from
+/// pixel to pixel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WithParameter {
+    pub z: Complex64,
+    pub c: Complex64,
+}
+
+impl State for WithParameter {
+    #[inline]
+    fn value(&self) -> Complex64 {
+        self.z
+    }
+}
@@ -0,0 +1,235 @@
+//! Statistics about escape-count renders.
+//!
+//! Comparing parameters by eye only goes so far. A `RenderStats` summarizes a render
+//! numerically: how much of it lies in the filled Julia set, how the escape counts are
+//! distributed, how much iteration it took, and how long each row took to compute, which
+//! shows where the time goes.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use viewport::Viewport;
+
+/// Statistics about a single escape-count render.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderStats {
+    pub width: u32,
+    pub height: u32,
+    /// The iteration limit
+    pub iterations: usize,
+    /// The number of pixels with each escape count which occurs, from 0 to `iterations`;
+    /// pixels which never escaped are counted under `iterations`
+    pub histogram: BTreeMap<usize, u64>,
+    /// The total number of iterations computed, over every pixel
+    pub work: u64,
+    /// How long each row took to compute, in row order; empty if the render wasn't timed
+    pub row_times: Vec<Duration>,
+    /// How long the whole render took to compute
+    pub elapsed: Duration,
+}
+
+impl RenderStats {
+    /// Gather statistics from the escape count of every pixel, where `work` iterations
+    /// were computed in all. The render isn't timed.
+    pub fn new(width: u32,
+               height: u32,
+               iterations: usize,
+               counts: &[usize],
+               work: u64)
+               -> RenderStats {
+        // the iteration limit may be far larger than the number of pixels, so only the
+        // counts which occur are stored
+        let mut histogram = BTreeMap::new();
+        for &count in counts {
+            *histogram.entry(count.min(iterations)).or_insert(0) += 1;
+        }
+        RenderStats {
+            width,
+            height,
+            iterations,
+            histogram,
+            work,
+            row_times: Vec::new(),
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    /// Gather statistics from escape counts alone, where each pixel took as many
+    /// iterations as its count.
+    pub fn from_counts(width: u32, height: u32, iterations: usize, counts: &[usize]) -> RenderStats {
+        let work = counts.iter().map(|&count| count.min(iterations) as u64).sum();
+        RenderStats::new(width, height, iterations, counts, work)
+    }
+
+    pub fn pixels(&self) -> u64 {
+        self.histogram.values().sum()
+    }
+
+    /// The number of pixels which never escaped, and so lie in the filled Julia set.
+    pub fn filled(&self) -> u64 {
+        self.histogram.get(&self.iterations).cloned().unwrap_or(0)
+    }
+
+    pub fn filled_fraction(&self) -> f64 {
+        self.filled() as f64 / self.pixels().max(1) as f64
+    }
+
+    /// The area of the filled set, if the render showed `viewport`.
+    pub fn area(&self, viewport: &Viewport) -> f64 {
+        self.filled_fraction() * (viewport.max_x - viewport.min_x) *
+        (viewport.max_y - viewport.min_y)
+    }
+
+    /// The least escape count.
+    pub fn min(&self) -> usize {
+        self.histogram.keys().next().cloned().unwrap_or(0)
+    }
+
+    /// The greatest escape count.
+    pub fn max(&self) -> usize {
+        self.histogram.keys().next_back().cloned().unwrap_or(0)
+    }
+
+    /// The mean escape count.
+    pub fn mean(&self) -> f64 {
+        let total = self.histogram
+            .iter()
+            .map(|(&count, &n)| count as f64 * n as f64)
+            .sum::<f64>();
+        total / self.pixels().max(1) as f64
+    }
+
+    /// The mean time per row, if the render was timed.
+    pub fn mean_row_time(&self) -> Option<Duration> {
+        if self.row_times.is_empty() {
+            return None;
+        }
+        Some(self.row_times.iter().sum::<Duration>() / self.row_times.len() as u32)
+    }
+
+    /// The slowest row and how long it took, if the render was timed.
+    pub fn slowest_row(&self) -> Option<(u32, Duration)> {
+        self.row_times
+            .iter()
+            .enumerate()
+            .max_by_key(|&(_, &time)| time)
+            .map(|(y, &time)| (y as u32, time))
+    }
+
+    /// Merge the histogram into `buckets` ranges of escape counts of equal width, as
+    /// `(first, last, pixels)`. The last bucket holds only the filled pixels.
+    pub fn buckets(&self, buckets: usize) -> Vec<(usize, usize, u64)> {
+        let buckets = buckets.clamp(2, self.iterations.saturating_add(1));
+        let width = self.iterations.div_ceil(buckets - 1).max(1);
+        let mut merged = (0..self.iterations)
+            .step_by(width)
+            .map(|first| {
+                let last = first.saturating_add(width).min(self.iterations) - 1;
+                (first, last, self.histogram.range(first..last + 1).map(|(_, &n)| n).sum())
+            })
+            .collect::<Vec<_>>();
+        merged.push((self.iterations, self.iterations, self.filled()));
+        merged
+    }
+
+    /// A one-line summary, for reporting many renders.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{:.2}% filled, escape counts {}/{:.1}/{}, {} iterations",
+                                  100.0 * self.filled_fraction(),
+                                  self.min(),
+                                  self.mean(),
+                                  self.max(),
+                                  self.work);
+        if !self.row_times.is_empty() {
+            summary.push_str(&format!(" in {:.3}s", self.elapsed.as_secs_f64()));
+        }
+        summary
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const BAR: f64 = 40.0;
+
+        writeln!(f, "Render statistics:")?;
+        writeln!(f, "  pixels:      {} ({}x{})", self.pixels(), self.width, self.height)?;
+        writeln!(f,
+                 "  filled:      {} ({:.2}%)",
+                 self.filled(),
+                 100.0 * self.filled_fraction())?;
+        writeln!(f,
+                 "  escape:      min {}, mean {:.2}, max {} of {}",
+                 self.min(),
+                 self.mean(),
+                 self.max(),
+                 self.iterations)?;
+        writeln!(f, "  computed:    {} iterations", self.work)?;
+        if let (Some(mean), Some((slowest, time))) = (self.mean_row_time(), self.slowest_row()) {
+            writeln!(f,
+                     "  time:        {:.3}s; {:.3}ms per row, slowest {:.3}ms (row {})",
+                     self.elapsed.as_secs_f64(),
+                     mean.as_secs_f64() * 1e3,
+                     time.as_secs_f64() * 1e3,
+                     slowest)?;
+        }
+        writeln!(f, "  histogram:")?;
+        let buckets = self.buckets(10);
+        let most = buckets.iter().map(|&(_, _, n)| n).max().unwrap_or(0).max(1) as f64;
+        for (first, last, n) in buckets {
+            let range = if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            };
+            writeln!(f,
+                     "    {:>11} {:>9} {}",
+                     range,
+                     n,
+                     "#".repeat((n as f64 / most * BAR).round() as usize))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        let stats = RenderStats::from_counts(3, 2, 10, &[0, 2, 2, 10, 10, 6]);
+        assert_eq!(stats.pixels(), 6);
+        assert_eq!(stats.filled(), 2);
+        assert_eq!(stats.work, 30);
+        assert_eq!((stats.min(), stats.max()), (0, 10));
+        assert!((stats.mean() - 5.0).abs() < 1e-12);
+        assert!((stats.area(&Viewport::new(-1.0, 1.0, -1.0, 2.0)) - 2.0).abs() < 1e-12);
+        assert_eq!(stats.mean_row_time(), None);
+        assert!(!stats.summary().contains(" in "));
+    }
+
+    #[test]
+    fn test_buckets() {
+        let stats = RenderStats::from_counts(3, 2, 10, &[0, 2, 2, 10, 10, 6]);
+        assert_eq!(stats.buckets(3),
+                   vec![(0, 4, 3), (5, 9, 1), (10, 10, 2)]);
+        let buckets = stats.buckets(100);
+        assert_eq!(buckets.len(), 11);
+        assert_eq!(buckets.iter().map(|&(_, _, n)| n).sum::<u64>(), 6);
+    }
+
+    #[test]
+    fn test_huge_iteration_limit() {
+        // only the counts which occur take up space, however high the limit
+        let iterations = 3_000_000_000;
+        let stats = RenderStats::from_counts(2, 2, iterations, &[0, 0, 7, iterations]);
+        assert_eq!(stats.histogram.len(), 3);
+        assert_eq!(stats.filled(), 1);
+        assert_eq!((stats.min(), stats.max()), (0, iterations));
+        let buckets = stats.buckets(10);
+        assert_eq!(buckets.len(), 10);
+        assert_eq!(buckets[0], (0, iterations / 9, 3));
+        assert!(stats.to_string().contains("filled:      1 (25.00%)"));
+    }
+}
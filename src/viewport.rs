@@ -0,0 +1,145 @@
+//! The region of the complex plane which is mapped onto an output image.
+//!
+//! Rendering functions don't care about viewports directly; they take an interpolation
+//! closure mapping pixel coordinates to complex values. A `Viewport` is the plain-data
+//! description of such a mapping, which makes it possible to record it alongside a
+//! render and reconstruct the exact same interpolation later.
+
+use num::complex::Complex64;
+
+/// An inclusive rectangular region of the complex plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub min_x: f64,
+    pub max_x: f64,
+    pub min_y: f64,
+    pub max_y: f64,
+}
+
+impl Viewport {
+    pub fn new(min_x: f64, max_x: f64, min_y: f64, max_y: f64) -> Viewport {
+        Viewport {
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+        }
+    }
+
+    /// Construct a square viewport around `center`.
+    ///
+    /// At a zoom of 1, the viewport extends 1 unit in each direction from the center.
+    pub fn from_center(center: Complex64, zoom: f64) -> Viewport {
+        let arm = 1.0 / zoom;
+        Viewport::new(center.re - arm, center.re + arm, center.im - arm, center.im + arm)
+    }
+
+    /// Return a copy of this viewport whose aspect ratio matches that of an image of the
+    /// given size.
+    ///
+    /// If the aspect ratios do not match, the range of x or y is **increased** as
+    /// appropriate to make the aspect ratio match.
+    pub fn rectilinear(&self, width: u32, height: u32) -> Viewport {
+        let mut vp = *self;
+        let viewport_ratio = width as f64 / height as f64;
+        let range_ratio = (vp.max_x - vp.min_x) / (vp.max_y - vp.min_y);
+
+        {
+            let match_result = if viewport_ratio > range_ratio {
+                // Viewport is wider than the given domain.
+                // Need to increase the range of x.
+                Some((&mut vp.min_x, &mut vp.max_x))
+            } else if viewport_ratio < range_ratio {
+                // Viewport is narrower than the given domain
+                // Need to increase the range of y.
+                Some((&mut vp.min_y, &mut vp.max_y))
+            } else {
+                None
+            };
+
+            if let Some((min, max)) = match_result {
+                // adjust min and max here
+                let center = (*min + *max) / 2.0;
+                let arm = (*max - center) * viewport_ratio / range_ratio;
+                *min = center - arm;
+                *max = center + arm;
+            }
+        }
+
+        vp
+    }
+
+    /// Gets an appropriate complex value from a pixel coordinate
+    /// in a given output size.
+    ///
+    /// x, y: pixel coordinates
+    /// width, height: size in pixels of the image
+    pub fn pixel(&self, x: u32, y: u32, width: u32, height: u32) -> Complex64 {
+        Complex64::new(self.min_x + ((x as f64 / (width - 1) as f64) * (self.max_x - self.min_x)),
+                       self.min_y + ((y as f64 / (height - 1) as f64) * (self.max_y - self.min_y)))
+    }
+
+    /// The fractional pixel coordinates of `z` in a given output size, which may lie
+    /// outside the image.
+    ///
+    /// This is the inverse of `pixel`.
+    pub fn position(&self, z: Complex64, width: u32, height: u32) -> (f64, f64) {
+        ((z.re - self.min_x) / (self.max_x - self.min_x) * (width - 1) as f64,
+         (z.im - self.min_y) / (self.max_y - self.min_y) * (height - 1) as f64)
+    }
+
+    /// The pixel nearest to `z` in a given output size, or `None` if it falls outside.
+    pub fn locate(&self, z: Complex64, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (x, y) = self.position(z, width, height);
+        let (x, y) = (x.round(), y.round());
+        if x >= 0.0 && x < width as f64 && y >= 0.0 && y < height as f64 {
+            Some((x as u32, y as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Construct a closure which maps pixel coordinates onto this viewport, stretching
+    /// it to fit the image if the aspect ratios differ.
+    pub fn interpolate(&self,
+                       width: u32,
+                       height: u32)
+                       -> Box<dyn Fn(u32, u32) -> Complex64 + Send + Sync> {
+        let vp = *self;
+        Box::new(move |x, y| vp.pixel(x, y, width, height))
+    }
+}
+
+impl Default for Viewport {
+    /// julia sets are only really interesting in the region [-1...1]
+    fn default() -> Viewport {
+        Viewport::new(-1.0, 1.0, -1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rectilinear_widens() {
+        let vp = Viewport::default().rectilinear(200, 100);
+        assert_eq!(vp, Viewport::new(-2.0, 2.0, -1.0, 1.0));
+    }
+
+    #[test]
+    fn test_from_center() {
+        let vp = Viewport::from_center(Complex64::new(0.5, -0.5), 4.0);
+        assert_eq!(vp, Viewport::new(0.25, 0.75, -0.75, -0.25));
+    }
+
+    #[test]
+    fn test_pixel_corners() {
+        let vp = Viewport::default();
+        assert_eq!(vp.pixel(0, 0, 11, 11), Complex64::new(-1.0, -1.0));
+        assert_eq!(vp.pixel(10, 10, 11, 11), Complex64::new(1.0, 1.0));
+        assert_eq!(vp.locate(vp.pixel(3, 7, 11, 11), 11, 11), Some((3, 7)));
+        assert_eq!(vp.locate(Complex64::new(1.1, 0.0), 11, 11), None);
+        assert_eq!(vp.position(Complex64::new(1.1, 0.0), 11, 11), (10.5, 5.0));
+    }
+}